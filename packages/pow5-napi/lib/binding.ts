@@ -0,0 +1,21 @@
+import { createRequire } from "node:module";
+
+/**
+ * The functions exported by the native addon, built from `src/lib.rs` by
+ * `napi build` into `pow5-napi.node`.
+ */
+export interface Binding {
+  getWorkPar217a(header: Uint8Array): Uint8Array;
+  elementaryIteration217a(header: Uint8Array): Uint8Array;
+  insertNonce217a(header: Uint8Array, nonce: number): Uint8Array;
+  matmulWork64b(header: Uint8Array): Uint8Array;
+  elementaryIteration64b(header: Uint8Array): Uint8Array;
+  insertNonce64b(header: Uint8Array, nonce: number): Uint8Array;
+  setNonce64b(header: Uint8Array, nonce: Uint8Array): Uint8Array;
+  elementaryIteration64bBatch(headers: Uint8Array[]): Promise<Uint8Array[]>;
+  verify64bBatch(headers: Uint8Array[], target: Uint8Array): Promise<boolean[]>;
+}
+
+const require = createRequire(import.meta.url);
+
+export const binding = require("../pow5-napi.node") as Binding;
@@ -0,0 +1,4 @@
+import * as Pow5_217a_Native from "./pow5-217a-native.js";
+import * as Pow5_64b_Native from "./pow5-64b-native.js";
+
+export { Pow5_217a_Native, Pow5_64b_Native };
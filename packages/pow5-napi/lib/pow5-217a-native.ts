@@ -0,0 +1,25 @@
+import { binding } from "./binding.js";
+import { FixedBuf } from "@webbuf/fixedbuf";
+import { WebBuf } from "@webbuf/webbuf";
+
+export function insertNonce(
+  header: FixedBuf<217>,
+  nonce: number,
+): FixedBuf<217> {
+  const res = WebBuf.fromUint8Array(binding.insertNonce217a(header.buf, nonce));
+  return FixedBuf.fromBuf(217, res);
+}
+
+export function getWorkPar(header: FixedBuf<217>): FixedBuf<32> {
+  return FixedBuf.fromBuf(
+    32,
+    WebBuf.fromUint8Array(binding.getWorkPar217a(header.buf)),
+  );
+}
+
+export function elementaryIteration(header: FixedBuf<217>): FixedBuf<32> {
+  return FixedBuf.fromBuf(
+    32,
+    WebBuf.fromUint8Array(binding.elementaryIteration217a(header.buf)),
+  );
+}
@@ -0,0 +1,72 @@
+import { binding } from "./binding.js";
+import { FixedBuf } from "@webbuf/fixedbuf";
+import { WebBuf } from "@webbuf/webbuf";
+
+/**
+ * Compute the matmul work for a 64-byte header.
+ * This is the ASIC-resistant computation (same algorithm as 217a).
+ */
+export function matmulWork(header: FixedBuf<64>): FixedBuf<32> {
+  return FixedBuf.fromBuf(
+    32,
+    WebBuf.fromUint8Array(binding.matmulWork64b(header.buf)),
+  );
+}
+
+/**
+ * Compute the elementary iteration for a 64-byte header.
+ * Returns the final PoW hash (double-hash of the matmul result).
+ */
+export function elementaryIteration(header: FixedBuf<64>): FixedBuf<32> {
+  return FixedBuf.fromBuf(
+    32,
+    WebBuf.fromUint8Array(binding.elementaryIteration64b(header.buf)),
+  );
+}
+
+/**
+ * Insert a 4-byte nonce into bytes 28-31 of the header.
+ * This is used for GPU iteration where the GPU increments the last 4 bytes.
+ */
+export function insertNonce(header: FixedBuf<64>, nonce: number): FixedBuf<64> {
+  const res = WebBuf.fromUint8Array(binding.insertNonce64b(header.buf, nonce));
+  return FixedBuf.fromBuf(64, res);
+}
+
+/**
+ * Set the full 32-byte nonce (bytes 0-31) of the header.
+ */
+export function setNonce(
+  header: FixedBuf<64>,
+  nonce: FixedBuf<32>,
+): FixedBuf<64> {
+  const res = WebBuf.fromUint8Array(binding.setNonce64b(header.buf, nonce.buf));
+  return FixedBuf.fromBuf(64, res);
+}
+
+/**
+ * Compute the elementary iteration for every header, in order, on the libuv
+ * thread pool and across every core.
+ */
+export async function elementaryIterationBatch(
+  headers: FixedBuf<64>[],
+): Promise<FixedBuf<32>[]> {
+  const hashes = await binding.elementaryIteration64bBatch(
+    headers.map((header) => header.buf),
+  );
+  return hashes.map((hash) => FixedBuf.fromBuf(32, WebBuf.fromUint8Array(hash)));
+}
+
+/**
+ * Check whether each header's hash meets the target, in order, on the libuv
+ * thread pool and across every core.
+ */
+export async function verifyBatch(
+  headers: FixedBuf<64>[],
+  target: FixedBuf<32>,
+): Promise<boolean[]> {
+  return binding.verify64bBatch(
+    headers.map((header) => header.buf),
+    target.buf,
+  );
+}
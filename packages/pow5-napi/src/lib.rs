@@ -0,0 +1,168 @@
+// Native Node.js bindings for pow5.
+//
+// The functions mirror the wasm-bindgen exports of `keypears_pow5`, under the
+// same names in camelCase (given explicitly, since napi would capitalize the
+// letter after a digit: `64B`), so `lib/` can wrap them in exactly the API that
+// `@keypears/pow5` puts over the wasm build. Errors are the same strings, thrown
+// as JS errors.
+//
+// The batch functions copy their inputs, hash them on the libuv thread pool,
+// split across every core, and resolve a promise, so a server can verify many
+// solutions without blocking its event loop.
+
+use keypears_pow5::difficulty::Target;
+use keypears_pow5::mine::hash_64b;
+use napi::bindgen_prelude::{AsyncTask, Uint8Array};
+use napi::{Env, Error, Result, Task};
+use napi_derive::napi;
+use std::num::NonZeroUsize;
+use std::thread;
+
+fn to_js(result: std::result::Result<Vec<u8>, String>) -> Result<Uint8Array> {
+    result.map(Uint8Array::new).map_err(Error::from_reason)
+}
+
+fn to_array<const N: usize>(name: &str, bytes: &[u8]) -> Result<[u8; N]> {
+    bytes.try_into().map_err(|_| {
+        Error::from_reason(format!(
+            "{} is not the correct size: expected {}, got {}",
+            name,
+            N,
+            bytes.len()
+        ))
+    })
+}
+
+// =============================================================================
+// pow5-217a: 217-byte input (earthbucks header format)
+// =============================================================================
+
+#[napi(js_name = "getWorkPar217a")]
+pub fn get_work_par_217a(header: Uint8Array) -> Result<Uint8Array> {
+    to_js(keypears_pow5::get_work_par_217a(header.to_vec()))
+}
+
+#[napi(js_name = "elementaryIteration217a")]
+pub fn elementary_iteration_217a(header: Uint8Array) -> Result<Uint8Array> {
+    to_js(keypears_pow5::elementary_iteration_217a(header.to_vec()))
+}
+
+#[napi(js_name = "insertNonce217a")]
+pub fn insert_nonce_217a(header: Uint8Array, nonce: u32) -> Result<Uint8Array> {
+    to_js(keypears_pow5::insert_nonce_217a(header.to_vec(), nonce))
+}
+
+// =============================================================================
+// pow5-64b: 64-byte input (32-byte nonce + 32-byte challenge)
+// =============================================================================
+
+#[napi(js_name = "matmulWork64b")]
+pub fn matmul_work_64b(header: Uint8Array) -> Result<Uint8Array> {
+    to_js(keypears_pow5::matmul_work_64b(header.to_vec()))
+}
+
+#[napi(js_name = "elementaryIteration64b")]
+pub fn elementary_iteration_64b(header: Uint8Array) -> Result<Uint8Array> {
+    to_js(keypears_pow5::elementary_iteration_64b(header.to_vec()))
+}
+
+#[napi(js_name = "insertNonce64b")]
+pub fn insert_nonce_64b(header: Uint8Array, nonce: u32) -> Result<Uint8Array> {
+    to_js(keypears_pow5::insert_nonce_64b(header.to_vec(), nonce))
+}
+
+#[napi(js_name = "setNonce64b")]
+pub fn set_nonce_64b(header: Uint8Array, nonce: Uint8Array) -> Result<Uint8Array> {
+    to_js(keypears_pow5::set_nonce_64b(
+        header.to_vec(),
+        nonce.to_vec(),
+    ))
+}
+
+// =============================================================================
+// batches, on the thread pool
+// =============================================================================
+
+/// Apply `f` to every item, split across the available cores, keeping order.
+fn parallel_map<T: Sync, U: Send>(items: &[T], f: impl Fn(&T) -> U + Sync) -> Vec<U> {
+    let threads = thread::available_parallelism().map_or(1, NonZeroUsize::get);
+    let chunk_size = items.len().div_ceil(threads).max(1);
+    thread::scope(|scope| {
+        let f = &f;
+        let handles: Vec<_> = items
+            .chunks(chunk_size)
+            .map(|chunk| scope.spawn(move || chunk.iter().map(f).collect::<Vec<_>>()))
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().expect("hashing does not panic"))
+            .collect()
+    })
+}
+
+fn headers_64b(headers: &[Uint8Array]) -> Result<Vec<[u8; 64]>> {
+    headers
+        .iter()
+        .map(|header| to_array("header", header))
+        .collect()
+}
+
+pub struct ElementaryIteration64bBatch {
+    headers: Vec<[u8; 64]>,
+}
+
+impl Task for ElementaryIteration64bBatch {
+    type Output = Vec<[u8; 32]>;
+    type JsValue = Vec<Uint8Array>;
+
+    fn compute(&mut self) -> Result<Self::Output> {
+        Ok(parallel_map(&self.headers, hash_64b))
+    }
+
+    fn resolve(&mut self, _env: Env, output: Self::Output) -> Result<Self::JsValue> {
+        Ok(output.into_iter().map(Uint8Array::from).collect())
+    }
+}
+
+/// The pow5-64b hash of every header, in order.
+#[napi(js_name = "elementaryIteration64bBatch")]
+pub fn elementary_iteration_64b_batch(
+    headers: Vec<Uint8Array>,
+) -> Result<AsyncTask<ElementaryIteration64bBatch>> {
+    Ok(AsyncTask::new(ElementaryIteration64bBatch {
+        headers: headers_64b(&headers)?,
+    }))
+}
+
+pub struct Verify64bBatch {
+    headers: Vec<[u8; 64]>,
+    target: Target,
+}
+
+impl Task for Verify64bBatch {
+    type Output = Vec<bool>;
+    type JsValue = Vec<bool>;
+
+    fn compute(&mut self) -> Result<Self::Output> {
+        let target = &self.target;
+        Ok(parallel_map(&self.headers, |header| {
+            target.is_met_by(&hash_64b(header))
+        }))
+    }
+
+    fn resolve(&mut self, _env: Env, output: Self::Output) -> Result<Self::JsValue> {
+        Ok(output)
+    }
+}
+
+/// Whether the pow5-64b hash of each header meets `target`, in order.
+#[napi(js_name = "verify64bBatch")]
+pub fn verify_64b_batch(
+    headers: Vec<Uint8Array>,
+    target: Uint8Array,
+) -> Result<AsyncTask<Verify64bBatch>> {
+    Ok(AsyncTask::new(Verify64bBatch {
+        headers: headers_64b(&headers)?,
+        target: Target::from_bytes(to_array("target", &target)?),
+    }))
+}
@@ -0,0 +1,105 @@
+import { describe, test, expect } from "vitest";
+import * as Pow5_64b_Wasm from "@keypears/pow5/src/pow5-64b-wasm.js";
+import * as Pow5_217a_Wasm from "@keypears/pow5/src/pow5-217a-wasm.js";
+import {
+  hashMeetsTarget,
+  targetFromDifficulty,
+} from "@keypears/pow5/src/difficulty.js";
+import { Pow5_64b_Native, Pow5_217a_Native } from "../lib/index.js";
+import { FixedBuf } from "@webbuf/fixedbuf";
+import { WebBuf } from "@webbuf/webbuf";
+
+// Expected values from Rust tests
+const EXPECTED_MATMUL_WORK_ALL_ZEROES =
+  "b1fee00a999ab4d93dcd2f6ced975c4e8ee110e0a1d48cb094fec3c934d0ee3c";
+const EXPECTED_ELEMENTARY_ITERATION_ALL_ONES =
+  "b5906d01328e86064b2a4783d0fc5f512fb1f2f923b3a869575482c0904fba44";
+const EXPECTED_WORK_PAR_217A_ALL_ZEROES =
+  "6fe9eddc39bb4183c44853c41876801be94a138ea9adea89f40a08442d2f79b8";
+
+const SAMPLES = 64;
+
+function randomHeaders64b(count: number): FixedBuf<64>[] {
+  return Array.from({ length: count }, () => FixedBuf.fromRandom(64));
+}
+
+describe("native and wasm backends agree", () => {
+  test("pow5-64b known vectors", () => {
+    const zeroes = FixedBuf.fromBuf(64, WebBuf.fromHex("00".repeat(64)));
+    const ones = FixedBuf.fromBuf(64, WebBuf.fromHex("11".repeat(64)));
+    expect(Pow5_64b_Native.matmulWork(zeroes).toHex()).toBe(
+      EXPECTED_MATMUL_WORK_ALL_ZEROES,
+    );
+    expect(Pow5_64b_Native.elementaryIteration(ones).toHex()).toBe(
+      EXPECTED_ELEMENTARY_ITERATION_ALL_ONES,
+    );
+  });
+
+  test("pow5-64b random headers", () => {
+    for (const header of randomHeaders64b(SAMPLES)) {
+      expect(Pow5_64b_Native.matmulWork(header).toHex()).toBe(
+        Pow5_64b_Wasm.matmulWork(header).toHex(),
+      );
+      expect(Pow5_64b_Native.elementaryIteration(header).toHex()).toBe(
+        Pow5_64b_Wasm.elementaryIteration(header).toHex(),
+      );
+      const nonce = header.buf[0]! * 0x01010101;
+      expect(Pow5_64b_Native.insertNonce(header, nonce).toHex()).toBe(
+        Pow5_64b_Wasm.insertNonce(header, nonce).toHex(),
+      );
+      const fullNonce = FixedBuf.fromRandom(32);
+      expect(Pow5_64b_Native.setNonce(header, fullNonce).toHex()).toBe(
+        Pow5_64b_Wasm.setNonce(header, fullNonce).toHex(),
+      );
+    }
+  });
+
+  test("pow5-217a random headers", () => {
+    const zeroes = FixedBuf.fromBuf(217, WebBuf.fromHex("00".repeat(217)));
+    expect(Pow5_217a_Native.getWorkPar(zeroes).toHex()).toBe(
+      EXPECTED_WORK_PAR_217A_ALL_ZEROES,
+    );
+    for (let i = 0; i < SAMPLES; i++) {
+      const header = FixedBuf.fromRandom(217);
+      expect(Pow5_217a_Native.getWorkPar(header).toHex()).toBe(
+        Pow5_217a_Wasm.getWorkPar(header).toHex(),
+      );
+      expect(Pow5_217a_Native.elementaryIteration(header).toHex()).toBe(
+        Pow5_217a_Wasm.elementaryIteration(header).toHex(),
+      );
+      expect(Pow5_217a_Native.insertNonce(header, i).toHex()).toBe(
+        Pow5_217a_Wasm.insertNonce(header, i).toHex(),
+      );
+    }
+  });
+
+  test("batches match one at a time", async () => {
+    const headers = randomHeaders64b(SAMPLES);
+    const hashes = await Pow5_64b_Native.elementaryIterationBatch(headers);
+    expect(hashes.map((hash) => hash.toHex())).toEqual(
+      headers.map((header) => Pow5_64b_Wasm.elementaryIteration(header).toHex()),
+    );
+
+    // difficulty 2 passes about half
+    const target = targetFromDifficulty(2n);
+    const valid = await Pow5_64b_Native.verifyBatch(headers, target);
+    expect(valid).toEqual(
+      headers.map((header) =>
+        hashMeetsTarget(Pow5_64b_Wasm.elementaryIteration(header), target),
+      ),
+    );
+
+    expect(await Pow5_64b_Native.elementaryIterationBatch([])).toEqual([]);
+  });
+
+  test("both reject wrong sizes", async () => {
+    const short = FixedBuf.fromRandom(63) as unknown as FixedBuf<64>;
+    expect(() => Pow5_64b_Wasm.elementaryIteration(short)).toThrow();
+    expect(() => Pow5_64b_Native.elementaryIteration(short)).toThrow(
+      "header is not the correct size: expected 64, got 63",
+    );
+    await expect(
+      Pow5_64b_Native.elementaryIterationBatch([short]),
+    ).rejects.toThrow("header is not the correct size: expected 64, got 63");
+  });
+});
@@ -0,0 +1,53 @@
+from typing import Literal, Optional
+
+import numpy as np
+import numpy.typing as npt
+
+Purpose = Literal["register", "login", "channel", "message"]
+
+class ChallengeError(Exception): ...
+
+def elementary_iteration_64b(header: bytes) -> bytes: ...
+def matmul_work_64b(header: bytes) -> bytes: ...
+def elementary_iteration_217a(header: bytes) -> bytes: ...
+def get_work_par_217a(header: bytes) -> bytes: ...
+def target_from_difficulty(difficulty: int) -> bytes: ...
+def meets_target(hash: bytes, target: bytes) -> bool: ...
+def target_to_compact(target: bytes) -> int: ...
+def compact_to_target(compact: int) -> bytes: ...
+def expected_work(target: bytes) -> int: ...
+def hash_work(hash: bytes) -> int: ...
+def mine_64b(header: bytes, target: bytes, max_iterations: int) -> Optional[bytes]: ...
+def elementary_iteration_64b_batch(
+    headers: npt.NDArray[np.uint8],
+) -> npt.NDArray[np.uint8]: ...
+def verify_64b_batch(
+    headers: npt.NDArray[np.uint8], target: bytes
+) -> npt.NDArray[np.bool_]: ...
+def verify_hmac_challenge(
+    secret: bytes,
+    signature: bytes,
+    purpose: Purpose,
+    header: bytes,
+    target: bytes,
+    expires_at: int,
+    now: int,
+    sender_address: Optional[str] = None,
+    recipient_address: Optional[str] = None,
+    key_id: int = 0,
+    accept_v1: bool = False,
+    min_difficulty: Optional[int] = None,
+) -> Literal["v1", "v2"]: ...
+def verify_ed25519_challenge(
+    public_key: bytes,
+    signature: bytes,
+    purpose: Purpose,
+    header: bytes,
+    target: bytes,
+    expires_at: int,
+    now: int,
+    sender_address: Optional[str] = None,
+    recipient_address: Optional[str] = None,
+    key_id: int = 0,
+    min_difficulty: Optional[int] = None,
+) -> Literal["v2"]: ...
@@ -0,0 +1,410 @@
+// Python bindings for pow5, for notebooks and ops scripts.
+//
+// Bytes in, bytes out: headers, hashes and targets are `bytes` of the usual
+// sizes, and size errors raise `ValueError` with the same messages as the
+// wasm build. Work is returned as a Python int, since it can exceed 64 bits.
+// Failed challenge verification raises `ChallengeError`.
+//
+// The batch functions take an (n, 64) uint8 array of headers, so a notebook
+// can hash a column of a dataframe in one call. They release the GIL and
+// split the batch across every core. `mine_64b` also releases the GIL and
+// checks for Ctrl-C between rounds.
+
+use keypears_pow5::challenge::{
+    Challenge, ChallengeKey, ChallengePurpose, ChallengeVerifier, ChallengeVersion, Compatibility,
+    SignatureAlgorithm, VerifyOptions,
+};
+use keypears_pow5::compact::CompactTarget;
+use keypears_pow5::difficulty::Target;
+use keypears_pow5::ed25519::Ed25519Verifier;
+use keypears_pow5::mine::{hash_64b, mine_64b_with_progress};
+use keypears_pow5::work::{Work, work_from_hash};
+use numpy::ndarray::Array2;
+use numpy::{PyArray1, PyArray2, PyReadonlyArray2};
+use pyo3::create_exception;
+use pyo3::exceptions::{PyException, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyInt};
+use std::num::NonZeroUsize;
+use std::thread;
+
+create_exception!(keypears_pow5, ChallengeError, PyException);
+
+/// Counters tried by `mine_64b` between checks for Ctrl-C.
+const SIGNAL_CHECK_INTERVAL: u64 = 1 << 14;
+
+fn to_array<const N: usize>(name: &str, bytes: &[u8]) -> PyResult<[u8; N]> {
+    bytes.try_into().map_err(|_| {
+        PyValueError::new_err(format!(
+            "{} is not the correct size: expected {}, got {}",
+            name,
+            N,
+            bytes.len()
+        ))
+    })
+}
+
+fn to_bytes(py: Python<'_>, result: Result<Vec<u8>, String>) -> PyResult<Bound<'_, PyBytes>> {
+    result
+        .map(|bytes| PyBytes::new(py, &bytes))
+        .map_err(PyValueError::new_err)
+}
+
+fn work_to_int(py: Python<'_>, work: Work) -> PyResult<Bound<'_, PyAny>> {
+    py.get_type::<PyInt>().call1((work.to_string(),))
+}
+
+fn parse_purpose(purpose: &str) -> PyResult<ChallengePurpose> {
+    match purpose {
+        "register" => Ok(ChallengePurpose::Register),
+        "login" => Ok(ChallengePurpose::Login),
+        "channel" => Ok(ChallengePurpose::Channel),
+        "message" => Ok(ChallengePurpose::Message),
+        _ => Err(PyValueError::new_err(format!(
+            "unknown purpose: {}",
+            purpose
+        ))),
+    }
+}
+
+// =============================================================================
+// hashing
+// =============================================================================
+
+/// The pow5-64b hash of a 64-byte header.
+#[pyfunction]
+fn elementary_iteration_64b<'py>(py: Python<'py>, header: &[u8]) -> PyResult<Bound<'py, PyBytes>> {
+    to_bytes(py, keypears_pow5::elementary_iteration_64b(header.to_vec()))
+}
+
+#[pyfunction]
+fn matmul_work_64b<'py>(py: Python<'py>, header: &[u8]) -> PyResult<Bound<'py, PyBytes>> {
+    to_bytes(py, keypears_pow5::matmul_work_64b(header.to_vec()))
+}
+
+/// The pow5-217a block id of a 217-byte header.
+#[pyfunction]
+fn elementary_iteration_217a<'py>(py: Python<'py>, header: &[u8]) -> PyResult<Bound<'py, PyBytes>> {
+    to_bytes(
+        py,
+        keypears_pow5::elementary_iteration_217a(header.to_vec()),
+    )
+}
+
+#[pyfunction]
+fn get_work_par_217a<'py>(py: Python<'py>, header: &[u8]) -> PyResult<Bound<'py, PyBytes>> {
+    to_bytes(py, keypears_pow5::get_work_par_217a(header.to_vec()))
+}
+
+// =============================================================================
+// difficulty math
+// =============================================================================
+
+/// The 32-byte target for a difficulty.
+#[pyfunction]
+fn target_from_difficulty(py: Python<'_>, difficulty: u64) -> PyResult<Bound<'_, PyBytes>> {
+    let target = Target::from_difficulty(difficulty)
+        .map_err(|err| PyValueError::new_err(err.to_string()))?;
+    Ok(PyBytes::new(py, target.as_bytes()))
+}
+
+/// Whether a 32-byte hash meets a 32-byte target.
+#[pyfunction]
+fn meets_target(hash: &[u8], target: &[u8]) -> PyResult<bool> {
+    let hash = to_array("hash", hash)?;
+    Ok(Target::from_bytes(to_array("target", target)?).is_met_by(&hash))
+}
+
+/// The compact 4-byte form of a target, rounded down.
+#[pyfunction]
+fn target_to_compact(target: &[u8]) -> PyResult<u32> {
+    let target = Target::from_bytes(to_array("target", target)?);
+    Ok(CompactTarget::from_target(&target).to_u32())
+}
+
+/// The target a compact 4-byte form stands for.
+#[pyfunction]
+fn compact_to_target(py: Python<'_>, compact: u32) -> PyResult<Bound<'_, PyBytes>> {
+    let compact =
+        CompactTarget::from_u32(compact).map_err(|err| PyValueError::new_err(err.to_string()))?;
+    Ok(PyBytes::new(py, compact.to_target().as_bytes()))
+}
+
+/// Expected hashes to meet a target, 2^256 / target.
+#[pyfunction]
+fn expected_work<'py>(py: Python<'py>, target: &[u8]) -> PyResult<Bound<'py, PyAny>> {
+    let target = Target::from_bytes(to_array("target", target)?);
+    work_to_int(py, Work::expected(&target))
+}
+
+/// Estimated work a solution with this hash stands for, 2^256 / (hash + 1).
+#[pyfunction]
+fn hash_work<'py>(py: Python<'py>, hash: &[u8]) -> PyResult<Bound<'py, PyAny>> {
+    work_to_int(py, work_from_hash(&to_array("hash", hash)?))
+}
+
+// =============================================================================
+// mining
+// =============================================================================
+
+/// Mine a 64-byte header against a 32-byte target, trying at most
+/// `max_iterations` counters as the Rust `mine_64b` does. Returns the solved
+/// header, or None if there was none.
+#[pyfunction]
+fn mine_64b<'py>(
+    py: Python<'py>,
+    header: &[u8],
+    target: &[u8],
+    max_iterations: u64,
+) -> PyResult<Option<Bound<'py, PyBytes>>> {
+    let header = to_array("header", header)?;
+    let target = Target::from_bytes(to_array("target", target)?);
+    let mut interrupted = None;
+    let solved = py.detach(|| {
+        mine_64b_with_progress(
+            &header,
+            &target,
+            max_iterations,
+            SIGNAL_CHECK_INTERVAL,
+            |_| {
+                interrupted = Python::attach(|py| py.check_signals().err());
+                interrupted.is_none()
+            },
+        )
+    });
+    if let Some(err) = interrupted {
+        return Err(err);
+    }
+    Ok(solved.map(|solved| PyBytes::new(py, &solved)))
+}
+
+// =============================================================================
+// batches
+// =============================================================================
+
+/// Apply `f` to every item, split across the available cores, keeping order.
+fn parallel_map<T: Sync, U: Send>(items: &[T], f: impl Fn(&T) -> U + Sync) -> Vec<U> {
+    let threads = thread::available_parallelism().map_or(1, NonZeroUsize::get);
+    let chunk_size = items.len().div_ceil(threads).max(1);
+    thread::scope(|scope| {
+        let f = &f;
+        let handles: Vec<_> = items
+            .chunks(chunk_size)
+            .map(|chunk| scope.spawn(move || chunk.iter().map(f).collect::<Vec<_>>()))
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().expect("hashing does not panic"))
+            .collect()
+    })
+}
+
+fn headers_64b(headers: &PyReadonlyArray2<'_, u8>) -> PyResult<Vec<[u8; 64]>> {
+    let headers = headers.as_array();
+    if headers.ncols() != 64 {
+        return Err(PyValueError::new_err(format!(
+            "headers must have 64 columns, got {}",
+            headers.ncols()
+        )));
+    }
+    Ok(headers
+        .rows()
+        .into_iter()
+        .map(|row| {
+            let mut header = [0u8; 64];
+            header
+                .iter_mut()
+                .zip(row)
+                .for_each(|(out, byte)| *out = *byte);
+            header
+        })
+        .collect())
+}
+
+/// The pow5-64b hash of every row of an (n, 64) uint8 array, as an (n, 32)
+/// uint8 array.
+#[pyfunction]
+fn elementary_iteration_64b_batch<'py>(
+    py: Python<'py>,
+    headers: PyReadonlyArray2<'py, u8>,
+) -> PyResult<Bound<'py, PyArray2<u8>>> {
+    let headers = headers_64b(&headers)?;
+    let hashes = py.detach(|| parallel_map(&headers, hash_64b));
+    let hashes = Array2::from_shape_vec((hashes.len(), 32), hashes.concat())
+        .expect("every hash is 32 bytes");
+    Ok(PyArray2::from_owned_array(py, hashes))
+}
+
+/// Whether the pow5-64b hash of every row of an (n, 64) uint8 array meets a
+/// 32-byte target, as an (n,) bool array.
+#[pyfunction]
+fn verify_64b_batch<'py>(
+    py: Python<'py>,
+    headers: PyReadonlyArray2<'py, u8>,
+    target: &[u8],
+) -> PyResult<Bound<'py, PyArray1<bool>>> {
+    let headers = headers_64b(&headers)?;
+    let target = Target::from_bytes(to_array("target", target)?);
+    let valid = py.detach(|| parallel_map(&headers, |header| target.is_met_by(&hash_64b(header))));
+    Ok(PyArray1::from_vec(py, valid))
+}
+
+// =============================================================================
+// challenges
+// =============================================================================
+
+#[allow(clippy::too_many_arguments)]
+fn challenge(
+    purpose: &str,
+    header: &[u8],
+    target: &[u8],
+    expires_at: u64,
+    sender_address: Option<String>,
+    recipient_address: Option<String>,
+    key_id: u32,
+    algorithm: SignatureAlgorithm,
+) -> PyResult<Challenge> {
+    Ok(Challenge {
+        purpose: parse_purpose(purpose)?,
+        algorithm,
+        key_id,
+        header: to_array("header", header)?,
+        target: Target::from_bytes(to_array("target", target)?),
+        expires_at,
+        sender_address,
+        recipient_address,
+    })
+}
+
+fn options(
+    purpose: &str,
+    now: u64,
+    accept_v1: bool,
+    min_difficulty: Option<u64>,
+) -> PyResult<VerifyOptions> {
+    let purpose = parse_purpose(purpose)?;
+    let mut options = VerifyOptions::new(purpose, now);
+    if accept_v1 {
+        options.compatibility = Compatibility::AcceptV1;
+    }
+    if let Some(min_difficulty) = min_difficulty {
+        let min = &mut options.min_difficulties;
+        match purpose {
+            ChallengePurpose::Register => min.register = min_difficulty,
+            ChallengePurpose::Login => min.login = min_difficulty,
+            ChallengePurpose::Channel => min.channel = min_difficulty,
+            ChallengePurpose::Message => min.message = min_difficulty,
+        }
+    }
+    Ok(options)
+}
+
+fn verify_with(
+    verifier: &impl ChallengeVerifier,
+    challenge: &Challenge,
+    signature: &[u8],
+    options: &VerifyOptions,
+) -> PyResult<&'static str> {
+    match verifier.verify(challenge, signature, options) {
+        Ok(ChallengeVersion::V1) => Ok("v1"),
+        Ok(ChallengeVersion::V2) => Ok("v2"),
+        Err(err) => Err(ChallengeError::new_err(err.to_string())),
+    }
+}
+
+/// Verify a solved HMAC-SHA-256 challenge as the issuing server would, with
+/// the minimums in `pow.server.ts` unless `min_difficulty` is given. Returns
+/// the encoding it was signed with, "v1" or "v2".
+#[pyfunction]
+#[pyo3(signature = (
+    secret, signature, purpose, header, target, expires_at, now,
+    sender_address=None, recipient_address=None, key_id=0, accept_v1=false,
+    min_difficulty=None,
+))]
+#[allow(clippy::too_many_arguments)]
+fn verify_hmac_challenge(
+    secret: &[u8],
+    signature: &[u8],
+    purpose: &str,
+    header: &[u8],
+    target: &[u8],
+    expires_at: u64,
+    now: u64,
+    sender_address: Option<String>,
+    recipient_address: Option<String>,
+    key_id: u32,
+    accept_v1: bool,
+    min_difficulty: Option<u64>,
+) -> PyResult<&'static str> {
+    let key = ChallengeKey::new(to_array("secret", secret)?);
+    let challenge = challenge(
+        purpose,
+        header,
+        target,
+        expires_at,
+        sender_address,
+        recipient_address,
+        key_id,
+        SignatureAlgorithm::HmacSha256,
+    )?;
+    let options = options(purpose, now, accept_v1, min_difficulty)?;
+    verify_with(&key, &challenge, signature, &options)
+}
+
+/// Verify a solved Ed25519 challenge with the issuer's published key. Returns
+/// "v2", the only encoding Ed25519 challenges are signed with.
+#[pyfunction]
+#[pyo3(signature = (
+    public_key, signature, purpose, header, target, expires_at, now,
+    sender_address=None, recipient_address=None, key_id=0, min_difficulty=None,
+))]
+#[allow(clippy::too_many_arguments)]
+fn verify_ed25519_challenge(
+    public_key: &[u8],
+    signature: &[u8],
+    purpose: &str,
+    header: &[u8],
+    target: &[u8],
+    expires_at: u64,
+    now: u64,
+    sender_address: Option<String>,
+    recipient_address: Option<String>,
+    key_id: u32,
+    min_difficulty: Option<u64>,
+) -> PyResult<&'static str> {
+    let verifier = Ed25519Verifier::from_bytes(key_id, &to_array("public key", public_key)?)
+        .map_err(PyValueError::new_err)?;
+    let challenge = challenge(
+        purpose,
+        header,
+        target,
+        expires_at,
+        sender_address,
+        recipient_address,
+        key_id,
+        SignatureAlgorithm::Ed25519,
+    )?;
+    let options = options(purpose, now, false, min_difficulty)?;
+    verify_with(&verifier, &challenge, signature, &options)
+}
+
+#[pymodule(name = "keypears_pow5")]
+fn pow5(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add("ChallengeError", m.py().get_type::<ChallengeError>())?;
+    m.add_function(wrap_pyfunction!(elementary_iteration_64b, m)?)?;
+    m.add_function(wrap_pyfunction!(matmul_work_64b, m)?)?;
+    m.add_function(wrap_pyfunction!(elementary_iteration_217a, m)?)?;
+    m.add_function(wrap_pyfunction!(get_work_par_217a, m)?)?;
+    m.add_function(wrap_pyfunction!(target_from_difficulty, m)?)?;
+    m.add_function(wrap_pyfunction!(meets_target, m)?)?;
+    m.add_function(wrap_pyfunction!(target_to_compact, m)?)?;
+    m.add_function(wrap_pyfunction!(compact_to_target, m)?)?;
+    m.add_function(wrap_pyfunction!(expected_work, m)?)?;
+    m.add_function(wrap_pyfunction!(hash_work, m)?)?;
+    m.add_function(wrap_pyfunction!(mine_64b, m)?)?;
+    m.add_function(wrap_pyfunction!(elementary_iteration_64b_batch, m)?)?;
+    m.add_function(wrap_pyfunction!(verify_64b_batch, m)?)?;
+    m.add_function(wrap_pyfunction!(verify_hmac_challenge, m)?)?;
+    m.add_function(wrap_pyfunction!(verify_ed25519_challenge, m)?)?;
+    Ok(())
+}
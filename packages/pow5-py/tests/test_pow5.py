@@ -0,0 +1,146 @@
+import numpy as np
+import pytest
+
+import keypears_pow5 as pow5
+
+# Expected values from the Rust tests
+MATMUL_WORK_ALL_ZEROES = "b1fee00a999ab4d93dcd2f6ced975c4e8ee110e0a1d48cb094fec3c934d0ee3c"
+ELEMENTARY_ITERATION_ALL_ONES = "b5906d01328e86064b2a4783d0fc5f512fb1f2f923b3a869575482c0904fba44"
+WORK_PAR_217A_ALL_ZEROES = "6fe9eddc39bb4183c44853c41876801be94a138ea9adea89f40a08442d2f79b8"
+
+
+def test_known_vectors():
+    assert pow5.matmul_work_64b(bytes(64)).hex() == MATMUL_WORK_ALL_ZEROES
+    assert pow5.elementary_iteration_64b(b"\x11" * 64).hex() == ELEMENTARY_ITERATION_ALL_ONES
+    assert pow5.get_work_par_217a(bytes(217)).hex() == WORK_PAR_217A_ALL_ZEROES
+
+
+def test_wrong_size():
+    with pytest.raises(ValueError, match="expected 64, got 63"):
+        pow5.elementary_iteration_64b(bytes(63))
+    with pytest.raises(ValueError):
+        pow5.get_work_par_217a(bytes(64))
+
+
+def test_difficulty():
+    target = pow5.target_from_difficulty(7_000_000)
+    assert pow5.target_to_compact(target) == 0x1E026591
+    assert pow5.target_to_compact(pow5.compact_to_target(0x1E026591)) == 0x1E026591
+    assert pow5.expected_work(target) == 7_000_000
+    assert pow5.hash_work(b"\x7f" + b"\xff" * 31) == 2
+    with pytest.raises(ValueError):
+        pow5.target_from_difficulty(0)
+
+
+def test_mine():
+    header = bytes(64)
+    target = pow5.target_from_difficulty(16)
+    solved = pow5.mine_64b(header, target, 10_000)
+    assert solved is not None
+    assert solved[32:] == header[32:]
+    assert pow5.meets_target(pow5.elementary_iteration_64b(solved), target)
+    assert pow5.mine_64b(header, bytes(32), 10) is None
+
+
+def test_batches():
+    rng = np.random.default_rng(5)
+    headers = rng.integers(0, 256, size=(32, 64), dtype=np.uint8)
+    hashes = pow5.elementary_iteration_64b_batch(headers)
+    assert hashes.shape == (32, 32)
+    for header, hash in zip(headers, hashes):
+        assert bytes(hash) == pow5.elementary_iteration_64b(bytes(header))
+
+    target = pow5.target_from_difficulty(2)
+    valid = pow5.verify_64b_batch(headers, target)
+    assert valid.dtype == np.bool_
+    assert list(valid) == [pow5.meets_target(bytes(hash), target) for hash in hashes]
+
+    with pytest.raises(ValueError, match="64 columns"):
+        pow5.elementary_iteration_64b_batch(np.zeros((2, 63), dtype=np.uint8))
+
+
+def test_challenge_errors():
+    target = pow5.target_from_difficulty(7_000_000)
+    with pytest.raises(pow5.ChallengeError):
+        pow5.verify_hmac_challenge(bytes(32), bytes(32), "login", bytes(64), target, 100, 200)
+    with pytest.raises(pow5.ChallengeError):
+        pow5.verify_hmac_challenge(bytes(32), bytes(32), "login", bytes(64), target, 200, 100)
+    with pytest.raises(ValueError, match="unknown purpose"):
+        pow5.verify_hmac_challenge(bytes(32), bytes(32), "spam", bytes(64), target, 200, 100)
+
+
+# The challenge pow.server.ts issued in the Rust contract tests, signed with a
+# key of 32 0x07 bytes, with its header mined to the 7M target
+V1_SOLVED_HEADER = (
+    "0b30557a9fc4e90e33587da2c7ec11365b80a5caef14395e0000000000293d30"
+    "abd0f51a3f6489aed3f81d42678cb1d6fb20456a8fb4d9fe23486d92b7dc0126"
+)
+V1_TARGET = "000002659116f56b142c62f7fe2f080c9a04dab65d0011605be673485241cd85"
+V1_SIGNATURE = "7ae46771322b83438088ba69869c8742867b7dd35d8f56d20cd7918cc77c1462"
+V1_EXPIRES_AT = 1_760_000_900_000
+
+# Signed by the Rust library: a v2 login challenge under the same key, and an
+# Ed25519 channel challenge under key id 9 with a secret of 32 0x05 bytes
+V2_SIGNATURE = "c14102975751d874adad2542fbf428369f16d4b955cf8ec52fe83cdd68f2814e"
+ED25519_PUBLIC_KEY = "6e7a1cdd29b0b78fd13af4c5598feff4ef2a97166e3ca6f2e4fbfccd80505bf1"
+ED25519_SIGNATURE = (
+    "6dc1c25e684cc753789e1d19bf02e0394e2fec3970d6b088403c40b2d0eab393"
+    "19d1762011819c38e76d73cef53b3e343acd2aa8eb47129c037b4e980872370e"
+)
+
+NOW = 1_760_000_000_000
+MAX_TARGET = b"\xff" * 32
+
+
+def test_verify_hmac_challenge():
+    v1 = dict(
+        secret=b"\x07" * 32,
+        signature=bytes.fromhex(V1_SIGNATURE),
+        purpose="message",
+        header=bytes.fromhex(V1_SOLVED_HEADER),
+        target=bytes.fromhex(V1_TARGET),
+        expires_at=V1_EXPIRES_AT,
+        now=NOW,
+        sender_address="alice@keypears.com",
+        recipient_address="bob@example.com",
+    )
+    assert pow5.verify_hmac_challenge(**v1, accept_v1=True) == "v1"
+    with pytest.raises(pow5.ChallengeError):
+        pow5.verify_hmac_challenge(**v1)
+
+    v2 = dict(
+        secret=b"\x07" * 32,
+        signature=bytes.fromhex(V2_SIGNATURE),
+        purpose="login",
+        header=b"\x42" * 64,
+        target=MAX_TARGET,
+        expires_at=V1_EXPIRES_AT,
+        now=NOW,
+        key_id=3,
+        min_difficulty=1,
+    )
+    assert pow5.verify_hmac_challenge(**v2) == "v2"
+    assert pow5.verify_hmac_challenge(**v2, accept_v1=True) == "v2"
+    with pytest.raises(pow5.ChallengeError):
+        pow5.verify_hmac_challenge(**{**v2, "key_id": 4})
+
+
+def test_verify_ed25519_challenge():
+    challenge = dict(
+        public_key=bytes.fromhex(ED25519_PUBLIC_KEY),
+        signature=bytes.fromhex(ED25519_SIGNATURE),
+        purpose="channel",
+        header=b"\x42" * 64,
+        target=MAX_TARGET,
+        expires_at=V1_EXPIRES_AT,
+        now=NOW,
+        sender_address="alice@keypears.com",
+        recipient_address="bob@example.com",
+        key_id=9,
+        min_difficulty=1,
+    )
+    assert pow5.verify_ed25519_challenge(**challenge) == "v2"
+    with pytest.raises(pow5.ChallengeError):
+        pow5.verify_ed25519_challenge(**{**challenge, "recipient_address": "carol@example.com"})
+    with pytest.raises(ValueError):
+        pow5.verify_ed25519_challenge(**{**challenge, "public_key": bytes(31)})
@@ -0,0 +1,82 @@
+/* Exercise the C ABI the way a Go or Python server would. Run ffi-test.zsh. */
+
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <string.h>
+
+#include "keypears_pow5.h"
+
+static int failures = 0;
+
+#define CHECK(cond)                                                 \
+  do {                                                              \
+    if (!(cond)) {                                                  \
+      fprintf(stderr, "%s:%d: check failed: %s\n", __FILE__,        \
+              __LINE__, #cond);                                     \
+      failures++;                                                   \
+    }                                                               \
+  } while (0)
+
+static void to_hex(const uint8_t *bytes, size_t len, char *out) {
+  for (size_t i = 0; i < len; i++) {
+    sprintf(out + 2 * i, "%02x", bytes[i]);
+  }
+}
+
+static bool stop_after_one(uint64_t iterations, void *user_data) {
+  (void)iterations;
+  (*(int *)user_data)++;
+  return false;
+}
+
+int main(void) {
+  uint8_t header[64];
+  uint8_t hash[32];
+  uint8_t target[32];
+  uint8_t solved[64];
+  char hex[65];
+
+  /* the all-zero pow5-217a header, as in the Rust tests */
+  uint8_t header_217a[217] = {0};
+  CHECK(pow5_hash_217a(header_217a, hash) == POW5_STATUS_OK);
+  to_hex(hash, 32, hex);
+  CHECK(strcmp(hex, "c88f591bfa80126e9a14d76d473ca8ae7ac578ed1eac0150fcbc06742f4f7d6f") == 0);
+
+  memset(header, 0x11, sizeof header);
+  CHECK(pow5_hash_64b(header, hash) == POW5_STATUS_OK);
+  CHECK(pow5_hash_64b(NULL, hash) == POW5_STATUS_NULL_POINTER);
+
+  /* login difficulty and its compact form */
+  uint32_t compact = 0;
+  CHECK(pow5_target_from_difficulty(7000000, target) == POW5_STATUS_OK);
+  CHECK(pow5_target_to_compact(target, &compact) == POW5_STATUS_OK);
+  CHECK(compact == 0x1e026591);
+  CHECK(pow5_compact_to_target(compact, target) == POW5_STATUS_OK);
+  CHECK(target[2] == 0x02 && target[3] == 0x65 && target[4] == 0x91);
+  CHECK(pow5_compact_to_target(0x1e006591, target) == POW5_STATUS_INVALID_INPUT);
+  CHECK(pow5_target_from_difficulty(0, target) == POW5_STATUS_INVALID_INPUT);
+
+  /* mine and verify at a low difficulty */
+  memset(header, 0, sizeof header);
+  CHECK(pow5_target_from_difficulty(16, target) == POW5_STATUS_OK);
+  CHECK(pow5_mine_64b(header, target, 10000, 0, NULL, NULL, solved) == POW5_STATUS_OK);
+  CHECK(pow5_verify_64b(solved, target) == POW5_STATUS_OK);
+  CHECK(memcmp(solved + 32, header + 32, 32) == 0);
+
+  uint8_t impossible[32] = {0};
+  CHECK(pow5_verify_64b(solved, impossible) == POW5_STATUS_NOT_MET);
+  CHECK(pow5_mine_64b(header, impossible, 10, 0, NULL, NULL, solved) == POW5_STATUS_NOT_FOUND);
+
+  int calls = 0;
+  CHECK(pow5_mine_64b(header, impossible, UINT64_MAX, 5, stop_after_one, &calls, solved) ==
+        POW5_STATUS_CANCELLED);
+  CHECK(calls == 1);
+
+  if (failures) {
+    fprintf(stderr, "%d checks failed\n", failures);
+    return 1;
+  }
+  printf("ok\n");
+  return 0;
+}
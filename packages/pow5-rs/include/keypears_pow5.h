@@ -0,0 +1,128 @@
+#ifndef KEYPEARS_POW5_H
+#define KEYPEARS_POW5_H
+
+/* Generated by cbindgen from src/ffi.rs. Do not edit; run cbindgen.zsh. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * The result of every call.
+ */
+typedef enum Pow5Status {
+  POW5_STATUS_OK = 0,
+  /**
+   * A required pointer was null.
+   */
+  POW5_STATUS_NULL_POINTER = 1,
+  /**
+   * The input was not valid, e.g. a difficulty of 0, a non-canonical
+   * compact target or a progress callback with an interval of 0.
+   */
+  POW5_STATUS_INVALID_INPUT = 2,
+  /**
+   * The hash does not meet the target.
+   */
+  POW5_STATUS_NOT_MET = 3,
+  /**
+   * The miner tried every iteration without a solution.
+   */
+  POW5_STATUS_NOT_FOUND = 4,
+  /**
+   * The progress callback stopped the miner.
+   */
+  POW5_STATUS_CANCELLED = 5,
+} Pow5Status;
+
+/**
+ * Called by `pow5_mine_64b` with the number of iterations tried so far.
+ * Return false to stop mining. May be null.
+ */
+typedef bool (*Pow5ProgressCallback)(uint64_t iterations, void *user_data);
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Write the pow5-64b hash of the 64-byte `header` to the 32-byte `out`.
+ *
+ * # Safety
+ *
+ * `header` must be valid for reading 64 bytes and `out` for writing 32.
+ */
+enum Pow5Status pow5_hash_64b(const uint8_t *header, uint8_t *out);
+
+/**
+ * Write the pow5-217a block id of the 217-byte `header` to the 32-byte `out`.
+ *
+ * # Safety
+ *
+ * `header` must be valid for reading 217 bytes and `out` for writing 32.
+ */
+enum Pow5Status pow5_hash_217a(const uint8_t *header, uint8_t *out);
+
+/**
+ * Check that the pow5-64b hash of the 64-byte `header` meets the 32-byte
+ * `target`. Returns `Ok` if it does and `NotMet` if it does not.
+ *
+ * # Safety
+ *
+ * `header` must be valid for reading 64 bytes and `target` for reading 32.
+ */
+enum Pow5Status pow5_verify_64b(const uint8_t *header, const uint8_t *target);
+
+/**
+ * Write the 32-byte target for `difficulty` to `out`.
+ *
+ * # Safety
+ *
+ * `out` must be valid for writing 32 bytes.
+ */
+enum Pow5Status pow5_target_from_difficulty(uint64_t difficulty, uint8_t *out);
+
+/**
+ * Encode the 32-byte `target` as a compact 4-byte target, rounding down.
+ *
+ * # Safety
+ *
+ * `target` must be valid for reading 32 bytes and `out` for writing a u32.
+ */
+enum Pow5Status pow5_target_to_compact(const uint8_t *target, uint32_t *out);
+
+/**
+ * Decode a compact 4-byte target to the 32-byte target `out`.
+ *
+ * # Safety
+ *
+ * `out` must be valid for writing 32 bytes.
+ */
+enum Pow5Status pow5_compact_to_target(uint32_t compact, uint8_t *out);
+
+/**
+ * Mine the 64-byte `header` against the 32-byte `target`, trying at most
+ * `max_iterations` nonces as `mine_64b` does, and write the solved header to
+ * the 64-byte `out`. If `progress` is not null it is called with `user_data`
+ * every `progress_interval` iterations and mining stops if it returns false.
+ *
+ * # Safety
+ *
+ * `header` must be valid for reading 64 bytes, `target` for reading 32 and
+ * `out` for writing 64. `progress`, if not null, must be safe to call with
+ * `user_data`.
+ */
+enum Pow5Status pow5_mine_64b(const uint8_t *header,
+                              const uint8_t *target,
+                              uint64_t max_iterations,
+                              uint64_t progress_interval,
+                              Pow5ProgressCallback progress,
+                              void *user_data,
+                              uint8_t *out);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* KEYPEARS_POW5_H */
@@ -0,0 +1,365 @@
+// Adaptive difficulty for operators under attack.
+//
+// The controller watches solved challenges and challenge requests over a
+// sliding window and recommends a difficulty that brings the solve rate back
+// to a target. The control law, applied at every update:
+//
+//   hashrate  = sum of the difficulties solved in the window / window length
+//   wanted    = hashrate / target solve rate
+//   if requests come faster than the request limit, the difficulty is also
+//   raised in proportion, since an attacker can stockpile challenges before
+//   solving any:
+//   wanted    = max(wanted, current * request rate / request limit)
+//   next      = current + damping * (wanted - current)
+//   next      = clamped to [current / max_step, current * max_step]
+//   next      = clamped to [floor, ceiling]
+//
+// An attacker with fixed hardware solves hashrate / difficulty challenges per
+// second, so `wanted` is the difficulty at which they would be held to the
+// target rate. With no traffic `wanted` is zero and the difficulty decays back
+// to the floor. Honest traffic counts too, so the target rate should sit well
+// above what honest users produce.
+//
+// Nothing here reads the clock: every time is passed in (ms since the Unix
+// epoch) and only IEEE basic arithmetic is used, so `replay` over recorded
+// traffic gives the same answers on every platform.
+
+use std::collections::VecDeque;
+
+/// Tuning for a [`DifficultyController`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ControllerConfig {
+    /// Lowest difficulty ever recommended; also the starting difficulty.
+    pub floor: u64,
+    /// Highest difficulty ever recommended.
+    pub ceiling: u64,
+    /// Solves per minute the controller steers towards.
+    pub target_solves_per_minute: f64,
+    /// Challenge requests per minute above which the difficulty is raised
+    /// even before solves arrive.
+    pub max_requests_per_minute: f64,
+    /// Length of the sliding window, in ms.
+    pub window_ms: u64,
+    /// Fraction of the way to the wanted difficulty to move on each update,
+    /// in (0, 1].
+    pub damping: f64,
+    /// Largest factor the difficulty may change by in one update, at least 1.
+    pub max_step: f64,
+}
+
+impl ControllerConfig {
+    /// A config between `floor` and `ceiling` with default tuning: a 5 minute
+    /// window, 10 solves and 60 requests per minute, half-way damping and at
+    /// most doubling or halving per update.
+    pub fn new(floor: u64, ceiling: u64) -> Self {
+        ControllerConfig {
+            floor,
+            ceiling,
+            target_solves_per_minute: 10.0,
+            max_requests_per_minute: 60.0,
+            window_ms: 5 * 60 * 1000,
+            damping: 0.5,
+            max_step: 2.0,
+        }
+    }
+
+    /// Registration, starting from `REGISTRATION_DIFFICULTY` in
+    /// `pow.server.ts`.
+    pub fn registration() -> Self {
+        ControllerConfig::new(70_000_000, 70_000_000 * 64)
+    }
+
+    /// Login, starting from `LOGIN_DIFFICULTY` in `pow.server.ts`.
+    pub fn login() -> Self {
+        ControllerConfig::new(7_000_000, 7_000_000 * 64)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.floor == 0 || self.floor > self.ceiling {
+            return Err("floor must be at least 1 and at most the ceiling".to_string());
+        }
+        let rates = [self.target_solves_per_minute, self.max_requests_per_minute];
+        if rates.iter().any(|rate| rate.is_nan() || *rate <= 0.0) {
+            return Err("rates must be greater than 0".to_string());
+        }
+        if self.window_ms == 0 {
+            return Err("window must not be empty".to_string());
+        }
+        if self.damping.is_nan() || self.damping <= 0.0 || self.damping > 1.0 {
+            return Err("damping must be in (0, 1]".to_string());
+        }
+        if self.max_step.is_nan() || self.max_step < 1.0 {
+            return Err("max step must be at least 1".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Something the controller learns from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Event {
+    /// A challenge at `difficulty` was solved and accepted at `at`.
+    Solve { at: u64, difficulty: u64 },
+    /// A challenge was requested at `at`.
+    Request { at: u64 },
+}
+
+impl Event {
+    pub fn at(&self) -> u64 {
+        match *self {
+            Event::Solve { at, .. } | Event::Request { at } => at,
+        }
+    }
+}
+
+/// Recommends a difficulty from recent traffic.
+#[derive(Clone, Debug)]
+pub struct DifficultyController {
+    config: ControllerConfig,
+    difficulty: u64,
+    last_event_at: u64,
+    solves: VecDeque<(u64, u64)>,
+    solved_difficulty: u128,
+    requests: VecDeque<u64>,
+}
+
+impl DifficultyController {
+    pub fn new(config: ControllerConfig) -> Result<Self, String> {
+        config.validate()?;
+        Ok(DifficultyController {
+            config,
+            difficulty: config.floor,
+            last_event_at: 0,
+            solves: VecDeque::new(),
+            solved_difficulty: 0,
+            requests: VecDeque::new(),
+        })
+    }
+
+    pub fn config(&self) -> &ControllerConfig {
+        &self.config
+    }
+
+    /// The current recommendation.
+    pub fn difficulty(&self) -> u64 {
+        self.difficulty
+    }
+
+    /// Record an event. Events must arrive in time order.
+    pub fn record(&mut self, event: Event) -> Result<(), String> {
+        if event.at() < self.last_event_at {
+            return Err(format!(
+                "event at {} is before the previous event at {}",
+                event.at(),
+                self.last_event_at
+            ));
+        }
+        self.last_event_at = event.at();
+        match event {
+            Event::Solve { at, difficulty } => {
+                self.solves.push_back((at, difficulty));
+                self.solved_difficulty += u128::from(difficulty);
+            }
+            Event::Request { at } => self.requests.push_back(at),
+        }
+        Ok(())
+    }
+
+    /// Apply the control law to the window ending at `now` and return the new
+    /// recommendation.
+    pub fn update(&mut self, now: u64) -> u64 {
+        let window_start = now.saturating_sub(self.config.window_ms);
+        while let Some(&(at, difficulty)) = self.solves.front() {
+            if at >= window_start {
+                break;
+            }
+            self.solves.pop_front();
+            self.solved_difficulty -= u128::from(difficulty);
+        }
+        while let Some(&at) = self.requests.front() {
+            if at >= window_start {
+                break;
+            }
+            self.requests.pop_front();
+        }
+
+        let window_minutes = self.config.window_ms as f64 / 60_000.0;
+        let current = self.difficulty as f64;
+        let hashes_per_minute = self.solved_difficulty as f64 / window_minutes;
+        let mut wanted = hashes_per_minute / self.config.target_solves_per_minute;
+        let requests_per_minute = self.requests.len() as f64 / window_minutes;
+        if requests_per_minute > self.config.max_requests_per_minute {
+            wanted =
+                wanted.max(current * requests_per_minute / self.config.max_requests_per_minute);
+        }
+
+        let next = (current + self.config.damping * (wanted - current))
+            .max(current / self.config.max_step)
+            .min(current * self.config.max_step)
+            .max(self.config.floor as f64)
+            .min(self.config.ceiling as f64);
+        self.difficulty = next as u64;
+        self.difficulty
+    }
+}
+
+/// Feed recorded `events` (in time order) through a fresh controller, updating
+/// every `interval_ms` from the first event until the last, and return each
+/// update as `(time, difficulty)`.
+pub fn replay(
+    config: ControllerConfig,
+    events: &[Event],
+    interval_ms: u64,
+) -> Result<Vec<(u64, u64)>, String> {
+    if interval_ms == 0 {
+        return Err("interval must be greater than 0".to_string());
+    }
+    let mut controller = DifficultyController::new(config)?;
+    let mut trace = Vec::new();
+    let (Some(first), Some(last)) = (events.first(), events.last()) else {
+        return Ok(trace);
+    };
+    let mut next_update = first.at() + interval_ms;
+    let mut events = events.iter().peekable();
+    while next_update <= last.at() + interval_ms {
+        while let Some(event) = events.next_if(|event| event.at() < next_update) {
+            controller.record(*event)?;
+        }
+        trace.push((next_update, controller.update(next_update)));
+        next_update += interval_ms;
+    }
+    Ok(trace)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINUTE: u64 = 60_000;
+
+    /// An attacker solving at `hashes_per_minute` against whatever the
+    /// controller recommends, for `minutes`, updating once a minute.
+    fn attack(controller: &mut DifficultyController, hashes_per_minute: u64, minutes: u64) {
+        for minute in 0..minutes {
+            let start = minute * MINUTE;
+            let difficulty = controller.difficulty();
+            let solves = hashes_per_minute / difficulty;
+            for i in 0..solves {
+                let at = start + i * MINUTE / solves.max(1);
+                controller.record(Event::Solve { at, difficulty }).unwrap();
+            }
+            controller.update(start + MINUTE);
+        }
+    }
+
+    fn config() -> ControllerConfig {
+        ControllerConfig {
+            window_ms: 5 * MINUTE,
+            ..ControllerConfig::new(1_000, 1_000_000)
+        }
+    }
+
+    #[test]
+    fn test_converges_on_target_rate() {
+        let mut controller = DifficultyController::new(config()).unwrap();
+        attack(&mut controller, 1_000_000, 60);
+        // 10 solves a minute at 1M hashes a minute
+        let difficulty = controller.difficulty();
+        assert!((90_000..=110_000).contains(&difficulty), "{}", difficulty);
+
+        // and back to the floor once the attack stops
+        for minute in 61..90 {
+            controller.update(minute * MINUTE);
+        }
+        assert_eq!(controller.difficulty(), 1_000);
+    }
+
+    #[test]
+    fn test_ceiling_and_max_step() {
+        let mut controller = DifficultyController::new(config()).unwrap();
+        controller
+            .record(Event::Solve {
+                at: 0,
+                difficulty: 1_000,
+            })
+            .unwrap();
+        for _ in 0..10_000 {
+            controller.record(Event::Request { at: 1 }).unwrap();
+        }
+        // at most doubling per update
+        assert_eq!(controller.update(MINUTE), 2_000);
+        assert_eq!(controller.update(2 * MINUTE), 4_000);
+
+        let config = ControllerConfig {
+            floor: 100_000,
+            ..config()
+        };
+        let mut controller = DifficultyController::new(config).unwrap();
+        attack(&mut controller, 1_000_000_000, 30);
+        assert_eq!(controller.difficulty(), 1_000_000);
+    }
+
+    #[test]
+    fn test_request_pressure() {
+        let mut controller = DifficultyController::new(config()).unwrap();
+        // 120 requests a minute against a limit of 60, and no solves yet
+        for at in 0..600 {
+            controller.record(Event::Request { at: at * 500 }).unwrap();
+        }
+        assert_eq!(controller.update(5 * MINUTE), 1_500);
+    }
+
+    #[test]
+    fn test_replay_is_deterministic() {
+        let mut events = Vec::new();
+        for i in 0..2_000 {
+            events.push(Event::Request { at: i * 997 });
+            if i % 3 == 0 {
+                events.push(Event::Solve {
+                    at: i * 997 + 500,
+                    difficulty: 50_000,
+                });
+            }
+        }
+        let trace = replay(config(), &events, MINUTE).unwrap();
+        assert_eq!(trace, replay(config(), &events, MINUTE).unwrap());
+        assert_eq!(trace.len(), 34);
+        assert_eq!(trace[0].0, MINUTE);
+        assert!(trace.iter().all(|&(_, d)| (1_000..=1_000_000).contains(&d)));
+        assert!(trace.last().unwrap().1 > 1_000);
+
+        assert_eq!(replay(config(), &[], MINUTE), Ok(Vec::new()));
+        events.swap(0, 5);
+        assert!(replay(config(), &events, MINUTE).is_err());
+        assert!(replay(config(), &events, 0).is_err());
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(ControllerConfig::registration().validate().is_ok());
+        assert!(ControllerConfig::login().validate().is_ok());
+        let bad = [
+            ControllerConfig::new(0, 10),
+            ControllerConfig::new(10, 9),
+            ControllerConfig {
+                damping: 0.0,
+                ..config()
+            },
+            ControllerConfig {
+                max_step: 0.5,
+                ..config()
+            },
+            ControllerConfig {
+                target_solves_per_minute: f64::NAN,
+                ..config()
+            },
+            ControllerConfig {
+                window_ms: 0,
+                ..config()
+            },
+        ];
+        for config in bad {
+            assert!(DifficultyController::new(config).is_err(), "{:?}", config);
+        }
+    }
+}
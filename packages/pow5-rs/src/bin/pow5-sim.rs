@@ -0,0 +1,110 @@
+// Print the spam economics of pow5 difficulty settings.
+//
+// Usage:
+//   pow5-sim <attacker_hash_rate> <attacker_cost_per_hour> [options]
+//
+// Options, each of which may be repeated:
+//   --device <name>=<hash_rate>:<share>   a legitimate user device
+//   --difficulty <name>=<difficulty>      a difficulty to report on
+//   --value <value>                       a spam message value to break even at
+//   --typst                               print Typst tables
+//
+// Without --device, legitimate users are assumed to have the attacker's
+// hardware. Without --difficulty, the difficulties in `pow.server.ts` are
+// used, and without --value, 0.0001, 0.001 and 0.01.
+//
+// Example:
+//   cargo run --bin pow5-sim -- 4500000 0.5 --device desktop=4500000:1 \
+//     --device phone=300000:3
+
+use keypears_pow5::simulate::{Attacker, Device, Scenario};
+use std::process::exit;
+
+fn parse_f64(name: &str, value: &str) -> Result<f64, String> {
+    value
+        .parse()
+        .map_err(|_| format!("{} is not a number: {}", name, value))
+}
+
+fn parse_device(arg: &str) -> Result<Device, String> {
+    let (name, rest) = arg
+        .split_once('=')
+        .ok_or_else(|| format!("device must be <name>=<hash_rate>:<share>: {}", arg))?;
+    let (rate, share) = rest
+        .split_once(':')
+        .ok_or_else(|| format!("device must be <name>=<hash_rate>:<share>: {}", arg))?;
+    Ok(Device {
+        name: name.to_string(),
+        hashes_per_second: parse_f64("hash rate", rate)?,
+        share: parse_f64("share", share)?,
+    })
+}
+
+fn parse_difficulty(arg: &str) -> Result<(String, u64), String> {
+    let (name, difficulty) = arg
+        .split_once('=')
+        .ok_or_else(|| format!("difficulty must be <name>=<difficulty>: {}", arg))?;
+    let difficulty = difficulty
+        .parse()
+        .map_err(|_| format!("difficulty is not an integer: {}", difficulty))?;
+    Ok((name.to_string(), difficulty))
+}
+
+fn run(args: &[String]) -> Result<String, String> {
+    let [hash_rate, cost, options @ ..] = args else {
+        return Err(
+            "usage: pow5-sim <attacker_hash_rate> <attacker_cost_per_hour> \
+                    [--device <name>=<hash_rate>:<share>] [--difficulty <name>=<difficulty>] \
+                    [--value <value>] [--typst]"
+                .to_string(),
+        );
+    };
+    let mut scenario = Scenario::new(Attacker {
+        hashes_per_second: parse_f64("attacker hash rate", hash_rate)?,
+        cost_per_hour: parse_f64("attacker cost per hour", cost)?,
+    });
+    let (mut devices, mut difficulties, mut values) = (Vec::new(), Vec::new(), Vec::new());
+    let mut typst = false;
+    let mut options = options.iter();
+    while let Some(option) = options.next() {
+        let mut value = || {
+            options
+                .next()
+                .ok_or_else(|| format!("{} needs a value", option))
+        };
+        match option.as_str() {
+            "--device" => devices.push(parse_device(value()?)?),
+            "--difficulty" => difficulties.push(parse_difficulty(value()?)?),
+            "--value" => values.push(parse_f64("value", value()?)?),
+            "--typst" => typst = true,
+            _ => return Err(format!("unknown option: {}", option)),
+        }
+    }
+    if !devices.is_empty() {
+        scenario.devices = devices;
+    }
+    if !difficulties.is_empty() {
+        scenario.difficulties = difficulties;
+    }
+    if !values.is_empty() {
+        scenario.spam_values = values;
+    }
+
+    let report = scenario.run()?;
+    Ok(if typst {
+        report.to_typst()
+    } else {
+        report.to_string()
+    })
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match run(&args) {
+        Ok(output) => print!("{}", output),
+        Err(err) => {
+            eprintln!("{}", err);
+            exit(1);
+        }
+    }
+}
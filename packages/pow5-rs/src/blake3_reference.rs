@@ -1,5 +1,13 @@
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+#[cfg(feature = "wasm")]
 use wasm_bindgen::prelude::*;
 
+#[cfg(not(feature = "simd128"))]
+use self::compress as compress_block;
+#[cfg(feature = "simd128")]
+use crate::simd128::compress as compress_block;
+
 const OUT_LEN: usize = 32;
 const BLOCK_LEN: usize = 64;
 
@@ -8,11 +16,12 @@ const CHUNK_START: u32 = 1;
 const CHUNK_END: u32 = 2;
 const ROOT: u32 = 8;
 
-const IV: [u32; 8] = [
+pub(crate) const IV: [u32; 8] = [
     0x6A09E667, 0xBB67AE85, 0x3C6EF372, 0xA54FF53A, 0x510E527F, 0x9B05688C, 0x1F83D9AB, 0x5BE0CD19,
 ];
 
-const MSG_PERMUTATION: [usize; 16] = [2, 6, 3, 10, 7, 0, 4, 13, 1, 11, 12, 5, 9, 14, 15, 8];
+pub(crate) const MSG_PERMUTATION: [usize; 16] =
+    [2, 6, 3, 10, 7, 0, 4, 13, 1, 11, 12, 5, 9, 14, 15, 8];
 
 // The mixing function, G, which mixes either a column or a diagonal.
 fn g(state: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize, mx: u32, my: u32) {
@@ -47,7 +56,10 @@ fn permute(m: &mut [u32; 16]) {
     *m = permuted;
 }
 
-fn compress(
+// the scalar compression function; with the simd128 feature the hash uses
+// `simd128::compress` instead, and this is kept for the parity tests
+#[cfg_attr(feature = "simd128", allow(dead_code))]
+pub(crate) fn compress(
     chaining_value: &[u32; 8],
     block_words: &[u32; 16],
     block_len: u32,
@@ -131,7 +143,7 @@ fn root_output_bytes(output: &Output, out_slice: &mut [u32]) {
         let out_block_start = out_block_index * OUT_BLOCK_SIZE;
         let out_block_end = min(out_block_start + OUT_BLOCK_SIZE, OUT_SLICE_SIZE);
 
-        let words = compress(
+        let words = compress_block(
             &output.input_chaining_value,
             &output.block_words,
             output.block_len,
@@ -179,7 +191,7 @@ fn blake3_hash_internal(
         if block_len as usize == BLOCK_LEN {
             let mut block_words = [0; 16];
             words_from_little_endian_bytes(&block, &mut block_words);
-            chaining_value = first_8_words(compress(
+            chaining_value = first_8_words(compress_block(
                 &chaining_value,
                 &block_words,
                 BLOCK_LEN as u32,
@@ -219,8 +231,8 @@ fn blake3_hash_internal(
     hash_output
 }
 
-#[wasm_bindgen]
-pub fn blake3_reference_hash(input: Vec<u8>) -> Vec<u8> {
+/// BLAKE3 of at most 1024 bytes (one chunk), the only inputs pow5 hashes.
+pub fn hash(input: &[u8]) -> [u8; OUT_LEN] {
     if input.len() > CHUNK_SIZE {
         panic!("input length must be less than or equal to 1024 bytes");
     }
@@ -234,14 +246,20 @@ pub fn blake3_reference_hash(input: Vec<u8>) -> Vec<u8> {
     // for wgsl compatibility, the above function returns a u32 array. but they are mostly empty.
     // only the least significant byte is a value. thus, we need to convert this to a u8 array,
     // ignoreing the first 3 most significant bytes of each u32.
-    let mut output = Vec::new();
+    let mut output = [0u8; OUT_LEN];
     #[allow(clippy::needless_range_loop)]
-    for i in 0..arr_u32.len() {
-        output.push((arr_u32[i] & 0xFF) as u8);
+    for i in 0..OUT_LEN {
+        output[i] = (arr_u32[i] & 0xFF) as u8;
     }
     output
 }
 
+#[cfg(feature = "alloc")]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn blake3_reference_hash(input: Vec<u8>) -> Vec<u8> {
+    hash(&input).to_vec()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -264,7 +282,7 @@ mod tests {
 
         for input in inputs.iter() {
             let vec_input = input.to_vec();
-            let hash_output = blake3_reference_hash(vec_input.clone());
+            let hash_output = hash(&vec_input);
 
             let mut hasher = LibraryBlake3Hasher::new();
             hasher.update(&vec_input);
@@ -0,0 +1,871 @@
+// Signed pow5-64b challenges.
+//
+// A challenge is a 64-byte header whose last 32 bytes are chosen by the
+// server, plus a target and an expiry, authenticated with HMAC-SHA-256 under a
+// server secret. The client fills in the first 32 bytes (the nonce) and the
+// server verifies the solved header without keeping any state.
+//
+// There are two MAC encodings:
+//
+// - v1 is the original `pow.server.ts` encoding, the raw concatenation
+//   `header[32..] || target || expiresAt || sender || recipient` with absent
+//   addresses skipped. It is ambiguous: the address pairs ("ab", "c") and
+//   ("a", "bc") produce the same input, and so do a sender-only and a
+//   recipient-only challenge for the same address.
+// - v2 starts with a versioned domain-separation tag and encodes every field
+//   as tag || length || value in a fixed order, including the purpose of the
+//   challenge. Distinct challenges always have distinct MAC inputs.
+//
+// New challenges are always signed with v2. A verifier can still accept v1
+// signatures during rollout with `Compatibility::AcceptV1`.
+//
+// v2 challenges can also be signed with Ed25519 instead of HMAC (see the
+// `ed25519` module), which lets anyone holding the published public key check
+// them. Both algorithms sign the same v2 encoding, which includes the
+// algorithm, and share the checks in `ChallengeVerifier::verify`.
+//
+// Registration, login, channel and message challenges otherwise have the same
+// shape, so the verifier is told which purpose it expects. A challenge for
+// another purpose is rejected, and so is one whose target is easier than the
+// minimum difficulty for the expected purpose. Without the purpose check, a
+// login challenge solved at a high enough difficulty could be replayed against
+// the registration endpoint.
+
+use crate::difficulty::Target;
+use crate::merkle::{MerkleProof, batch_bound_header};
+use crate::message::{message_bound_header, message_commitment};
+use crate::mine::hash_64b;
+use crate::multi::MultiProof;
+use crate::replay::{SpentStore, consume};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::fmt;
+
+type HmacSha256 = Hmac<Sha256>;
+
+const NONCE_SIZE: usize = 32;
+const DOMAIN_TAG_V2: &[u8] = b"keypears pow5-64b challenge v2";
+
+// v2 field tags, in the order they appear in the encoding
+const TAG_PURPOSE: u8 = 0x01;
+const TAG_ALGORITHM: u8 = 0x02;
+const TAG_KEY_ID: u8 = 0x03;
+const TAG_CHALLENGE: u8 = 0x04;
+const TAG_TARGET: u8 = 0x05;
+const TAG_EXPIRES_AT: u8 = 0x06;
+const TAG_SENDER_ADDRESS: u8 = 0x07;
+const TAG_RECIPIENT_ADDRESS: u8 = 0x08;
+
+/// How long a challenge stays valid after it is issued, as in `pow.server.ts`.
+pub const CHALLENGE_EXPIRY_MS: u64 = 15 * 60 * 1000;
+
+/// What a challenge authorizes. Signed into every v2 challenge.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum ChallengePurpose {
+    Register = 1,
+    Login = 2,
+    Channel = 3,
+    Message = 4,
+}
+
+impl ChallengePurpose {
+    pub const fn as_byte(self) -> u8 {
+        self as u8
+    }
+
+    pub const fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            1 => Some(ChallengePurpose::Register),
+            2 => Some(ChallengePurpose::Login),
+            3 => Some(ChallengePurpose::Channel),
+            4 => Some(ChallengePurpose::Message),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ChallengePurpose {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChallengePurpose::Register => write!(f, "register"),
+            ChallengePurpose::Login => write!(f, "login"),
+            ChallengePurpose::Channel => write!(f, "channel"),
+            ChallengePurpose::Message => write!(f, "message"),
+        }
+    }
+}
+
+/// The lowest difficulty a verifier accepts for each purpose. The defaults
+/// match the constants in `pow.server.ts`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MinDifficulties {
+    pub register: u64,
+    pub login: u64,
+    pub channel: u64,
+    pub message: u64,
+}
+
+impl MinDifficulties {
+    pub const fn get(&self, purpose: ChallengePurpose) -> u64 {
+        match purpose {
+            ChallengePurpose::Register => self.register,
+            ChallengePurpose::Login => self.login,
+            ChallengePurpose::Channel => self.channel,
+            ChallengePurpose::Message => self.message,
+        }
+    }
+
+    /// Every minimum multiplied by `factor`, saturating.
+    pub const fn scaled(&self, factor: u64) -> Self {
+        MinDifficulties {
+            register: self.register.saturating_mul(factor),
+            login: self.login.saturating_mul(factor),
+            channel: self.channel.saturating_mul(factor),
+            message: self.message.saturating_mul(factor),
+        }
+    }
+}
+
+impl Default for MinDifficulties {
+    fn default() -> Self {
+        MinDifficulties {
+            register: 70_000_000,
+            login: 7_000_000,
+            channel: 7_000_000,
+            message: 7_000_000,
+        }
+    }
+}
+
+/// How a challenge is signed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum SignatureAlgorithm {
+    /// HMAC-SHA-256 under a server secret. Only the issuing server can verify.
+    HmacSha256 = 1,
+    /// Ed25519 under a published key pair. Anyone can verify.
+    Ed25519 = 2,
+}
+
+impl SignatureAlgorithm {
+    pub const fn as_byte(self) -> u8 {
+        self as u8
+    }
+
+    pub const fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            1 => Some(SignatureAlgorithm::HmacSha256),
+            2 => Some(SignatureAlgorithm::Ed25519),
+            _ => None,
+        }
+    }
+
+    /// Size of a signature in bytes.
+    pub const fn signature_size(self) -> usize {
+        match self {
+            SignatureAlgorithm::HmacSha256 => 32,
+            SignatureAlgorithm::Ed25519 => 64,
+        }
+    }
+}
+
+/// MAC encoding a challenge was signed with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ChallengeVersion {
+    V1,
+    V2,
+}
+
+/// Which MAC encodings a verifier accepts.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Compatibility {
+    /// Only accept v2 signatures.
+    #[default]
+    V2Only,
+    /// Also accept v1 signatures from servers that have not upgraded yet.
+    /// The purpose is still compared with the verifier's for v1 challenges,
+    /// but the v1 MAC does not cover it, so a client can relabel one.
+    AcceptV1,
+}
+
+/// Everything [`ChallengeVerifier::verify`] checks a solved challenge against.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VerifyOptions {
+    /// The purpose of the endpoint doing the verification.
+    pub purpose: ChallengePurpose,
+    /// Current time in milliseconds since the Unix epoch.
+    pub now: u64,
+    pub compatibility: Compatibility,
+    pub min_difficulties: MinDifficulties,
+}
+
+impl VerifyOptions {
+    pub fn new(purpose: ChallengePurpose, now: u64) -> Self {
+        VerifyOptions {
+            purpose,
+            now,
+            compatibility: Compatibility::default(),
+            min_difficulties: MinDifficulties::default(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ChallengeError {
+    Expired,
+    WrongPurpose {
+        expected: ChallengePurpose,
+        actual: ChallengePurpose,
+    },
+    DifficultyTooLow {
+        purpose: ChallengePurpose,
+        min_difficulty: u64,
+    },
+    UnknownKey(u32),
+    InvalidSignature,
+    HashDoesNotMeetTarget,
+    AlreadySpent,
+    SpentStore(String),
+}
+
+impl fmt::Display for ChallengeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChallengeError::Expired => write!(f, "Challenge expired"),
+            ChallengeError::WrongPurpose { expected, actual } => {
+                write!(f, "Challenge is for {actual}, expected {expected}")
+            }
+            ChallengeError::DifficultyTooLow {
+                purpose,
+                min_difficulty,
+            } => write!(
+                f,
+                "Difficulty is below the {purpose} minimum of {min_difficulty}"
+            ),
+            ChallengeError::UnknownKey(key_id) => write!(f, "Unknown signing key {key_id}"),
+            ChallengeError::InvalidSignature => write!(f, "Invalid signature"),
+            ChallengeError::HashDoesNotMeetTarget => write!(f, "Hash does not meet target"),
+            ChallengeError::AlreadySpent => write!(f, "Proof of work already used"),
+            ChallengeError::SpentStore(message) => write!(f, "Spent-token store failed: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for ChallengeError {}
+
+/// A pow5-64b challenge as issued by the server, or as submitted back with a
+/// solved header. Only `header[32..]` is signed; the nonce half is the
+/// client's to fill in.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Challenge {
+    pub purpose: ChallengePurpose,
+    /// Signed in v2. v1 challenges are always HMAC-SHA-256.
+    pub algorithm: SignatureAlgorithm,
+    /// Identifies the signing key in a [`KeyRing`](crate::keyring::KeyRing).
+    /// Signed in v2; v1 challenges have no key id.
+    pub key_id: u32,
+    pub header: [u8; 64],
+    pub target: Target,
+    /// Expiry in milliseconds since the Unix epoch.
+    pub expires_at: u64,
+    pub sender_address: Option<String>,
+    pub recipient_address: Option<String>,
+}
+
+impl Challenge {
+    /// The canonical v2 MAC input. Ed25519 signs the same bytes.
+    pub fn mac_input_v2(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(256);
+        out.extend_from_slice(DOMAIN_TAG_V2);
+        push_field(&mut out, TAG_PURPOSE, &[self.purpose.as_byte()]);
+        push_field(&mut out, TAG_ALGORITHM, &[self.algorithm.as_byte()]);
+        push_field(&mut out, TAG_KEY_ID, &self.key_id.to_be_bytes());
+        push_field(&mut out, TAG_CHALLENGE, &self.header[NONCE_SIZE..]);
+        push_field(&mut out, TAG_TARGET, self.target.as_bytes());
+        push_field(&mut out, TAG_EXPIRES_AT, &self.expires_at.to_be_bytes());
+        if let Some(sender) = &self.sender_address {
+            push_field(&mut out, TAG_SENDER_ADDRESS, sender.as_bytes());
+        }
+        if let Some(recipient) = &self.recipient_address {
+            push_field(&mut out, TAG_RECIPIENT_ADDRESS, recipient.as_bytes());
+        }
+        out
+    }
+
+    /// The legacy v1 MAC input, byte-for-byte what `signChallenge` in
+    /// `pow.server.ts` signs.
+    pub fn mac_input_v1(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(128);
+        out.extend_from_slice(&self.header[NONCE_SIZE..]);
+        out.extend_from_slice(self.target.as_bytes());
+        out.extend_from_slice(&self.expires_at.to_be_bytes());
+        if let Some(sender) = &self.sender_address {
+            out.extend_from_slice(sender.as_bytes());
+        }
+        if let Some(recipient) = &self.recipient_address {
+            out.extend_from_slice(recipient.as_bytes());
+        }
+        out
+    }
+
+    pub fn mac_input(&self, version: ChallengeVersion) -> Vec<u8> {
+        match version {
+            ChallengeVersion::V1 => self.mac_input_v1(),
+            ChallengeVersion::V2 => self.mac_input_v2(),
+        }
+    }
+}
+
+pub(crate) fn push_field(out: &mut Vec<u8>, tag: u8, value: &[u8]) {
+    out.push(tag);
+    out.extend_from_slice(&(value.len() as u32).to_be_bytes());
+    out.extend_from_slice(value);
+}
+
+/// Checks solved challenges. Implemented by every signing scheme, so the
+/// checks that do not depend on the signature are shared.
+pub trait ChallengeVerifier {
+    /// Check the signature of a challenge and return the encoding it was
+    /// signed with.
+    fn verify_signature(
+        &self,
+        challenge: &Challenge,
+        signature: &[u8],
+        compatibility: Compatibility,
+    ) -> Result<ChallengeVersion, ChallengeError>;
+
+    /// Verify a solved challenge: not expired, issued for the expected
+    /// purpose, at least as hard as that purpose's minimum, correctly signed,
+    /// and the pow5-64b hash of the solved header meets the target.
+    ///
+    /// The minimum difficulty is checked against the expected purpose, not
+    /// the one the challenge claims, so it also protects v1 challenges whose
+    /// purpose is not signed.
+    fn verify(
+        &self,
+        challenge: &Challenge,
+        signature: &[u8],
+        options: &VerifyOptions,
+    ) -> Result<ChallengeVersion, ChallengeError> {
+        self.verify_work(challenge, signature, options, &challenge.header)
+    }
+
+    /// Verify a solved challenge that is bound to a message (see the
+    /// `message` module): the work must have been done on the header derived
+    /// from the challenge and `ciphertext`, so it cannot be moved to another
+    /// message. Consume it with [`consume`](crate::replay::consume).
+    fn verify_message_bound(
+        &self,
+        challenge: &Challenge,
+        signature: &[u8],
+        options: &VerifyOptions,
+        ciphertext: &[u8],
+    ) -> Result<ChallengeVersion, ChallengeError> {
+        let work_header = message_bound_header(&challenge.header, ciphertext);
+        self.verify_work(challenge, signature, options, &work_header)
+    }
+
+    /// Verify one message of a batch solved with a single challenge (see the
+    /// `merkle` module): `proof` must place the message in the tree the work
+    /// was bound to, and the challenge must be at least as hard as the
+    /// per-message minimum times the batch size. Consume it with
+    /// [`consume_batch_member`](crate::replay::consume_batch_member).
+    fn verify_batch_member(
+        &self,
+        challenge: &Challenge,
+        signature: &[u8],
+        options: &VerifyOptions,
+        ciphertext: &[u8],
+        proof: &MerkleProof,
+    ) -> Result<ChallengeVersion, ChallengeError> {
+        let root = proof.root(&message_commitment(ciphertext));
+        let work_header = batch_bound_header(&challenge.header, &root, proof.leaf_count());
+        let options = VerifyOptions {
+            min_difficulties: options
+                .min_difficulties
+                .scaled(u64::from(proof.leaf_count())),
+            ..*options
+        };
+        self.verify_work(challenge, signature, &options, &work_header)
+    }
+
+    /// Verify a challenge solved as `k` independent sub-solutions (see the
+    /// `multi` module). Consume it with
+    /// [`consume_multi`](crate::replay::consume_multi).
+    fn verify_multi(
+        &self,
+        challenge: &Challenge,
+        signature: &[u8],
+        options: &VerifyOptions,
+        proof: &MultiProof,
+    ) -> Result<ChallengeVersion, ChallengeError> {
+        let version = self.verify_issued(challenge, signature, options)?;
+        if !proof.is_valid(&challenge.header, &challenge.target) {
+            return Err(ChallengeError::HashDoesNotMeetTarget);
+        }
+        Ok(version)
+    }
+
+    /// Run every check of [`verify`](Self::verify), but take the pow5-64b hash
+    /// of `work_header` instead of the challenge header.
+    fn verify_work(
+        &self,
+        challenge: &Challenge,
+        signature: &[u8],
+        options: &VerifyOptions,
+        work_header: &[u8; 64],
+    ) -> Result<ChallengeVersion, ChallengeError> {
+        let version = self.verify_issued(challenge, signature, options)?;
+        if !challenge.target.is_met_by(&hash_64b(work_header)) {
+            return Err(ChallengeError::HashDoesNotMeetTarget);
+        }
+        Ok(version)
+    }
+
+    /// Check everything about a challenge except the work: expiry, purpose,
+    /// minimum difficulty and signature.
+    fn verify_issued(
+        &self,
+        challenge: &Challenge,
+        signature: &[u8],
+        options: &VerifyOptions,
+    ) -> Result<ChallengeVersion, ChallengeError> {
+        if options.now > challenge.expires_at {
+            return Err(ChallengeError::Expired);
+        }
+
+        if challenge.purpose != options.purpose {
+            return Err(ChallengeError::WrongPurpose {
+                expected: options.purpose,
+                actual: challenge.purpose,
+            });
+        }
+
+        let min_difficulty = options.min_difficulties.get(options.purpose);
+        let min_target = Target::from_difficulty(min_difficulty.max(1)).expect("difficulty >= 1");
+        if challenge.target > min_target {
+            return Err(ChallengeError::DifficultyTooLow {
+                purpose: options.purpose,
+                min_difficulty,
+            });
+        }
+
+        self.verify_signature(challenge, signature, options.compatibility)
+    }
+
+    /// Verify a solved challenge and consume it, so the same solution is
+    /// rejected next time. Use this for every verification that gates a real
+    /// action, like `verifyAndConsumePow` in the webapp.
+    ///
+    /// The store's check-and-insert is atomic, so of two concurrent
+    /// submissions of the same solution exactly one succeeds.
+    fn verify_and_consume(
+        &self,
+        challenge: &Challenge,
+        signature: &[u8],
+        options: &VerifyOptions,
+        store: &dyn SpentStore,
+    ) -> Result<ChallengeVersion, ChallengeError> {
+        let version = self.verify(challenge, signature, options)?;
+        consume(challenge, options.now, store)?;
+        Ok(version)
+    }
+}
+
+/// The server secret used to sign and verify HMAC-SHA-256 challenges.
+pub struct ChallengeKey([u8; 32]);
+
+impl ChallengeKey {
+    pub fn new(secret: [u8; 32]) -> Self {
+        ChallengeKey(secret)
+    }
+
+    fn hmac(&self, challenge: &Challenge, version: ChallengeVersion) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(&self.0).expect("HMAC accepts any key length");
+        mac.update(&challenge.mac_input(version));
+        mac
+    }
+
+    /// Sign a challenge with the given encoding. New challenges should always
+    /// use [`ChallengeVersion::V2`]. `challenge.algorithm` must be
+    /// [`SignatureAlgorithm::HmacSha256`] for the signature to verify.
+    pub fn sign(&self, challenge: &Challenge, version: ChallengeVersion) -> [u8; 32] {
+        self.hmac(challenge, version).finalize().into_bytes().into()
+    }
+}
+
+impl ChallengeVerifier for ChallengeKey {
+    /// Compares in constant time.
+    fn verify_signature(
+        &self,
+        challenge: &Challenge,
+        signature: &[u8],
+        compatibility: Compatibility,
+    ) -> Result<ChallengeVersion, ChallengeError> {
+        if challenge.algorithm != SignatureAlgorithm::HmacSha256 {
+            return Err(ChallengeError::InvalidSignature);
+        }
+        if self
+            .hmac(challenge, ChallengeVersion::V2)
+            .verify_slice(signature)
+            .is_ok()
+        {
+            return Ok(ChallengeVersion::V2);
+        }
+        if compatibility == Compatibility::AcceptV1
+            && self
+                .hmac(challenge, ChallengeVersion::V1)
+                .verify_slice(signature)
+                .is_ok()
+        {
+            return Ok(ChallengeVersion::V1);
+        }
+        Err(ChallengeError::InvalidSignature)
+    }
+}
+
+/// Fixtures for the tests of every module that verifies challenges.
+#[cfg(test)]
+pub(crate) mod test_support {
+    use super::*;
+
+    pub(crate) const NOW: u64 = 1_760_000_000_000;
+
+    /// Options for `purpose` at `now` with every minimum difficulty at 1, so
+    /// test challenges can use targets that need little or no mining.
+    pub(crate) fn test_options(purpose: ChallengePurpose, now: u64) -> VerifyOptions {
+        VerifyOptions {
+            min_difficulties: MinDifficulties {
+                register: 1,
+                login: 1,
+                channel: 1,
+                message: 1,
+            },
+            ..VerifyOptions::new(purpose, now)
+        }
+    }
+
+    /// An HMAC challenge for `purpose` with key id 0 and no addresses. Its
+    /// target is `Target::MAX`, which any nonce meets, and it expires
+    /// `CHALLENGE_EXPIRY_MS` after `NOW`.
+    pub(crate) fn test_challenge(purpose: ChallengePurpose) -> Challenge {
+        Challenge {
+            purpose,
+            algorithm: SignatureAlgorithm::HmacSha256,
+            key_id: 0,
+            header: [0x42; 64],
+            target: Target::MAX,
+            expires_at: NOW + CHALLENGE_EXPIRY_MS,
+            sender_address: None,
+            recipient_address: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test_support::{NOW, test_options};
+    use super::*;
+
+    fn test_challenge() -> Challenge {
+        let mut header = [0u8; 64];
+        header[NONCE_SIZE..].copy_from_slice(&[0x42; 32]);
+        Challenge {
+            key_id: 3,
+            header,
+            sender_address: Some("alice@example.com".to_string()),
+            recipient_address: Some("bob@example.com".to_string()),
+            ..test_support::test_challenge(ChallengePurpose::Message)
+        }
+    }
+
+    #[test]
+    fn test_mac_input_v1_matches_pow_server() {
+        let challenge = test_challenge();
+        let mut expected = vec![0x42; 32];
+        expected.extend_from_slice(&[0xff; 32]);
+        expected.extend_from_slice(&challenge.expires_at.to_be_bytes());
+        expected.extend_from_slice(b"alice@example.com");
+        expected.extend_from_slice(b"bob@example.com");
+        assert_eq!(challenge.mac_input_v1(), expected);
+    }
+
+    #[test]
+    fn test_mac_input_v2_layout() {
+        let mut challenge = test_challenge();
+        challenge.sender_address = None;
+        challenge.recipient_address = Some("b@c".to_string());
+        let input = challenge.mac_input_v2();
+
+        let mut expected = DOMAIN_TAG_V2.to_vec();
+        expected.extend_from_slice(&[TAG_PURPOSE, 0, 0, 0, 1, 4]);
+        expected.extend_from_slice(&[TAG_ALGORITHM, 0, 0, 0, 1, 1]);
+        expected.extend_from_slice(&[TAG_KEY_ID, 0, 0, 0, 4, 0, 0, 0, 3]);
+        expected.extend_from_slice(&[TAG_CHALLENGE, 0, 0, 0, 32]);
+        expected.extend_from_slice(&[0x42; 32]);
+        expected.extend_from_slice(&[TAG_TARGET, 0, 0, 0, 32]);
+        expected.extend_from_slice(&[0xff; 32]);
+        expected.extend_from_slice(&[TAG_EXPIRES_AT, 0, 0, 0, 8]);
+        expected.extend_from_slice(&challenge.expires_at.to_be_bytes());
+        expected.extend_from_slice(&[TAG_RECIPIENT_ADDRESS, 0, 0, 0, 3]);
+        expected.extend_from_slice(b"b@c");
+        assert_eq!(input, expected);
+    }
+
+    #[test]
+    fn test_v1_is_ambiguous_and_v2_is_not() {
+        let mut a = test_challenge();
+        a.sender_address = Some("ab".to_string());
+        a.recipient_address = Some("c".to_string());
+        let mut b = test_challenge();
+        b.sender_address = Some("a".to_string());
+        b.recipient_address = Some("bc".to_string());
+        assert_eq!(a.mac_input_v1(), b.mac_input_v1());
+        assert_ne!(a.mac_input_v2(), b.mac_input_v2());
+
+        let mut sender_only = test_challenge();
+        sender_only.recipient_address = None;
+        let mut recipient_only = test_challenge();
+        recipient_only.sender_address = None;
+        recipient_only.recipient_address = sender_only.sender_address.clone();
+        assert_eq!(sender_only.mac_input_v1(), recipient_only.mac_input_v1());
+        assert_ne!(sender_only.mac_input_v2(), recipient_only.mac_input_v2());
+
+        let mut empty = test_challenge();
+        empty.sender_address = Some(String::new());
+        let mut absent = test_challenge();
+        absent.sender_address = None;
+        assert_ne!(empty.mac_input_v2(), absent.mac_input_v2());
+    }
+
+    #[test]
+    fn test_v2_binds_key_id() {
+        let a = test_challenge();
+        let b = Challenge {
+            key_id: 4,
+            ..test_challenge()
+        };
+        assert_ne!(a.mac_input_v2(), b.mac_input_v2());
+        assert_eq!(a.mac_input_v1(), b.mac_input_v1());
+    }
+
+    #[test]
+    fn test_v2_binds_algorithm() {
+        let a = test_challenge();
+        let b = Challenge {
+            algorithm: SignatureAlgorithm::Ed25519,
+            ..test_challenge()
+        };
+        assert_ne!(a.mac_input_v2(), b.mac_input_v2());
+
+        // an HMAC key does not accept a challenge labelled as Ed25519
+        let key = ChallengeKey::new([7; 32]);
+        let signature = key.sign(&b, ChallengeVersion::V2);
+        assert_eq!(
+            key.verify_signature(&b, &signature, Compatibility::V2Only),
+            Err(ChallengeError::InvalidSignature)
+        );
+    }
+
+    #[test]
+    fn test_v2_binds_purpose() {
+        let login = Challenge {
+            purpose: ChallengePurpose::Login,
+            ..test_challenge()
+        };
+        let register = Challenge {
+            purpose: ChallengePurpose::Register,
+            ..test_challenge()
+        };
+        assert_ne!(login.mac_input_v2(), register.mac_input_v2());
+        assert_eq!(login.mac_input_v1(), register.mac_input_v1());
+    }
+
+    #[test]
+    fn test_verify() {
+        let key = ChallengeKey::new([7; 32]);
+        let challenge = test_challenge();
+        let signature = key.sign(&challenge, ChallengeVersion::V2);
+
+        // the client only changes the nonce half
+        let mut solved = challenge.clone();
+        solved.header[..NONCE_SIZE].copy_from_slice(&[0x99; 32]);
+        let options = test_options(ChallengePurpose::Message, NOW);
+        assert_eq!(
+            key.verify(&solved, &signature, &options),
+            Ok(ChallengeVersion::V2)
+        );
+
+        let late = VerifyOptions {
+            now: solved.expires_at + 1,
+            ..options
+        };
+        assert_eq!(
+            key.verify(&solved, &signature, &late),
+            Err(ChallengeError::Expired)
+        );
+
+        let mut tampered = solved.clone();
+        tampered.recipient_address = Some("eve@example.com".to_string());
+        assert_eq!(
+            key.verify(&tampered, &signature, &options),
+            Err(ChallengeError::InvalidSignature)
+        );
+
+        let other_key = ChallengeKey::new([8; 32]);
+        assert_eq!(
+            other_key.verify(&solved, &signature, &options),
+            Err(ChallengeError::InvalidSignature)
+        );
+    }
+
+    #[test]
+    fn test_verify_hash_does_not_meet_target() {
+        let key = ChallengeKey::new([7; 32]);
+        let challenge = Challenge {
+            target: Target::from_bytes([0; 32]),
+            ..test_challenge()
+        };
+        let signature = key.sign(&challenge, ChallengeVersion::V2);
+        assert_eq!(
+            key.verify(
+                &challenge,
+                &signature,
+                &test_options(ChallengePurpose::Message, NOW)
+            ),
+            Err(ChallengeError::HashDoesNotMeetTarget)
+        );
+    }
+
+    #[test]
+    fn test_verify_v1_compatibility() {
+        let key = ChallengeKey::new([7; 32]);
+        let challenge = test_challenge();
+        let signature = key.sign(&challenge, ChallengeVersion::V1);
+        let options = test_options(ChallengePurpose::Message, NOW);
+        assert_eq!(
+            key.verify(&challenge, &signature, &options),
+            Err(ChallengeError::InvalidSignature)
+        );
+        let compat = VerifyOptions {
+            compatibility: Compatibility::AcceptV1,
+            ..options
+        };
+        assert_eq!(
+            key.verify(&challenge, &signature, &compat),
+            Ok(ChallengeVersion::V1)
+        );
+    }
+
+    #[test]
+    fn test_verify_v1_still_checks_purpose() {
+        let key = ChallengeKey::new([7; 32]);
+        let login = Challenge {
+            purpose: ChallengePurpose::Login,
+            ..test_challenge()
+        };
+        let signature = key.sign(&login, ChallengeVersion::V1);
+        let compat = |purpose| VerifyOptions {
+            compatibility: Compatibility::AcceptV1,
+            ..test_options(purpose, NOW)
+        };
+        assert_eq!(
+            key.verify(&login, &signature, &compat(ChallengePurpose::Register)),
+            Err(ChallengeError::WrongPurpose {
+                expected: ChallengePurpose::Register,
+                actual: ChallengePurpose::Login,
+            })
+        );
+
+        // the v1 MAC does not cover the purpose, so a relabelled one passes
+        let relabelled = Challenge {
+            purpose: ChallengePurpose::Register,
+            ..login
+        };
+        assert_eq!(
+            key.verify(&relabelled, &signature, &compat(ChallengePurpose::Register)),
+            Ok(ChallengeVersion::V1)
+        );
+    }
+
+    #[test]
+    fn test_verify_rejects_other_purpose() {
+        let key = ChallengeKey::new([7; 32]);
+        let login = Challenge {
+            purpose: ChallengePurpose::Login,
+            ..test_challenge()
+        };
+        let signature = key.sign(&login, ChallengeVersion::V2);
+        assert_eq!(
+            key.verify(
+                &login,
+                &signature,
+                &test_options(ChallengePurpose::Login, NOW)
+            ),
+            Ok(ChallengeVersion::V2)
+        );
+        assert_eq!(
+            key.verify(
+                &login,
+                &signature,
+                &test_options(ChallengePurpose::Register, NOW)
+            ),
+            Err(ChallengeError::WrongPurpose {
+                expected: ChallengePurpose::Register,
+                actual: ChallengePurpose::Login,
+            })
+        );
+
+        // relabelling the challenge breaks the v2 signature
+        let relabelled = Challenge {
+            purpose: ChallengePurpose::Register,
+            ..login
+        };
+        assert_eq!(
+            key.verify(
+                &relabelled,
+                &signature,
+                &test_options(ChallengePurpose::Register, NOW)
+            ),
+            Err(ChallengeError::InvalidSignature)
+        );
+    }
+
+    #[test]
+    fn test_verify_min_difficulty() {
+        let key = ChallengeKey::new([7; 32]);
+        let challenge = Challenge {
+            purpose: ChallengePurpose::Login,
+            target: Target::from_difficulty(6_999_999).unwrap(),
+            ..test_challenge()
+        };
+        let signature = key.sign(&challenge, ChallengeVersion::V2);
+        assert_eq!(
+            key.verify(
+                &challenge,
+                &signature,
+                &VerifyOptions::new(ChallengePurpose::Login, NOW)
+            ),
+            Err(ChallengeError::DifficultyTooLow {
+                purpose: ChallengePurpose::Login,
+                min_difficulty: 7_000_000,
+            })
+        );
+
+        // exactly the minimum passes the floor and fails on the hash instead
+        let challenge = Challenge {
+            target: Target::from_difficulty(7_000_000).unwrap(),
+            ..challenge
+        };
+        let signature = key.sign(&challenge, ChallengeVersion::V2);
+        assert_eq!(
+            key.verify(
+                &challenge,
+                &signature,
+                &VerifyOptions::new(ChallengePurpose::Login, NOW)
+            ),
+            Err(ChallengeError::HashDoesNotMeetTarget)
+        );
+    }
+}
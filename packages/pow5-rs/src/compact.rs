@@ -0,0 +1,200 @@
+// Compact 4-byte target encoding, in the style of Bitcoin's nBits.
+//
+// A compact target is a u32: the high byte is the exponent e, the length of
+// the target in bytes (0 to 32), and the low 24 bits are the mantissa m, its
+// three most significant bytes. The target it stands for is
+//
+//   m * 256^(e - 3)      if e >= 3
+//   m / 256^(3 - e)      if e < 3 (the bytes shifted out must be zero)
+//
+// Unlike nBits there is no sign bit, since targets are unsigned, so the
+// mantissa has a full 24 bits. Every target has exactly one encoding: the
+// mantissa's top byte is nonzero (the exponent is as small as it can be), and
+// zero is only ever 0x00000000. Decoding rejects anything else, so a compact
+// target on the wire has one meaning.
+//
+// Encoding keeps the three most significant bytes and rounds the rest down,
+// so the compact target is never easier than the target it came from. Decoding
+// is exact, and encoding a decoded target gives back the same compact value.
+
+use crate::difficulty::{Target, ZeroDifficulty};
+use core::fmt;
+
+const MANTISSA_BYTES: usize = 3;
+const MANTISSA_MASK: u32 = 0x00ff_ffff;
+
+/// A target in 4 bytes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct CompactTarget(u32);
+
+impl CompactTarget {
+    /// Parse a compact target, rejecting encodings that are not canonical.
+    pub fn from_u32(bits: u32) -> Result<Self, CompactError> {
+        let exponent = (bits >> 24) as usize;
+        let mantissa = bits & MANTISSA_MASK;
+        if exponent > 32 {
+            return Err(CompactError::ExponentTooLarge(exponent as u8));
+        }
+        if mantissa == 0 {
+            if exponent != 0 {
+                return Err(CompactError::NonCanonical(bits));
+            }
+            return Ok(CompactTarget(0));
+        }
+        let dropped_bits = 8 * MANTISSA_BYTES.saturating_sub(exponent);
+        if mantissa >> 16 == 0 || mantissa & ((1 << dropped_bits) - 1) != 0 {
+            return Err(CompactError::NonCanonical(bits));
+        }
+        Ok(CompactTarget(bits))
+    }
+
+    pub fn to_u32(self) -> u32 {
+        self.0
+    }
+
+    pub fn from_be_bytes(bytes: [u8; 4]) -> Result<Self, CompactError> {
+        CompactTarget::from_u32(u32::from_be_bytes(bytes))
+    }
+
+    pub fn to_be_bytes(self) -> [u8; 4] {
+        self.0.to_be_bytes()
+    }
+
+    /// The closest compact target that is not easier than `target`.
+    pub fn from_target(target: &Target) -> Self {
+        let bytes = target.as_bytes();
+        let Some(first) = bytes.iter().position(|&b| b != 0) else {
+            return CompactTarget(0);
+        };
+        let exponent = 32 - first;
+        let mut mantissa = [0u8; 4];
+        for (i, byte) in bytes[first..].iter().take(MANTISSA_BYTES).enumerate() {
+            mantissa[1 + i] = *byte;
+        }
+        CompactTarget(((exponent as u32) << 24) | u32::from_be_bytes(mantissa))
+    }
+
+    /// The target this stands for, exactly.
+    pub fn to_target(self) -> Target {
+        let exponent = (self.0 >> 24) as usize;
+        let mantissa = (self.0 & MANTISSA_MASK).to_be_bytes();
+        let mut bytes = [0u8; 32];
+        for (i, byte) in mantissa[1..].iter().enumerate() {
+            // position of this mantissa byte in the 32-byte target
+            let position = (32 + i) as isize - exponent as isize;
+            if (0..32).contains(&position) {
+                bytes[position as usize] = *byte;
+            }
+        }
+        Target::from_bytes(bytes)
+    }
+
+    /// The compact target for a difficulty, rounded to be no easier.
+    pub fn from_difficulty(difficulty: u64) -> Result<Self, ZeroDifficulty> {
+        let target = Target::from_difficulty(difficulty)?;
+        Ok(CompactTarget::from_target(&target))
+    }
+}
+
+impl fmt::Display for CompactTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:08x}", self.0)
+    }
+}
+
+/// Why a compact target was rejected.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CompactError {
+    /// The exponent byte, which is more than 32.
+    ExponentTooLarge(u8),
+    /// The full compact value.
+    NonCanonical(u32),
+}
+
+impl fmt::Display for CompactError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompactError::ExponentTooLarge(exponent) => {
+                write!(f, "compact target exponent {} exceeds 32", exponent)
+            }
+            CompactError::NonCanonical(bits) => {
+                write!(f, "non-canonical compact target {:#010x}", bits)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn target(hex_str: &str) -> Target {
+        let mut bytes = [0u8; 32];
+        hex::decode_to_slice(hex_str, &mut bytes).unwrap();
+        Target::from_bytes(bytes)
+    }
+
+    #[test]
+    fn test_from_target() {
+        // the login difficulty in pow.server.ts
+        let login = Target::from_difficulty(7_000_000).unwrap();
+        let compact = CompactTarget::from_target(&login);
+        assert_eq!(compact.to_u32(), 0x1e02_6591);
+        assert_eq!(
+            hex::encode(compact.to_target().as_bytes()),
+            "0000026591000000000000000000000000000000000000000000000000000000"
+        );
+        // rounded down, never easier
+        assert!(compact.to_target() <= login);
+
+        assert_eq!(
+            CompactTarget::from_target(&Target::MAX).to_u32(),
+            0x20ff_ffff
+        );
+        assert_eq!(
+            CompactTarget::from_target(&Target::from_bytes([0; 32])).to_u32(),
+            0
+        );
+
+        let small = target("0000000000000000000000000000000000000000000000000000000000001234");
+        assert_eq!(CompactTarget::from_target(&small).to_u32(), 0x0212_3400);
+        assert_eq!(CompactTarget::from_target(&small).to_target(), small);
+    }
+
+    #[test]
+    fn test_round_trip_is_exact() {
+        for difficulty in [1, 2, 3, 1000, 7_000_000, 70_000_000, u64::MAX] {
+            let compact = CompactTarget::from_difficulty(difficulty).unwrap();
+            let decoded = compact.to_target();
+            assert_eq!(CompactTarget::from_target(&decoded), compact);
+            assert_eq!(CompactTarget::from_u32(compact.to_u32()), Ok(compact));
+            assert_eq!(
+                CompactTarget::from_be_bytes(compact.to_be_bytes()),
+                Ok(compact)
+            );
+        }
+        assert!(CompactTarget::from_difficulty(0).is_err());
+    }
+
+    #[test]
+    fn test_rejects_non_canonical() {
+        for bits in [
+            0x2101_0000, // exponent too large
+            0x0100_0000, // zero with a nonzero exponent
+            0x1e00_6591, // mantissa not normalized
+            0x0212_3456, // bytes shifted out are not zero
+            0x0112_3400,
+        ] {
+            assert!(CompactTarget::from_u32(bits).is_err(), "{:#010x}", bits);
+        }
+        for bits in [0, 0x0112_0000, 0x0312_3456, 0x20ff_ffff] {
+            assert!(CompactTarget::from_u32(bits).is_ok(), "{:#010x}", bits);
+        }
+    }
+
+    #[test]
+    fn test_display() {
+        let compact = CompactTarget::from_difficulty(7_000_000).unwrap();
+        assert_eq!(compact.to_string(), "1e026591");
+    }
+}
@@ -0,0 +1,278 @@
+// serde types for the PoW parts of the client contract.
+//
+// These match the zod schemas in `packages/client/src/contract.ts` and
+// `webapp/src/server/schemas.ts` field for field: camelCase names, lowercase
+// hex for byte strings (either case is accepted, as with the zod regex), plain
+// JSON numbers for `expiresAt` and `difficulty`, and optional addresses that
+// are omitted rather than null.
+//
+// The contract does not carry a purpose, algorithm or key id. JSON challenges
+// are always HMAC-SHA-256, and the purpose and key id come from the endpoint
+// that receives them.
+
+use crate::challenge::{Challenge, ChallengePurpose, SignatureAlgorithm};
+use crate::difficulty::Target;
+use serde::{Deserialize, Serialize};
+
+/// The output of `getPowChallenge`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PowChallenge {
+    #[serde(with = "hex_bytes")]
+    pub header: [u8; 64],
+    #[serde(with = "hex_bytes")]
+    pub target: [u8; 32],
+    pub expires_at: u64,
+    pub difficulty: u64,
+    #[serde(with = "hex_bytes")]
+    pub signature: [u8; 32],
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "address::deserialize"
+    )]
+    pub sender_address: Option<String>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "address::deserialize"
+    )]
+    pub recipient_address: Option<String>,
+}
+
+impl PowChallenge {
+    /// The JSON form of a challenge signed with `signature`. `difficulty` is
+    /// informational and must match the target.
+    pub fn from_challenge(challenge: &Challenge, signature: [u8; 32], difficulty: u64) -> Self {
+        PowChallenge {
+            header: challenge.header,
+            target: challenge.target.to_bytes(),
+            expires_at: challenge.expires_at,
+            difficulty,
+            signature,
+            sender_address: challenge.sender_address.clone(),
+            recipient_address: challenge.recipient_address.clone(),
+        }
+    }
+
+    pub fn to_challenge(&self, purpose: ChallengePurpose, key_id: u32) -> Challenge {
+        Challenge {
+            purpose,
+            algorithm: SignatureAlgorithm::HmacSha256,
+            key_id,
+            header: self.header,
+            target: Target::from_bytes(self.target),
+            expires_at: self.expires_at,
+            sender_address: self.sender_address.clone(),
+            recipient_address: self.recipient_address.clone(),
+        }
+    }
+}
+
+/// The `pow` object submitted with a solved challenge (`PowSolutionSchema`).
+/// `notifyMessage` sends it without the addresses.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PowSolution {
+    #[serde(with = "hex_bytes")]
+    pub solved_header: [u8; 64],
+    #[serde(with = "hex_bytes")]
+    pub target: [u8; 32],
+    pub expires_at: u64,
+    #[serde(with = "hex_bytes")]
+    pub signature: [u8; 32],
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "address::deserialize"
+    )]
+    pub sender_address: Option<String>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "address::deserialize"
+    )]
+    pub recipient_address: Option<String>,
+}
+
+impl PowSolution {
+    /// The challenge to verify `self.signature` against.
+    pub fn to_challenge(&self, purpose: ChallengePurpose, key_id: u32) -> Challenge {
+        Challenge {
+            purpose,
+            algorithm: SignatureAlgorithm::HmacSha256,
+            key_id,
+            header: self.solved_header,
+            target: Target::from_bytes(self.target),
+            expires_at: self.expires_at,
+            sender_address: self.sender_address.clone(),
+            recipient_address: self.recipient_address.clone(),
+        }
+    }
+}
+
+/// Whether `address` matches `addressSchema`: `^[a-z][a-z0-9]*@[a-z0-9.-]+$`.
+pub fn is_valid_address(address: &str) -> bool {
+    let Some((name, domain)) = address.split_once('@') else {
+        return false;
+    };
+    let mut name = name.bytes();
+    matches!(name.next(), Some(b'a'..=b'z'))
+        && name.all(|b| b.is_ascii_lowercase() || b.is_ascii_digit())
+        && !domain.is_empty()
+        && domain
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'.' || b == b'-')
+}
+
+mod hex_bytes {
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer, const N: usize>(
+        bytes: &[u8; N],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&hex::encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>, const N: usize>(
+        deserializer: D,
+    ) -> Result<[u8; N], D::Error> {
+        let hex_str = String::deserialize(deserializer)?;
+        if hex_str.len() != N * 2 {
+            return Err(D::Error::custom(format!(
+                "expected {} hex characters, got {}",
+                N * 2,
+                hex_str.len()
+            )));
+        }
+        let mut bytes = [0u8; N];
+        hex::decode_to_slice(&hex_str, &mut bytes).map_err(D::Error::custom)?;
+        Ok(bytes)
+    }
+}
+
+mod address {
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer};
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<String>, D::Error> {
+        let address = String::deserialize(deserializer)?;
+        if !super::is_valid_address(&address) {
+            return Err(D::Error::custom(format!("invalid address: {}", address)));
+        }
+        Ok(Some(address))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::challenge::{ChallengeKey, ChallengeVerifier, ChallengeVersion, Compatibility};
+
+    // Produced by the `createPowChallenge` logic in `pow.server.ts` with a pow
+    // signing key of 32 0x07 bytes and a fixed header, then JSON.stringify.
+    const CHALLENGE_JSON: &str = r#"{"header":"0b30557a9fc4e90e33587da2c7ec11365b80a5caef14395e83a8cdf2173c6186abd0f51a3f6489aed3f81d42678cb1d6fb20456a8fb4d9fe23486d92b7dc0126","target":"000002659116f56b142c62f7fe2f080c9a04dab65d0011605be673485241cd85","expiresAt":1760000900000,"difficulty":7000000,"signature":"7ae46771322b83438088ba69869c8742867b7dd35d8f56d20cd7918cc77c1462","senderAddress":"alice@keypears.com","recipientAddress":"bob@example.com"}"#;
+
+    // The same challenge issued without addresses; undefined fields are
+    // dropped by JSON.stringify.
+    const CHALLENGE_JSON_NO_ADDRESSES: &str = r#"{"header":"0b30557a9fc4e90e33587da2c7ec11365b80a5caef14395e83a8cdf2173c6186abd0f51a3f6489aed3f81d42678cb1d6fb20456a8fb4d9fe23486d92b7dc0126","target":"000002659116f56b142c62f7fe2f080c9a04dab65d0011605be673485241cd85","expiresAt":1760000900000,"difficulty":7000000,"signature":"96b831d239d642f1cb50c956de9c48c39a39562c46864404823c004f40c7c5fd"}"#;
+
+    // The first challenge submitted back with a nonce filled in. The nonce is
+    // not a real solution; only the signature is checked here.
+    const SOLUTION_JSON: &str = r#"{"solvedHeader":"ffffffffffffffffffffffffffffffffffffffffffffffffffffffff0012d687abd0f51a3f6489aed3f81d42678cb1d6fb20456a8fb4d9fe23486d92b7dc0126","target":"000002659116f56b142c62f7fe2f080c9a04dab65d0011605be673485241cd85","expiresAt":1760000900000,"signature":"7ae46771322b83438088ba69869c8742867b7dd35d8f56d20cd7918cc77c1462","senderAddress":"alice@keypears.com","recipientAddress":"bob@example.com"}"#;
+
+    #[test]
+    fn test_challenge_round_trip() {
+        for json in [CHALLENGE_JSON, CHALLENGE_JSON_NO_ADDRESSES] {
+            let challenge: PowChallenge = serde_json::from_str(json).unwrap();
+            assert_eq!(serde_json::to_string(&challenge).unwrap(), json);
+        }
+
+        let challenge: PowChallenge = serde_json::from_str(CHALLENGE_JSON).unwrap();
+        assert_eq!(challenge.expires_at, 1_760_000_900_000);
+        assert_eq!(challenge.difficulty, 7_000_000);
+        assert_eq!(
+            Target::from_bytes(challenge.target),
+            Target::from_difficulty(7_000_000).unwrap()
+        );
+        assert_eq!(
+            challenge.sender_address.as_deref(),
+            Some("alice@keypears.com")
+        );
+    }
+
+    #[test]
+    fn test_ts_signature_verifies_as_v1() {
+        let key = ChallengeKey::new([7; 32]);
+        for json in [CHALLENGE_JSON, CHALLENGE_JSON_NO_ADDRESSES] {
+            let pow: PowChallenge = serde_json::from_str(json).unwrap();
+            let challenge = pow.to_challenge(ChallengePurpose::Message, 0);
+            assert_eq!(
+                key.verify_signature(&challenge, &pow.signature, Compatibility::AcceptV1),
+                Ok(ChallengeVersion::V1)
+            );
+        }
+
+        let solution: PowSolution = serde_json::from_str(SOLUTION_JSON).unwrap();
+        let challenge = solution.to_challenge(ChallengePurpose::Message, 0);
+        assert_eq!(
+            key.verify_signature(&challenge, &solution.signature, Compatibility::AcceptV1),
+            Ok(ChallengeVersion::V1)
+        );
+    }
+
+    #[test]
+    fn test_solution_round_trip() {
+        let solution: PowSolution = serde_json::from_str(SOLUTION_JSON).unwrap();
+        assert_eq!(serde_json::to_string(&solution).unwrap(), SOLUTION_JSON);
+
+        // notifyMessage sends the pow object without addresses
+        let json = format!(
+            r#"{{"solvedHeader":"{}","target":"{}","expiresAt":1,"signature":"{}"}}"#,
+            "00".repeat(64),
+            "ff".repeat(32),
+            "00".repeat(32)
+        );
+        let solution: PowSolution = serde_json::from_str(&json).unwrap();
+        assert_eq!(solution.sender_address, None);
+        assert_eq!(serde_json::to_string(&solution).unwrap(), json);
+    }
+
+    #[test]
+    fn test_from_challenge() {
+        let pow: PowChallenge = serde_json::from_str(CHALLENGE_JSON).unwrap();
+        let challenge = pow.to_challenge(ChallengePurpose::Channel, 0);
+        assert_eq!(
+            PowChallenge::from_challenge(&challenge, pow.signature, pow.difficulty),
+            pow
+        );
+    }
+
+    #[test]
+    fn test_rejects_what_zod_rejects() {
+        let uppercase = CHALLENGE_JSON.replace("0b30557a9fc4", "0B30557A9FC4");
+        assert!(serde_json::from_str::<PowChallenge>(&uppercase).is_ok());
+
+        let cases = [
+            CHALLENGE_JSON.replace("\"header\":\"0b", "\"header\":\""),
+            CHALLENGE_JSON.replace("\"header\":\"0b", "\"header\":\"0bab"),
+            CHALLENGE_JSON.replace("\"header\":\"0b", "\"header\":\"zz"),
+            CHALLENGE_JSON.replace("alice@keypears.com", "Alice@keypears.com"),
+            CHALLENGE_JSON.replace("alice@keypears.com", "1alice@keypears.com"),
+            CHALLENGE_JSON.replace("alice@keypears.com", "alice@"),
+            CHALLENGE_JSON.replace("alice@keypears.com", "alice"),
+            CHALLENGE_JSON.replace(",\"expiresAt\":1760000900000", ""),
+        ];
+        for json in cases {
+            assert!(
+                serde_json::from_str::<PowChallenge>(&json).is_err(),
+                "{}",
+                json
+            );
+        }
+    }
+}
@@ -0,0 +1,139 @@
+// Difficulty and target math. This mirrors `difficulty.ts` in pow5-ts so the
+// same difficulty always produces the same 32-byte target on both sides.
+
+use core::fmt;
+
+/// A 256-bit proof-of-work target in big-endian format.
+///
+/// A hash is valid if it is strictly less than the target. Because the bytes
+/// are big-endian, the derived ordering is the numeric ordering: a smaller
+/// target is a harder target.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Target([u8; 32]);
+
+impl Target {
+    /// The easiest possible target, 2^256 - 1 (difficulty 1).
+    pub const MAX: Target = Target([0xff; 32]);
+
+    pub const fn from_bytes(bytes: [u8; 32]) -> Self {
+        Target(bytes)
+    }
+
+    pub const fn to_bytes(self) -> [u8; 32] {
+        self.0
+    }
+
+    pub const fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+
+    /// Convert a difficulty to a target: `target = MAX_TARGET / difficulty`.
+    /// Higher difficulty = lower target = harder to mine.
+    pub fn from_difficulty(difficulty: u64) -> Result<Self, ZeroDifficulty> {
+        if difficulty == 0 {
+            return Err(ZeroDifficulty);
+        }
+        // long division of 2^256 - 1 by a u64, one byte at a time
+        let divisor = difficulty as u128;
+        let mut remainder: u128 = 0;
+        let mut target = [0u8; 32];
+        for byte in target.iter_mut() {
+            let dividend = (remainder << 8) | 0xff;
+            *byte = (dividend / divisor) as u8;
+            remainder = dividend % divisor;
+        }
+        Ok(Target(target))
+    }
+
+    /// The target `factor` times easier, capped at [`Target::MAX`]. Splitting
+    /// a proof into `k` parts gives each part the target times `k`.
+    pub fn saturating_mul(self, factor: u64) -> Self {
+        let factor = factor as u128;
+        let mut carry: u128 = 0;
+        let mut product = [0u8; 32];
+        for (out, byte) in product.iter_mut().zip(self.0).rev() {
+            let value = byte as u128 * factor + carry;
+            *out = value as u8;
+            carry = value >> 8;
+        }
+        if carry != 0 {
+            return Target::MAX;
+        }
+        Target(product)
+    }
+
+    /// Check if a hash meets the target (hash < target).
+    pub fn is_met_by(&self, hash: &[u8; 32]) -> bool {
+        hash < &self.0
+    }
+}
+
+/// A difficulty of 0, which has no target.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ZeroDifficulty;
+
+impl fmt::Display for ZeroDifficulty {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "difficulty must be greater than 0")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_target_from_difficulty() {
+        assert_eq!(Target::from_difficulty(1).unwrap(), Target::MAX);
+
+        let target = Target::from_difficulty(2).unwrap();
+        assert_eq!(
+            hex::encode(target.as_bytes()),
+            "7fffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff"
+        );
+
+        // the login difficulty in pow.server.ts
+        let target = Target::from_difficulty(7_000_000).unwrap();
+        assert_eq!(
+            hex::encode(target.as_bytes()),
+            "000002659116f56b142c62f7fe2f080c9a04dab65d0011605be673485241cd85"
+        );
+
+        assert!(Target::from_difficulty(0).is_err());
+    }
+
+    #[test]
+    fn test_is_met_by() {
+        let target = Target::from_difficulty(256).unwrap();
+        let mut hash = [0u8; 32];
+        assert!(target.is_met_by(&hash));
+        hash[0] = 0x01;
+        assert!(!target.is_met_by(&hash));
+        // equal to the target is not enough
+        assert!(!target.is_met_by(target.as_bytes()));
+    }
+
+    #[test]
+    fn test_saturating_mul() {
+        let target = Target::from_difficulty(256).unwrap();
+        assert_eq!(target.saturating_mul(1), target);
+        assert_eq!(
+            hex::encode(target.saturating_mul(16).as_bytes()),
+            "0ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff0"
+        );
+        assert_eq!(
+            hex::encode(target.saturating_mul(256).as_bytes()),
+            "ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff00"
+        );
+        assert_eq!(target.saturating_mul(257), Target::MAX);
+        assert_eq!(Target::MAX.saturating_mul(2), Target::MAX);
+        assert_eq!(target.saturating_mul(0), Target::from_bytes([0; 32]));
+    }
+
+    #[test]
+    fn test_harder_target_orders_lower() {
+        let easy = Target::from_difficulty(7_000_000).unwrap();
+        let hard = Target::from_difficulty(70_000_000).unwrap();
+        assert!(hard < easy);
+    }
+}
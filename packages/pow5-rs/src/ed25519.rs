@@ -0,0 +1,209 @@
+// Publicly verifiable challenges signed with Ed25519.
+//
+// HMAC challenges can only be checked by the server that issued them. In
+// federation, a recipient's domain may need to prove to a third party (an
+// abuse desk, or the sender's server) that work was done against its own
+// challenge. An Ed25519 challenge is signed over the same v2 encoding as an
+// HMAC challenge, and anyone with the domain's published public key can verify
+// it through the same `ChallengeVerifier` API.
+//
+// There is no v1 encoding for Ed25519, so `Compatibility` has no effect here.
+
+use crate::challenge::{Challenge, ChallengeError, ChallengeVerifier, ChallengeVersion};
+use crate::challenge::{Compatibility, SignatureAlgorithm};
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+
+/// A domain's Ed25519 challenge signing key.
+pub struct Ed25519Signer {
+    key_id: u32,
+    signing_key: SigningKey,
+}
+
+impl Ed25519Signer {
+    pub fn new(key_id: u32, secret: [u8; 32]) -> Self {
+        Ed25519Signer {
+            key_id,
+            signing_key: SigningKey::from_bytes(&secret),
+        }
+    }
+
+    pub fn key_id(&self) -> u32 {
+        self.key_id
+    }
+
+    /// The public half, to publish alongside the key id.
+    pub fn verifier(&self) -> Ed25519Verifier {
+        Ed25519Verifier {
+            key_id: self.key_id,
+            verifying_key: self.signing_key.verifying_key(),
+        }
+    }
+
+    /// Stamp the algorithm and key id into `challenge` and sign it with v2.
+    pub fn sign(&self, challenge: &mut Challenge) -> [u8; 64] {
+        challenge.algorithm = SignatureAlgorithm::Ed25519;
+        challenge.key_id = self.key_id;
+        self.signing_key.sign(&challenge.mac_input_v2()).to_bytes()
+    }
+}
+
+impl ChallengeVerifier for Ed25519Signer {
+    fn verify_signature(
+        &self,
+        challenge: &Challenge,
+        signature: &[u8],
+        compatibility: Compatibility,
+    ) -> Result<ChallengeVersion, ChallengeError> {
+        self.verifier()
+            .verify_signature(challenge, signature, compatibility)
+    }
+}
+
+/// A published Ed25519 challenge key. Verifies challenges without any secret.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Ed25519Verifier {
+    key_id: u32,
+    verifying_key: VerifyingKey,
+}
+
+impl Ed25519Verifier {
+    pub fn from_bytes(key_id: u32, public_key: &[u8; 32]) -> Result<Self, String> {
+        let verifying_key = VerifyingKey::from_bytes(public_key)
+            .map_err(|_| "invalid Ed25519 public key".to_string())?;
+        Ok(Ed25519Verifier {
+            key_id,
+            verifying_key,
+        })
+    }
+
+    pub fn key_id(&self) -> u32 {
+        self.key_id
+    }
+
+    pub fn to_bytes(&self) -> [u8; 32] {
+        self.verifying_key.to_bytes()
+    }
+}
+
+impl ChallengeVerifier for Ed25519Verifier {
+    /// Uses strict verification, so a signature has exactly one valid
+    /// encoding and cannot be mutated into another that also verifies.
+    fn verify_signature(
+        &self,
+        challenge: &Challenge,
+        signature: &[u8],
+        _compatibility: Compatibility,
+    ) -> Result<ChallengeVersion, ChallengeError> {
+        if challenge.algorithm != SignatureAlgorithm::Ed25519 {
+            return Err(ChallengeError::InvalidSignature);
+        }
+        if challenge.key_id != self.key_id {
+            return Err(ChallengeError::UnknownKey(challenge.key_id));
+        }
+        let signature =
+            Signature::from_slice(signature).map_err(|_| ChallengeError::InvalidSignature)?;
+        self.verifying_key
+            .verify_strict(&challenge.mac_input_v2(), &signature)
+            .map_err(|_| ChallengeError::InvalidSignature)?;
+        Ok(ChallengeVersion::V2)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::challenge::test_support::{self, NOW};
+    use crate::challenge::{ChallengeKey, ChallengePurpose, VerifyOptions};
+
+    fn test_challenge() -> Challenge {
+        Challenge {
+            sender_address: Some("alice@example.com".to_string()),
+            recipient_address: Some("bob@example.org".to_string()),
+            ..test_support::test_challenge(ChallengePurpose::Channel)
+        }
+    }
+
+    fn test_options() -> VerifyOptions {
+        test_support::test_options(ChallengePurpose::Channel, NOW)
+    }
+
+    #[test]
+    fn test_third_party_verifies_with_public_key() {
+        let signer = Ed25519Signer::new(9, [5; 32]);
+        let mut challenge = test_challenge();
+        let signature = signer.sign(&mut challenge);
+        assert_eq!(challenge.algorithm, SignatureAlgorithm::Ed25519);
+        assert_eq!(challenge.key_id, 9);
+
+        // the third party only has the published key id and public key
+        let published = signer.verifier().to_bytes();
+        let verifier = Ed25519Verifier::from_bytes(9, &published).unwrap();
+        assert_eq!(
+            verifier.verify(&challenge, &signature, &test_options()),
+            Ok(ChallengeVersion::V2)
+        );
+        assert_eq!(
+            signer.verify(&challenge, &signature, &test_options()),
+            Ok(ChallengeVersion::V2)
+        );
+    }
+
+    #[test]
+    fn test_rejects_tampered_challenge() {
+        let signer = Ed25519Signer::new(9, [5; 32]);
+        let verifier = signer.verifier();
+        let mut challenge = test_challenge();
+        let signature = signer.sign(&mut challenge);
+
+        let mut tampered = challenge.clone();
+        tampered.sender_address = Some("mallory@example.com".to_string());
+        assert_eq!(
+            verifier.verify(&tampered, &signature, &test_options()),
+            Err(ChallengeError::InvalidSignature)
+        );
+
+        let mut bad_signature = signature;
+        bad_signature[0] ^= 1;
+        assert_eq!(
+            verifier.verify(&challenge, &bad_signature, &test_options()),
+            Err(ChallengeError::InvalidSignature)
+        );
+
+        assert_eq!(
+            verifier.verify(&challenge, &signature[..32], &test_options()),
+            Err(ChallengeError::InvalidSignature)
+        );
+
+        let other = Ed25519Signer::new(10, [6; 32]).verifier();
+        assert_eq!(
+            other.verify(&challenge, &signature, &test_options()),
+            Err(ChallengeError::UnknownKey(9))
+        );
+    }
+
+    #[test]
+    fn test_algorithms_do_not_cross_verify() {
+        let hmac_key = ChallengeKey::new([5; 32]);
+        let mut challenge = test_challenge();
+        let hmac_signature = hmac_key.sign(&challenge, ChallengeVersion::V2);
+        let verifier = Ed25519Signer::new(0, [5; 32]).verifier();
+        assert_eq!(
+            verifier.verify(&challenge, &hmac_signature, &test_options()),
+            Err(ChallengeError::InvalidSignature)
+        );
+
+        let signature = Ed25519Signer::new(0, [5; 32]).sign(&mut challenge);
+        assert_eq!(
+            hmac_key.verify(&challenge, &signature, &test_options()),
+            Err(ChallengeError::InvalidSignature)
+        );
+    }
+
+    #[test]
+    fn test_rejects_invalid_public_key() {
+        // y = 2 has no matching x on the curve
+        let mut bytes = [0u8; 32];
+        bytes[0] = 2;
+        assert!(Ed25519Verifier::from_bytes(0, &bytes).is_err());
+    }
+}
@@ -0,0 +1,339 @@
+// C ABI for servers that are not written in JavaScript.
+//
+// Every function takes fixed-size buffers by pointer and returns a
+// `Pow5Status`. Sizes are implied by the function name: headers are 64 or 217
+// bytes, hashes and targets 32. Nothing is allocated, so there is nothing for
+// the caller to free. `include/keypears_pow5.h` is generated from this file by
+// `cbindgen.zsh`; regenerate it after changing anything here.
+
+use crate::compact::CompactTarget;
+use crate::difficulty::Target;
+use crate::mine::{hash_64b, mine_64b_with_progress};
+use crate::pow::elementary_iteration_217a;
+use crate::{HASH_SIZE, HEADER_SIZE_64B, HEADER_SIZE_217A};
+use std::ffi::c_void;
+
+/// The result of every call.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Pow5Status {
+    Ok = 0,
+    /// A required pointer was null.
+    NullPointer = 1,
+    /// The input was not valid, e.g. a difficulty of 0, a non-canonical
+    /// compact target or a progress callback with an interval of 0.
+    InvalidInput = 2,
+    /// The hash does not meet the target.
+    NotMet = 3,
+    /// The miner tried every iteration without a solution.
+    NotFound = 4,
+    /// The progress callback stopped the miner.
+    Cancelled = 5,
+}
+
+/// Called by `pow5_mine_64b` with the number of iterations tried so far.
+/// Return false to stop mining. May be null.
+pub type Pow5ProgressCallback =
+    Option<extern "C" fn(iterations: u64, user_data: *mut c_void) -> bool>;
+
+/// # Safety
+///
+/// `ptr` must be null or valid for reading `N` bytes.
+unsafe fn read<const N: usize>(ptr: *const u8) -> Option<[u8; N]> {
+    if ptr.is_null() {
+        return None;
+    }
+    // SAFETY: the caller guarantees `ptr` is valid for `N` bytes
+    Some(unsafe { std::ptr::read_unaligned(ptr as *const [u8; N]) })
+}
+
+/// # Safety
+///
+/// `ptr` must be valid for writing `N` bytes.
+unsafe fn write<const N: usize>(ptr: *mut u8, bytes: &[u8; N]) {
+    // SAFETY: the caller guarantees `ptr` is valid for `N` bytes
+    unsafe { std::ptr::copy_nonoverlapping(bytes.as_ptr(), ptr, N) }
+}
+
+/// Write the pow5-64b hash of the 64-byte `header` to the 32-byte `out`.
+///
+/// # Safety
+///
+/// `header` must be valid for reading 64 bytes and `out` for writing 32.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pow5_hash_64b(header: *const u8, out: *mut u8) -> Pow5Status {
+    let Some(header) = (unsafe { read::<HEADER_SIZE_64B>(header) }) else {
+        return Pow5Status::NullPointer;
+    };
+    if out.is_null() {
+        return Pow5Status::NullPointer;
+    }
+    unsafe { write(out, &hash_64b(&header)) };
+    Pow5Status::Ok
+}
+
+/// Write the pow5-217a block id of the 217-byte `header` to the 32-byte `out`.
+///
+/// # Safety
+///
+/// `header` must be valid for reading 217 bytes and `out` for writing 32.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pow5_hash_217a(header: *const u8, out: *mut u8) -> Pow5Status {
+    let Some(header) = (unsafe { read::<HEADER_SIZE_217A>(header) }) else {
+        return Pow5Status::NullPointer;
+    };
+    if out.is_null() {
+        return Pow5Status::NullPointer;
+    }
+    unsafe { write(out, &elementary_iteration_217a(&header)) };
+    Pow5Status::Ok
+}
+
+/// Check that the pow5-64b hash of the 64-byte `header` meets the 32-byte
+/// `target`. Returns `Ok` if it does and `NotMet` if it does not.
+///
+/// # Safety
+///
+/// `header` must be valid for reading 64 bytes and `target` for reading 32.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pow5_verify_64b(header: *const u8, target: *const u8) -> Pow5Status {
+    let (Some(header), Some(target)) = (unsafe { read::<HEADER_SIZE_64B>(header) }, unsafe {
+        read::<HASH_SIZE>(target)
+    }) else {
+        return Pow5Status::NullPointer;
+    };
+    if Target::from_bytes(target).is_met_by(&hash_64b(&header)) {
+        Pow5Status::Ok
+    } else {
+        Pow5Status::NotMet
+    }
+}
+
+/// Write the 32-byte target for `difficulty` to `out`.
+///
+/// # Safety
+///
+/// `out` must be valid for writing 32 bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pow5_target_from_difficulty(difficulty: u64, out: *mut u8) -> Pow5Status {
+    if out.is_null() {
+        return Pow5Status::NullPointer;
+    }
+    let Ok(target) = Target::from_difficulty(difficulty) else {
+        return Pow5Status::InvalidInput;
+    };
+    unsafe { write(out, target.as_bytes()) };
+    Pow5Status::Ok
+}
+
+/// Encode the 32-byte `target` as a compact 4-byte target, rounding down.
+///
+/// # Safety
+///
+/// `target` must be valid for reading 32 bytes and `out` for writing a u32.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pow5_target_to_compact(target: *const u8, out: *mut u32) -> Pow5Status {
+    let Some(target) = (unsafe { read::<HASH_SIZE>(target) }) else {
+        return Pow5Status::NullPointer;
+    };
+    if out.is_null() {
+        return Pow5Status::NullPointer;
+    }
+    let compact = CompactTarget::from_target(&Target::from_bytes(target));
+    // SAFETY: the caller guarantees `out` is valid for a u32
+    unsafe { out.write_unaligned(compact.to_u32()) };
+    Pow5Status::Ok
+}
+
+/// Decode a compact 4-byte target to the 32-byte target `out`.
+///
+/// # Safety
+///
+/// `out` must be valid for writing 32 bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pow5_compact_to_target(compact: u32, out: *mut u8) -> Pow5Status {
+    if out.is_null() {
+        return Pow5Status::NullPointer;
+    }
+    let Ok(compact) = CompactTarget::from_u32(compact) else {
+        return Pow5Status::InvalidInput;
+    };
+    unsafe { write(out, compact.to_target().as_bytes()) };
+    Pow5Status::Ok
+}
+
+/// Mine the 64-byte `header` against the 32-byte `target`, trying at most
+/// `max_iterations` nonces as `mine_64b` does, and write the solved header to
+/// the 64-byte `out`. If `progress` is not null it is called with `user_data`
+/// every `progress_interval` iterations and mining stops if it returns false.
+///
+/// # Safety
+///
+/// `header` must be valid for reading 64 bytes, `target` for reading 32 and
+/// `out` for writing 64. `progress`, if not null, must be safe to call with
+/// `user_data`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pow5_mine_64b(
+    header: *const u8,
+    target: *const u8,
+    max_iterations: u64,
+    progress_interval: u64,
+    progress: Pow5ProgressCallback,
+    user_data: *mut c_void,
+    out: *mut u8,
+) -> Pow5Status {
+    let (Some(header), Some(target)) = (unsafe { read::<HEADER_SIZE_64B>(header) }, unsafe {
+        read::<HASH_SIZE>(target)
+    }) else {
+        return Pow5Status::NullPointer;
+    };
+    if out.is_null() {
+        return Pow5Status::NullPointer;
+    }
+    if progress.is_some() && progress_interval == 0 {
+        return Pow5Status::InvalidInput;
+    }
+    let mut cancelled = false;
+    let solved = mine_64b_with_progress(
+        &header,
+        &Target::from_bytes(target),
+        max_iterations,
+        progress_interval,
+        |iterations| {
+            cancelled = progress.is_some_and(|progress| !progress(iterations, user_data));
+            !cancelled
+        },
+    );
+    match solved {
+        Some(solved) => {
+            unsafe { write(out, &solved) };
+            Pow5Status::Ok
+        }
+        None if cancelled => Pow5Status::Cancelled,
+        None => Pow5Status::NotFound,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ptr::{null, null_mut};
+
+    #[test]
+    fn test_hash() {
+        let header = [0x11; HEADER_SIZE_64B];
+        let mut out = [0u8; HASH_SIZE];
+        let status = unsafe { pow5_hash_64b(header.as_ptr(), out.as_mut_ptr()) };
+        assert_eq!(status, Pow5Status::Ok);
+        assert_eq!(
+            out.to_vec(),
+            crate::elementary_iteration_64b(header.to_vec()).unwrap()
+        );
+
+        let header = [0x11; HEADER_SIZE_217A];
+        let status = unsafe { pow5_hash_217a(header.as_ptr(), out.as_mut_ptr()) };
+        assert_eq!(status, Pow5Status::Ok);
+        assert_eq!(
+            out.to_vec(),
+            crate::elementary_iteration_217a(header.to_vec()).unwrap()
+        );
+
+        let status = unsafe { pow5_hash_64b(null(), out.as_mut_ptr()) };
+        assert_eq!(status, Pow5Status::NullPointer);
+        let status = unsafe { pow5_hash_217a(header.as_ptr(), null_mut()) };
+        assert_eq!(status, Pow5Status::NullPointer);
+    }
+
+    #[test]
+    fn test_difficulty_and_compact() {
+        let mut target = [0u8; HASH_SIZE];
+        let status = unsafe { pow5_target_from_difficulty(7_000_000, target.as_mut_ptr()) };
+        assert_eq!(status, Pow5Status::Ok);
+        assert_eq!(
+            &target,
+            Target::from_difficulty(7_000_000).unwrap().as_bytes()
+        );
+        let status = unsafe { pow5_target_from_difficulty(0, target.as_mut_ptr()) };
+        assert_eq!(status, Pow5Status::InvalidInput);
+
+        let mut compact = 0u32;
+        let status = unsafe { pow5_target_to_compact(target.as_ptr(), &mut compact) };
+        assert_eq!(status, Pow5Status::Ok);
+        assert_eq!(compact, 0x1e02_6591);
+        let status = unsafe { pow5_compact_to_target(compact, target.as_mut_ptr()) };
+        assert_eq!(status, Pow5Status::Ok);
+        assert_eq!(&target[..5], &[0, 0, 2, 0x65, 0x91]);
+        let status = unsafe { pow5_compact_to_target(0x1e00_6591, target.as_mut_ptr()) };
+        assert_eq!(status, Pow5Status::InvalidInput);
+    }
+
+    extern "C" fn stop(_iterations: u64, user_data: *mut c_void) -> bool {
+        unsafe { *(user_data as *mut u32) += 1 };
+        false
+    }
+
+    #[test]
+    fn test_mine_and_verify() {
+        let target = *Target::from_difficulty(16).unwrap().as_bytes();
+        let header = [0u8; HEADER_SIZE_64B];
+        let mut solved = [0u8; HEADER_SIZE_64B];
+        let status = unsafe {
+            pow5_mine_64b(
+                header.as_ptr(),
+                target.as_ptr(),
+                10_000,
+                0,
+                None,
+                null_mut(),
+                solved.as_mut_ptr(),
+            )
+        };
+        assert_eq!(status, Pow5Status::Ok);
+        let status = unsafe { pow5_verify_64b(solved.as_ptr(), target.as_ptr()) };
+        assert_eq!(status, Pow5Status::Ok);
+
+        let impossible = [0u8; HASH_SIZE];
+        let status = unsafe { pow5_verify_64b(solved.as_ptr(), impossible.as_ptr()) };
+        assert_eq!(status, Pow5Status::NotMet);
+        let status = unsafe {
+            pow5_mine_64b(
+                header.as_ptr(),
+                impossible.as_ptr(),
+                10,
+                0,
+                None,
+                null_mut(),
+                solved.as_mut_ptr(),
+            )
+        };
+        assert_eq!(status, Pow5Status::NotFound);
+
+        let mut calls = 0u32;
+        let status = unsafe {
+            pow5_mine_64b(
+                header.as_ptr(),
+                impossible.as_ptr(),
+                u64::MAX,
+                5,
+                Some(stop),
+                &mut calls as *mut u32 as *mut c_void,
+                solved.as_mut_ptr(),
+            )
+        };
+        assert_eq!(status, Pow5Status::Cancelled);
+        assert_eq!(calls, 1);
+
+        let status = unsafe {
+            pow5_mine_64b(
+                header.as_ptr(),
+                impossible.as_ptr(),
+                10,
+                0,
+                Some(stop),
+                null_mut(),
+                solved.as_mut_ptr(),
+            )
+        };
+        assert_eq!(status, Pow5Status::InvalidInput);
+    }
+}
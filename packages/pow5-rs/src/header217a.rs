@@ -0,0 +1,214 @@
+// Typed model of the 217-byte EarthBucks header hashed by pow5-217a.
+//
+//   offset  size  field
+//   0       1     version
+//   1       32    previous block id
+//   33      32    merkle root
+//   65      8     timestamp, big-endian
+//   73      8     block number, big-endian
+//   81      4     reserved
+//   85      32    target
+//   117     32    nonce; the miners iterate the first 4 bytes
+//   149     2     work_ser algorithm, big-endian
+//   151     32    work_ser
+//   183     2     work_par algorithm, big-endian
+//   185     32    work_par
+//
+// The 4 reserved bytes have no meaning in pow5 and are carried through as
+// they are.
+
+use crate::difficulty::Target;
+use crate::pow::{self, SizeError};
+use crate::{HEADER_SIZE_217A, NONCE_END_217A, NONCE_START_217A};
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Header217a {
+    pub version: u8,
+    pub prev_block_id: [u8; 32],
+    pub merkle_root: [u8; 32],
+    pub timestamp: u64,
+    pub block_num: u64,
+    pub reserved: [u8; 4],
+    pub target: Target,
+    pub nonce: [u8; 32],
+    pub work_ser_algo: u16,
+    pub work_ser: [u8; 32],
+    pub work_par_algo: u16,
+    pub work_par: [u8; 32],
+}
+
+impl Header217a {
+    pub const SIZE: usize = HEADER_SIZE_217A;
+
+    pub fn to_bytes(&self) -> [u8; HEADER_SIZE_217A] {
+        let mut bytes = [0u8; HEADER_SIZE_217A];
+        let mut writer = Writer {
+            bytes: &mut bytes,
+            offset: 0,
+        };
+        writer.put(&[self.version]);
+        writer.put(&self.prev_block_id);
+        writer.put(&self.merkle_root);
+        writer.put(&self.timestamp.to_be_bytes());
+        writer.put(&self.block_num.to_be_bytes());
+        writer.put(&self.reserved);
+        writer.put(self.target.as_bytes());
+        writer.put(&self.nonce);
+        writer.put(&self.work_ser_algo.to_be_bytes());
+        writer.put(&self.work_ser);
+        writer.put(&self.work_par_algo.to_be_bytes());
+        writer.put(&self.work_par);
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SizeError> {
+        let bytes: [u8; HEADER_SIZE_217A] = pow::to_array("header", bytes)?;
+        let mut reader = Reader {
+            bytes: &bytes,
+            offset: 0,
+        };
+        Ok(Header217a {
+            version: u8::from_be_bytes(reader.take()),
+            prev_block_id: reader.take(),
+            merkle_root: reader.take(),
+            timestamp: u64::from_be_bytes(reader.take()),
+            block_num: u64::from_be_bytes(reader.take()),
+            reserved: reader.take(),
+            target: Target::from_bytes(reader.take()),
+            nonce: reader.take(),
+            work_ser_algo: u16::from_be_bytes(reader.take()),
+            work_ser: reader.take(),
+            work_par_algo: u16::from_be_bytes(reader.take()),
+            work_par: reader.take(),
+        })
+    }
+
+    /// Set the 4-byte nonce the miners iterate, as `insert_nonce_217a` does.
+    pub fn set_nonce_u32(&mut self, nonce: u32) {
+        self.nonce[..NONCE_END_217A - NONCE_START_217A].copy_from_slice(&nonce.to_be_bytes());
+    }
+
+    /// Compute work_par for this header. It is not stored; `id` computes it
+    /// again.
+    pub fn compute_work_par(&self) -> [u8; 32] {
+        pow::get_work_par_217a(&self.to_bytes())
+    }
+
+    /// The block id: `elementary_iteration_217a` of the serialized header.
+    pub fn id(&self) -> [u8; 32] {
+        pow::elementary_iteration_217a(&self.to_bytes())
+    }
+
+    /// Whether the id meets the header's own target.
+    pub fn meets_target(&self) -> bool {
+        self.target.is_met_by(&self.id())
+    }
+}
+
+struct Writer<'a> {
+    bytes: &'a mut [u8; HEADER_SIZE_217A],
+    offset: usize,
+}
+
+impl Writer<'_> {
+    fn put(&mut self, field: &[u8]) {
+        self.bytes[self.offset..self.offset + field.len()].copy_from_slice(field);
+        self.offset += field.len();
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8; HEADER_SIZE_217A],
+    offset: usize,
+}
+
+impl Reader<'_> {
+    fn take<const N: usize>(&mut self) -> [u8; N] {
+        let field = self.bytes[self.offset..self.offset + N].try_into().unwrap();
+        self.offset += N;
+        field
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::WORK_PAR_START_217A;
+
+    fn sample() -> Header217a {
+        Header217a {
+            version: 1,
+            prev_block_id: [0x01; 32],
+            merkle_root: [0x02; 32],
+            timestamp: 0x0304_0506_0708_090a,
+            block_num: 42,
+            reserved: [0x0b; 4],
+            target: Target::from_difficulty(1000).unwrap(),
+            nonce: [0x0c; 32],
+            work_ser_algo: 1,
+            work_ser: [0x0d; 32],
+            work_par_algo: 2,
+            work_par: [0x0e; 32],
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let header = sample();
+        let bytes = header.to_bytes();
+        assert_eq!(Header217a::from_bytes(&bytes), Ok(header.clone()));
+
+        assert_eq!(bytes[0], 1);
+        assert_eq!(&bytes[65..73], &[3, 4, 5, 6, 7, 8, 9, 10]);
+        assert_eq!(&bytes[73..81], &42u64.to_be_bytes());
+        assert_eq!(&bytes[81..85], &[0x0b; 4]);
+        assert_eq!(&bytes[NONCE_START_217A..NONCE_START_217A + 32], &[0x0c; 32]);
+        assert_eq!(
+            &bytes[WORK_PAR_START_217A - 2..WORK_PAR_START_217A],
+            &[0, 2]
+        );
+        assert_eq!(&bytes[WORK_PAR_START_217A..], &[0x0e; 32]);
+
+        assert!(Header217a::from_bytes(&bytes[..216]).is_err());
+        assert!(Header217a::from_bytes(&[0; 218]).is_err());
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn test_id_matches_vec_api() {
+        let zeroes = Header217a::from_bytes(&[0; HEADER_SIZE_217A]).unwrap();
+        assert_eq!(
+            hex::encode(zeroes.id()),
+            "c88f591bfa80126e9a14d76d473ca8ae7ac578ed1eac0150fcbc06742f4f7d6f"
+        );
+
+        let header = sample();
+        assert_eq!(
+            header.id().to_vec(),
+            crate::elementary_iteration_217a(header.to_bytes().to_vec()).unwrap()
+        );
+        assert_eq!(
+            header.compute_work_par().to_vec(),
+            crate::get_work_par_217a(header.to_bytes().to_vec()).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_set_nonce_u32() {
+        let mut header = Header217a::from_bytes(&[0; HEADER_SIZE_217A]).unwrap();
+        header.set_nonce_u32(376413);
+        let mut expected = [0; HEADER_SIZE_217A];
+        pow::insert_nonce_217a(&mut expected, 376413);
+        assert_eq!(header.to_bytes(), expected);
+        assert_eq!(
+            hex::encode(header.id()),
+            "00000004f0ac89d75f135f184abbf0a82fad1e07fb4a29adb159648d70adf474"
+        );
+
+        // MAX is met by almost every id and zero by none
+        header.target = Target::MAX;
+        assert!(header.meets_target());
+        header.target = Target::from_bytes([0; 32]);
+        assert!(!header.meets_target());
+    }
+}
@@ -0,0 +1,201 @@
+// Challenge signing keys with rotation.
+//
+// Every challenge carries the id of the key that signed it. The ring signs new
+// challenges with the current key and verifies against the current key plus
+// any retired keys that may still have challenges in flight. A retired key is
+// dropped once every challenge it could have signed has expired, so operators
+// can rotate the secret without breaking users who are mid-mining.
+
+use crate::challenge::{CHALLENGE_EXPIRY_MS, Challenge, ChallengeError, ChallengeKey};
+use crate::challenge::{ChallengeVerifier, ChallengeVersion, Compatibility, SignatureAlgorithm};
+
+struct RetiredKey {
+    key_id: u32,
+    key: ChallengeKey,
+    /// Last moment (ms since the Unix epoch) a challenge signed by this key
+    /// can still be valid.
+    valid_until: u64,
+}
+
+pub struct KeyRing {
+    current_id: u32,
+    current: ChallengeKey,
+    retired: Vec<RetiredKey>,
+    challenge_lifetime: u64,
+}
+
+impl KeyRing {
+    /// Create a ring with a single signing key. Retired keys are kept for
+    /// [`CHALLENGE_EXPIRY_MS`] after rotation.
+    pub fn new(key_id: u32, key: ChallengeKey) -> Self {
+        KeyRing::with_challenge_lifetime(key_id, key, CHALLENGE_EXPIRY_MS)
+    }
+
+    /// Create a ring for challenges that live `challenge_lifetime` ms.
+    pub fn with_challenge_lifetime(
+        key_id: u32,
+        key: ChallengeKey,
+        challenge_lifetime: u64,
+    ) -> Self {
+        KeyRing {
+            current_id: key_id,
+            current: key,
+            retired: Vec::new(),
+            challenge_lifetime,
+        }
+    }
+
+    pub fn current_key_id(&self) -> u32 {
+        self.current_id
+    }
+
+    /// Make `key` the signing key. The previous key keeps verifying the
+    /// challenges it already issued until they have all expired.
+    pub fn rotate(&mut self, key_id: u32, key: ChallengeKey, now: u64) -> Result<(), String> {
+        if self.find(key_id, now).is_some() {
+            return Err(format!("key id {} is already in use", key_id));
+        }
+        self.prune(now);
+        let previous_id = std::mem::replace(&mut self.current_id, key_id);
+        let previous = std::mem::replace(&mut self.current, key);
+        self.retired.push(RetiredKey {
+            key_id: previous_id,
+            key: previous,
+            valid_until: now + self.challenge_lifetime,
+        });
+        Ok(())
+    }
+
+    /// Forget retired keys whose challenges have all expired.
+    pub fn prune(&mut self, now: u64) {
+        self.retired.retain(|retired| now <= retired.valid_until);
+    }
+
+    fn find(&self, key_id: u32, now: u64) -> Option<&ChallengeKey> {
+        if key_id == self.current_id {
+            return Some(&self.current);
+        }
+        self.retired
+            .iter()
+            .find(|retired| retired.key_id == key_id && now <= retired.valid_until)
+            .map(|retired| &retired.key)
+    }
+
+    /// Stamp the algorithm and current key id into `challenge` and sign it
+    /// with v2.
+    pub fn sign(&self, challenge: &mut Challenge) -> [u8; 32] {
+        challenge.algorithm = SignatureAlgorithm::HmacSha256;
+        challenge.key_id = self.current_id;
+        self.current.sign(challenge, ChallengeVersion::V2)
+    }
+}
+
+impl ChallengeVerifier for KeyRing {
+    /// Checks the signature with the key named by the challenge's key id. v1
+    /// challenges have no key id of their own, so callers accepting them set
+    /// `key_id` to the id the legacy secret was given in this ring.
+    ///
+    /// Retired keys are looked up by the challenge's expiry rather than the
+    /// current time, which is equivalent for unexpired challenges.
+    fn verify_signature(
+        &self,
+        challenge: &Challenge,
+        signature: &[u8],
+        compatibility: Compatibility,
+    ) -> Result<ChallengeVersion, ChallengeError> {
+        let key = self
+            .find(challenge.key_id, challenge.expires_at)
+            .ok_or(ChallengeError::UnknownKey(challenge.key_id))?;
+        key.verify_signature(challenge, signature, compatibility)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::challenge::test_support::{self, NOW};
+    use crate::challenge::{ChallengePurpose, VerifyOptions};
+
+    fn test_challenge(now: u64) -> Challenge {
+        Challenge {
+            expires_at: now + CHALLENGE_EXPIRY_MS,
+            ..test_support::test_challenge(ChallengePurpose::Login)
+        }
+    }
+
+    fn test_options(now: u64) -> VerifyOptions {
+        test_support::test_options(ChallengePurpose::Login, now)
+    }
+
+    #[test]
+    fn test_sign_stamps_current_key_id() {
+        let ring = KeyRing::new(1, ChallengeKey::new([1; 32]));
+        let mut challenge = test_challenge(NOW);
+        let signature = ring.sign(&mut challenge);
+        assert_eq!(challenge.key_id, 1);
+        assert_eq!(
+            ring.verify(&challenge, &signature, &test_options(NOW)),
+            Ok(ChallengeVersion::V2)
+        );
+    }
+
+    #[test]
+    fn test_rotation_keeps_in_flight_challenges() {
+        let mut ring = KeyRing::new(1, ChallengeKey::new([1; 32]));
+        let mut old = test_challenge(NOW);
+        let old_signature = ring.sign(&mut old);
+
+        let rotated_at = NOW + 60_000;
+        ring.rotate(2, ChallengeKey::new([2; 32]), rotated_at)
+            .unwrap();
+        assert_eq!(ring.current_key_id(), 2);
+
+        let mut new = test_challenge(rotated_at);
+        let new_signature = ring.sign(&mut new);
+        assert_eq!(new.key_id, 2);
+
+        // both verify while the old challenge is still in flight
+        let now = rotated_at + 1000;
+        assert!(
+            ring.verify(&old, &old_signature, &test_options(now))
+                .is_ok()
+        );
+        assert!(
+            ring.verify(&new, &new_signature, &test_options(now))
+                .is_ok()
+        );
+
+        // a new challenge cannot be relabelled to the retired key
+        let mut forged = new.clone();
+        forged.key_id = 1;
+        assert_eq!(
+            ring.verify(&forged, &new_signature, &test_options(now)),
+            Err(ChallengeError::InvalidSignature)
+        );
+    }
+
+    #[test]
+    fn test_retired_key_expires() {
+        let mut ring = KeyRing::new(1, ChallengeKey::new([1; 32]));
+        let mut challenge = test_challenge(NOW);
+        let signature = ring.sign(&mut challenge);
+        ring.rotate(2, ChallengeKey::new([2; 32]), NOW).unwrap();
+
+        ring.prune(NOW + CHALLENGE_EXPIRY_MS + 1);
+        assert_eq!(
+            ring.verify_signature(&challenge, &signature, Compatibility::V2Only),
+            Err(ChallengeError::UnknownKey(1))
+        );
+    }
+
+    #[test]
+    fn test_rotate_rejects_key_id_in_use() {
+        let mut ring = KeyRing::new(1, ChallengeKey::new([1; 32]));
+        assert!(ring.rotate(1, ChallengeKey::new([2; 32]), NOW).is_err());
+        ring.rotate(2, ChallengeKey::new([2; 32]), NOW).unwrap();
+        assert!(ring.rotate(1, ChallengeKey::new([3; 32]), NOW).is_err());
+        // once the retired key is gone its id can be reused
+        let later = NOW + CHALLENGE_EXPIRY_MS + 1;
+        ring.rotate(1, ChallengeKey::new([3; 32]), later).unwrap();
+    }
+}
@@ -1,5 +1,60 @@
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+// The core, always compiled, hashes and checks targets on fixed-size arrays
+// with no allocation. `alloc` adds the `Vec` API below and the modules that
+// need a heap; `std` adds challenges, replay stores and the tools built on
+// them; `wasm` exports the `Vec` API with wasm-bindgen. `threads` mines on a
+// rayon pool, which in wasm runs on Web Workers.
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
+#[cfg(feature = "std")]
+pub mod adjust;
 pub mod blake3_reference;
-use blake3_reference::blake3_reference_hash;
+#[cfg(feature = "std")]
+pub mod challenge;
+pub mod compact;
+#[cfg(feature = "serde")]
+pub mod contract;
+pub mod difficulty;
+#[cfg(feature = "std")]
+pub mod ed25519;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod header217a;
+#[cfg(feature = "std")]
+pub mod keyring;
+#[cfg(feature = "std")]
+pub mod merkle;
+#[cfg(feature = "std")]
+pub mod message;
+pub mod mine;
+#[cfg(feature = "std")]
+pub mod multi;
+#[cfg(feature = "std")]
+pub mod policy;
+pub mod pow;
+#[cfg(feature = "std")]
+pub mod replay;
+#[cfg(feature = "alloc")]
+pub mod scratchpad;
+#[cfg(feature = "alloc")]
+pub mod sequential;
+#[cfg(feature = "simd128")]
+mod simd128;
+#[cfg(feature = "std")]
+pub mod simulate;
+#[cfg(feature = "std")]
+pub mod stamp;
+#[cfg(all(feature = "threads", feature = "wasm", target_arch = "wasm32"))]
+mod thread_pool;
+#[cfg(feature = "std")]
+pub mod token;
+pub mod work;
+#[cfg(feature = "alloc")]
+use alloc::{string::String, string::ToString, vec::Vec};
+#[cfg(feature = "wasm")]
 use wasm_bindgen::prelude::*;
 
 // =============================================================================
@@ -15,99 +70,29 @@ const WORK_PAR_END_217A: usize = 1 + 32 + 32 + 8 + 8 + 4 + 32 + 32 + 2 + 32 + 2
 
 /// Compute work_par for 217-byte input (earthbucks format).
 /// This is the ASIC-resistant matmul computation.
-#[wasm_bindgen]
+#[cfg(feature = "alloc")]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
 pub fn get_work_par_217a(header: Vec<u8>) -> Result<Vec<u8>, String> {
-    if header.len() != HEADER_SIZE_217A {
-        return Err(format!(
-            "header is not the correct size: expected {}, got {}",
-            HEADER_SIZE_217A,
-            header.len()
-        ));
-    }
-    // first, hash the header with blake3
-    let matrix_a_row_1 = blake3_reference_hash(header.clone());
-
-    // next, we will do the following. we will hash this hash over and over, 32
-    // times. we will then multiply and add (similar to matmul) each value of
-    // matrix_A_row_1 against each value of the new columns, of which there are
-    // 32. these values will go into the final hash.
-    let mut matrix_c_working_column = matrix_a_row_1.clone();
-    let mut matrix_c_row_1 = [0u32; HASH_SIZE];
-    #[allow(clippy::needless_range_loop)]
-    for i in 0..32 {
-        // now, hash the working column to get a new matrix_B_working_column
-        matrix_c_working_column = blake3_reference_hash(matrix_c_working_column.to_vec());
-
-        // the working column has been updated. now we "multiply and add" it
-        // against the header hash.
-        for j in 0..32 {
-            matrix_c_row_1[i] += (matrix_a_row_1[j] as u32) * (matrix_c_working_column[j] as u32);
-        }
-    }
-
-    // now we need to convert the matrix_c_row_1 to a u8 array - in *big-endian*
-    // format
-    let mut final_pre_hash: [u8; 32 * 4] = [0u8; 32 * 4];
-    #[allow(clippy::needless_range_loop)]
-    for i in 0..32 {
-        let x = matrix_c_row_1[i];
-        let j = i * 4;
-        final_pre_hash[j] = (x >> 24) as u8;
-        final_pre_hash[j + 1] = (x >> 16) as u8;
-        final_pre_hash[j + 2] = (x >> 8) as u8;
-        final_pre_hash[j + 3] = x as u8;
-    }
-
-    // we have now produced the first row of a matrix C via a matmul-esque operation. we will now
-    // hash this row to get the "parallel work" or "work_par".
-    let work_par = blake3_reference_hash(final_pre_hash.to_vec());
-
-    Ok(work_par.to_vec())
+    let header = pow::to_array("header", &header).map_err(|err| err.to_string())?;
+    Ok(pow::get_work_par_217a(&header).to_vec())
 }
 
 /// Elementary iteration for 217-byte input (earthbucks format).
 /// Computes work_par, inserts it into the header, then double-hashes.
-#[wasm_bindgen]
+#[cfg(feature = "alloc")]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
 pub fn elementary_iteration_217a(header: Vec<u8>) -> Result<Vec<u8>, String> {
-    if header.len() != HEADER_SIZE_217A {
-        return Err(format!(
-            "header is not the correct size: expected {}, got {}",
-            HEADER_SIZE_217A,
-            header.len()
-        ));
-    }
-
-    let work_par = get_work_par_217a(header.clone())?;
-
-    // now we need to insert to the work_par into the header
-    let mut working_header = header.clone();
-    #[allow(clippy::manual_memcpy)]
-    for i in WORK_PAR_START_217A..WORK_PAR_END_217A {
-        working_header[i] = work_par[i - WORK_PAR_START_217A];
-    }
-
-    // now we need to hash the header
-    let hash_1 = blake3_reference_hash(working_header);
-
-    // now we need to hash it again because the "id" is actually the hash of the hash
-    let hash_2 = blake3_reference_hash(hash_1);
-
-    Ok(hash_2)
+    let header = pow::to_array("header", &header).map_err(|err| err.to_string())?;
+    Ok(pow::elementary_iteration_217a(&header).to_vec())
 }
 
 /// Insert 4-byte nonce into 217-byte header at bytes 117-121.
-#[wasm_bindgen]
+#[cfg(feature = "alloc")]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
 pub fn insert_nonce_217a(header: Vec<u8>, nonce: u32) -> Result<Vec<u8>, String> {
-    if header.len() != HEADER_SIZE_217A {
-        return Err(format!(
-            "header is not the correct size: expected {}, got {}",
-            HEADER_SIZE_217A,
-            header.len()
-        ));
-    }
-    let mut header = header.clone();
-    header[NONCE_START_217A..NONCE_END_217A].copy_from_slice(&nonce.to_be_bytes());
-    Ok(header)
+    let mut header = pow::to_array("header", &header).map_err(|err| err.to_string())?;
+    pow::insert_nonce_217a(&mut header, nonce);
+    Ok(header.to_vec())
 }
 
 // =============================================================================
@@ -120,116 +105,166 @@ const NONCE_END_64B: usize = 32;
 
 /// Matmul work computation for 64-byte input.
 /// Same ASIC-resistant algorithm as 217a, just with different input size.
-#[wasm_bindgen]
+#[cfg(feature = "alloc")]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
 pub fn matmul_work_64b(header: Vec<u8>) -> Result<Vec<u8>, String> {
-    if header.len() != HEADER_SIZE_64B {
-        return Err(format!(
-            "header is not the correct size: expected {}, got {}",
-            HEADER_SIZE_64B,
-            header.len()
-        ));
-    }
-    // first, hash the header with blake3
-    let matrix_a_row_1 = blake3_reference_hash(header.clone());
-
-    // next, we will do the following. we will hash this hash over and over, 32
-    // times. we will then multiply and add (similar to matmul) each value of
-    // matrix_A_row_1 against each value of the new columns, of which there are
-    // 32. these values will go into the final hash.
-    let mut matrix_c_working_column = matrix_a_row_1.clone();
-    let mut matrix_c_row_1 = [0u32; HASH_SIZE];
-    #[allow(clippy::needless_range_loop)]
-    for i in 0..32 {
-        // now, hash the working column to get a new matrix_B_working_column
-        matrix_c_working_column = blake3_reference_hash(matrix_c_working_column.to_vec());
-
-        // the working column has been updated. now we "multiply and add" it
-        // against the header hash.
-        for j in 0..32 {
-            matrix_c_row_1[i] += (matrix_a_row_1[j] as u32) * (matrix_c_working_column[j] as u32);
-        }
-    }
-
-    // now we need to convert the matrix_c_row_1 to a u8 array - in *big-endian*
-    // format
-    let mut final_pre_hash: [u8; 32 * 4] = [0u8; 32 * 4];
-    #[allow(clippy::needless_range_loop)]
-    for i in 0..32 {
-        let x = matrix_c_row_1[i];
-        let j = i * 4;
-        final_pre_hash[j] = (x >> 24) as u8;
-        final_pre_hash[j + 1] = (x >> 16) as u8;
-        final_pre_hash[j + 2] = (x >> 8) as u8;
-        final_pre_hash[j + 3] = x as u8;
-    }
-
-    // hash the 128-byte matmul result to get the final 32-byte output
-    let result = blake3_reference_hash(final_pre_hash.to_vec());
-
-    Ok(result.to_vec())
+    let header = pow::to_array("header", &header).map_err(|err| err.to_string())?;
+    Ok(pow::matmul_work_64b(&header).to_vec())
 }
 
 /// Elementary iteration for 64-byte input.
 /// Unlike 217a, we don't insert work_par into the header.
 /// We simply double-hash the matmul result to produce the final PoW hash.
-#[wasm_bindgen]
+#[cfg(feature = "alloc")]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
 pub fn elementary_iteration_64b(header: Vec<u8>) -> Result<Vec<u8>, String> {
-    if header.len() != HEADER_SIZE_64B {
-        return Err(format!(
-            "header is not the correct size: expected {}, got {}",
-            HEADER_SIZE_64B,
-            header.len()
-        ));
-    }
-
-    let work = matmul_work_64b(header)?;
-
-    // double-hash the matmul result
-    let hash_1 = blake3_reference_hash(work);
-    let hash_2 = blake3_reference_hash(hash_1);
-
-    Ok(hash_2)
+    let header = pow::to_array("header", &header).map_err(|err| err.to_string())?;
+    Ok(pow::elementary_iteration_64b(&header).to_vec())
 }
 
 /// Insert nonce into the last 4 bytes of the 32-byte nonce field (bytes 28-31).
 /// This matches the WGSL implementation where the GPU iterates the last 4 bytes.
-#[wasm_bindgen]
+#[cfg(feature = "alloc")]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
 pub fn insert_nonce_64b(header: Vec<u8>, nonce: u32) -> Result<Vec<u8>, String> {
-    if header.len() != HEADER_SIZE_64B {
-        return Err(format!(
-            "header is not the correct size: expected {}, got {}",
-            HEADER_SIZE_64B,
-            header.len()
-        ));
-    }
-    let mut header = header.clone();
-    // Insert nonce into bytes 28-31 (last 4 bytes of the 32-byte nonce)
-    header[28..32].copy_from_slice(&nonce.to_be_bytes());
-    Ok(header)
+    let mut header = pow::to_array("header", &header).map_err(|err| err.to_string())?;
+    pow::insert_nonce_64b(&mut header, nonce);
+    Ok(header.to_vec())
 }
 
 /// Set the full 32-byte nonce (bytes 0-31).
-#[wasm_bindgen]
+#[cfg(feature = "alloc")]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
 pub fn set_nonce_64b(header: Vec<u8>, nonce: Vec<u8>) -> Result<Vec<u8>, String> {
-    if header.len() != HEADER_SIZE_64B {
-        return Err(format!(
-            "header is not the correct size: expected {}, got {}",
-            HEADER_SIZE_64B,
-            header.len()
-        ));
-    }
-    if nonce.len() != 32 {
-        return Err(format!(
-            "nonce is not the correct size: expected 32, got {}",
-            nonce.len()
-        ));
+    let mut header = pow::to_array("header", &header).map_err(|err| err.to_string())?;
+    let nonce = pow::to_array("nonce", &nonce).map_err(|err| err.to_string())?;
+    pow::set_nonce_64b(&mut header, &nonce);
+    Ok(header.to_vec())
+}
+
+/// Mine a 64-byte header on up to `threads` threads, trying at most
+/// `max_iterations` counters from the one already in bytes 24-31. Returns the
+/// header `mine_64b` would find, or `None` if no counter tried meets the
+/// 32-byte `target`. It runs on one thread without the `threads` feature, or
+/// in wasm before `initThreadPool` has finished.
+#[cfg(feature = "alloc")]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn mine_64b_parallel(
+    header: Vec<u8>,
+    target: Vec<u8>,
+    max_iterations: u64,
+    threads: u32,
+) -> Result<Option<Vec<u8>>, String> {
+    let header = pow::to_array("header", &header).map_err(|err| err.to_string())?;
+    let target = pow::to_array("target", &target).map_err(|err| err.to_string())?;
+    let target = difficulty::Target::from_bytes(target);
+    #[cfg(all(feature = "threads", feature = "wasm", target_arch = "wasm32"))]
+    let threads = if thread_pool::pool_ready() {
+        threads
+    } else {
+        1
+    };
+    #[cfg(feature = "threads")]
+    let solved = mine::mine_64b_parallel(&header, &target, max_iterations, threads as usize);
+    #[cfg(not(feature = "threads"))]
+    let solved = {
+        let _ = threads;
+        mine::mine_64b(&header, &target, max_iterations)
+    };
+    Ok(solved.map(|header| header.to_vec()))
+}
+
+// =============================================================================
+// compact 4-byte targets
+// =============================================================================
+
+/// Encode a 32-byte target as a compact 4-byte target, rounding down so it is
+/// never easier.
+#[cfg(feature = "alloc")]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn target_to_compact(target: Vec<u8>) -> Result<u32, String> {
+    let target = pow::to_array("target", &target).map_err(|err| err.to_string())?;
+    let target = difficulty::Target::from_bytes(target);
+    Ok(compact::CompactTarget::from_target(&target).to_u32())
+}
+
+/// Decode a compact 4-byte target to the 32-byte target it stands for.
+#[cfg(feature = "alloc")]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn compact_to_target(compact: u32) -> Result<Vec<u8>, String> {
+    let compact = compact::CompactTarget::from_u32(compact).map_err(|err| err.to_string())?;
+    Ok(compact.to_target().to_bytes().to_vec())
+}
+
+// =============================================================================
+// sequential work: iterated BLAKE3 with checkpoints
+// =============================================================================
+
+/// Compute a sequential-work proof for a 32-byte seed: a chain of `iterations`
+/// BLAKE3 hashes with `segments` checkpoints. Returns the serialized proof.
+#[cfg(feature = "alloc")]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn sequential_work(seed: Vec<u8>, iterations: u64, segments: u32) -> Result<Vec<u8>, String> {
+    let seed = pow::to_array("seed", &seed).map_err(|err| err.to_string())?;
+    Ok(sequential::SequentialProof::compute(&seed, iterations, segments)?.to_bytes())
+}
+
+/// Verify a serialized sequential-work proof of a chain of `iterations` steps
+/// by checking every segment. Returns the 32-byte output of the chain if it is
+/// valid.
+#[cfg(feature = "alloc")]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn verify_sequential_work(
+    seed: Vec<u8>,
+    proof: Vec<u8>,
+    iterations: u64,
+) -> Result<Vec<u8>, String> {
+    let seed = pow::to_array("seed", &seed).map_err(|err| err.to_string())?;
+    let proof = sequential::SequentialProof::from_bytes(&proof, iterations)?;
+    if !proof.verify_full(&seed, iterations) {
+        return Err("sequential work proof is invalid".to_string());
     }
-    let mut header = header.clone();
-    header[NONCE_START_64B..NONCE_END_64B].copy_from_slice(&nonce);
-    Ok(header)
+    Ok(proof.output().to_vec())
 }
 
-#[cfg(test)]
+/// Compute the sequential-work seed of a 217-byte header (earthbucks format).
+#[cfg(feature = "alloc")]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn work_ser_seed_217a(header: Vec<u8>) -> Result<Vec<u8>, String> {
+    let header = header217a::Header217a::from_bytes(&header).map_err(|err| err.to_string())?;
+    Ok(sequential::seed_217a(&header).to_vec())
+}
+
+/// Insert the 32-byte output of a sequential-work proof into the work_ser slot
+/// of a 217-byte header (earthbucks format).
+#[cfg(feature = "alloc")]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn set_work_ser_217a(header: Vec<u8>, work_ser: Vec<u8>) -> Result<Vec<u8>, String> {
+    let mut header = header217a::Header217a::from_bytes(&header).map_err(|err| err.to_string())?;
+    let work_ser = pow::to_array("work_ser", &work_ser).map_err(|err| err.to_string())?;
+    sequential::set_work_ser_217a(&mut header, &work_ser);
+    Ok(header.to_bytes().to_vec())
+}
+
+// =============================================================================
+// experimental memory-hard variant of pow5-64b
+// =============================================================================
+
+/// Memory-hard hash of a 64-byte header with a scratchpad of `memory_kib` KiB
+/// read `reads` times. See the `scratchpad` module.
+#[cfg(feature = "alloc")]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn scratchpad_hash_64b(
+    header: Vec<u8>,
+    memory_kib: u32,
+    reads: u32,
+) -> Result<Vec<u8>, String> {
+    let header = pow::to_array("header", &header).map_err(|err| err.to_string())?;
+    let params = scratchpad::ScratchpadParams::new(memory_kib, reads)?;
+    Ok(scratchpad::Scratchpad::new(params).hash(&header).to_vec())
+}
+
+#[cfg(all(test, feature = "alloc"))]
 mod tests {
     use super::*;
 
@@ -344,4 +379,72 @@ mod tests {
             assert_eq!(result[i], 0);
         }
     }
+
+    #[test]
+    fn test_mine_64b_parallel() {
+        let header = vec![0x42; HEADER_SIZE_64B];
+        let target = difficulty::Target::from_difficulty(16).unwrap();
+        let expected = mine::mine_64b(&[0x42; HEADER_SIZE_64B], &target, 10_000).unwrap();
+        for threads in [0, 1, 4] {
+            let result =
+                mine_64b_parallel(header.clone(), target.as_bytes().to_vec(), 10_000, threads);
+            assert_eq!(result, Ok(Some(expected.to_vec())));
+        }
+
+        assert_eq!(
+            mine_64b_parallel(header.clone(), vec![0; 32], 100, 4),
+            Ok(None)
+        );
+        assert!(mine_64b_parallel(header, vec![0xff; 31], 100, 4).is_err());
+    }
+
+    // =========================================================================
+    // sequential work tests
+    // =========================================================================
+
+    #[test]
+    fn test_verify_sequential_work() {
+        let seed = vec![0x11; 32];
+        let proof = sequential_work(seed.clone(), 100, 4).unwrap();
+        let output = verify_sequential_work(seed.clone(), proof, 100).unwrap();
+        assert_eq!(output.len(), 32);
+
+        // the caller's iteration count is enforced both ways
+        let short = sequential_work(seed.clone(), 99, 4).unwrap();
+        assert!(verify_sequential_work(seed.clone(), short, 100).is_err());
+        let oversized = sequential_work(seed.clone(), 1000, 4).unwrap();
+        assert!(verify_sequential_work(seed, oversized, 100).is_err());
+    }
+
+    // =========================================================================
+    // compact target tests
+    // =========================================================================
+
+    #[test]
+    fn test_compact_target() {
+        let target = difficulty::Target::from_difficulty(7_000_000).unwrap();
+        let compact = target_to_compact(target.to_bytes().to_vec()).unwrap();
+        assert_eq!(compact, 0x1e02_6591);
+        assert_eq!(
+            hex::encode(compact_to_target(compact).unwrap()),
+            "0000026591000000000000000000000000000000000000000000000000000000"
+        );
+        assert!(target_to_compact(vec![0; 31]).is_err());
+        assert!(compact_to_target(0x1e00_6591).is_err());
+    }
+
+    // =========================================================================
+    // memory-hard variant tests
+    // =========================================================================
+
+    #[test]
+    fn test_scratchpad_hash_64b() {
+        let result = scratchpad_hash_64b(vec![0; HEADER_SIZE_64B], 64, 1024).unwrap();
+        assert_eq!(
+            hex::encode(&result),
+            "884de2a9fdaae2ac509df5669096d76e02e9da861b468dc2f7971915f1fba020"
+        );
+        assert!(scratchpad_hash_64b(vec![0; 63], 64, 1024).is_err());
+        assert!(scratchpad_hash_64b(vec![0; HEADER_SIZE_64B], 0, 1024).is_err());
+    }
 }
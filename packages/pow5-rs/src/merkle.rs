@@ -0,0 +1,383 @@
+// One proof of work covering a batch of messages.
+//
+// The sender commits to the messages of a batch with a BLAKE3 Merkle tree over
+// their message commitments and mines a single pow5-64b header bound to the
+// root, at the per-message difficulty times the number of messages. Each
+// recipient gets its message, the solved challenge and an inclusion proof for
+// its own leaf, and checks that the leaf is in the tree the work was done for.
+// The total work is the same as solving for every message separately, but the
+// GPU is set up once.
+//
+// A challenge names at most one recipient, and its signature covers the name.
+// A challenge issued for a recipient only covers a burst of messages to that
+// recipient; a batch with one message per recipient needs a challenge issued
+// without a recipient address.
+//
+// Leaves and interior nodes are hashed with different prefixes, so a node can
+// never be passed off as a leaf. A node without a sibling is carried up to the
+// next level unchanged rather than paired with itself, so no two different
+// batches share a root.
+
+use crate::blake3_reference::blake3_reference_hash;
+use crate::challenge::push_field;
+
+const LEAF_PREFIX: u8 = 0x00;
+const NODE_PREFIX: u8 = 0x01;
+
+const DOMAIN_TAG: &[u8] = b"keypears pow5-64b batch binding v1";
+const TAG_SERVER_NONCE: u8 = 0x01;
+const TAG_ROOT: u8 = 0x02;
+const TAG_LEAF_COUNT: u8 = 0x03;
+
+const NONCE_SIZE: usize = 32;
+
+/// The tree hash of a message commitment.
+pub fn leaf_hash(commitment: &[u8; 32]) -> [u8; 32] {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(&[LEAF_PREFIX]);
+    hasher.update(commitment);
+    hasher.finalize().into()
+}
+
+fn node_hash(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(&[NODE_PREFIX]);
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+/// The header to mine on for a batch: the nonce half of `header`, followed by
+/// its challenge half bound to the batch root and size.
+pub fn batch_bound_header(header: &[u8; 64], root: &[u8; 32], leaf_count: u32) -> [u8; 64] {
+    let mut input = DOMAIN_TAG.to_vec();
+    push_field(&mut input, TAG_SERVER_NONCE, &header[NONCE_SIZE..]);
+    push_field(&mut input, TAG_ROOT, root);
+    push_field(&mut input, TAG_LEAF_COUNT, &leaf_count.to_be_bytes());
+    let mut bound = *header;
+    bound[NONCE_SIZE..].copy_from_slice(&blake3_reference_hash(input));
+    bound
+}
+
+/// Number of siblings on the path from leaf `index` to the root.
+fn path_len(mut index: u32, mut len: u32) -> usize {
+    let mut siblings = 0;
+    while len > 1 {
+        if index % 2 == 1 || index + 1 < len {
+            siblings += 1;
+        }
+        index /= 2;
+        len = len.div_ceil(2);
+    }
+    siblings
+}
+
+/// A Merkle tree over the message commitments of a batch.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MerkleTree {
+    // levels[0] holds the leaf hashes, the last level holds the root
+    levels: Vec<Vec<[u8; 32]>>,
+}
+
+impl MerkleTree {
+    /// Build the tree over `commitments`, in order. A batch holds between one
+    /// and `u32::MAX` messages.
+    pub fn new(commitments: &[[u8; 32]]) -> Result<Self, String> {
+        if commitments.is_empty() {
+            return Err("batch must not be empty".to_string());
+        }
+        if u32::try_from(commitments.len()).is_err() {
+            return Err("batch has too many messages".to_string());
+        }
+
+        let mut levels = vec![commitments.iter().map(leaf_hash).collect::<Vec<_>>()];
+        while levels.last().unwrap().len() > 1 {
+            let next = levels
+                .last()
+                .unwrap()
+                .chunks(2)
+                .map(|pair| match pair {
+                    [left, right] => node_hash(left, right),
+                    [single] => *single,
+                    _ => unreachable!(),
+                })
+                .collect();
+            levels.push(next);
+        }
+        Ok(MerkleTree { levels })
+    }
+
+    pub fn root(&self) -> [u8; 32] {
+        self.levels.last().unwrap()[0]
+    }
+
+    pub fn leaf_count(&self) -> u32 {
+        self.levels[0].len() as u32
+    }
+
+    /// The inclusion proof for the leaf at `index`, if there is one.
+    pub fn proof(&self, index: u32) -> Option<MerkleProof> {
+        if index >= self.leaf_count() {
+            return None;
+        }
+        let mut siblings = Vec::new();
+        let mut i = index as usize;
+        for level in &self.levels[..self.levels.len() - 1] {
+            if let Some(sibling) = level.get(i ^ 1) {
+                siblings.push(*sibling);
+            }
+            i /= 2;
+        }
+        Some(MerkleProof {
+            leaf_index: index,
+            leaf_count: self.leaf_count(),
+            siblings,
+        })
+    }
+}
+
+/// Proof that one message commitment is a leaf of a batch.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MerkleProof {
+    leaf_index: u32,
+    leaf_count: u32,
+    siblings: Vec<[u8; 32]>,
+}
+
+impl MerkleProof {
+    pub fn leaf_index(&self) -> u32 {
+        self.leaf_index
+    }
+
+    pub fn leaf_count(&self) -> u32 {
+        self.leaf_count
+    }
+
+    /// The root of the tree that has `commitment` at this proof's leaf.
+    pub fn root(&self, commitment: &[u8; 32]) -> [u8; 32] {
+        let mut hash = leaf_hash(commitment);
+        let mut siblings = self.siblings.iter();
+        let mut index = self.leaf_index;
+        let mut len = self.leaf_count;
+        while len > 1 {
+            if index % 2 == 1 {
+                hash = node_hash(siblings.next().unwrap(), &hash);
+            } else if index + 1 < len {
+                hash = node_hash(&hash, siblings.next().unwrap());
+            }
+            index /= 2;
+            len = len.div_ceil(2);
+        }
+        hash
+    }
+
+    /// Whether `commitment` is at this proof's leaf of the tree with `root`.
+    pub fn verify(&self, commitment: &[u8; 32], root: &[u8; 32]) -> bool {
+        self.root(commitment) == *root
+    }
+
+    /// Serialize as leaf index and leaf count (u32, big-endian) followed by
+    /// the sibling hashes from the leaf up.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(8 + self.siblings.len() * 32);
+        bytes.extend_from_slice(&self.leaf_index.to_be_bytes());
+        bytes.extend_from_slice(&self.leaf_count.to_be_bytes());
+        for sibling in &self.siblings {
+            bytes.extend_from_slice(sibling);
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        if bytes.len() < 8 {
+            return Err("proof is too short".to_string());
+        }
+        let leaf_index = u32::from_be_bytes(bytes[0..4].try_into().unwrap());
+        let leaf_count = u32::from_be_bytes(bytes[4..8].try_into().unwrap());
+        if leaf_index >= leaf_count {
+            return Err("leaf index is out of range".to_string());
+        }
+        let expected = path_len(leaf_index, leaf_count) * 32;
+        if bytes.len() - 8 != expected {
+            return Err(format!(
+                "expected {} bytes of sibling hashes, got {}",
+                expected,
+                bytes.len() - 8
+            ));
+        }
+        let siblings = bytes[8..]
+            .chunks_exact(32)
+            .map(|chunk| chunk.try_into().unwrap())
+            .collect();
+        Ok(MerkleProof {
+            leaf_index,
+            leaf_count,
+            siblings,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::challenge::test_support::test_options;
+    use crate::challenge::{
+        Challenge, ChallengeError, ChallengeKey, ChallengePurpose, ChallengeVerifier,
+        ChallengeVersion, SignatureAlgorithm,
+    };
+    use crate::difficulty::Target;
+    use crate::message::message_commitment;
+    use crate::mine::mine_64b;
+    use crate::replay::{MemorySpentStore, consume_batch_member};
+
+    fn commitments(n: usize) -> Vec<[u8; 32]> {
+        (0..n).map(|i| message_commitment(&[i as u8; 7])).collect()
+    }
+
+    #[test]
+    fn test_every_leaf_proves() {
+        for n in 1..=17 {
+            let leaves = commitments(n);
+            let tree = MerkleTree::new(&leaves).unwrap();
+            assert_eq!(tree.leaf_count(), n as u32);
+            for (i, leaf) in leaves.iter().enumerate() {
+                let proof = tree.proof(i as u32).unwrap();
+                assert!(proof.verify(leaf, &tree.root()));
+                assert!(!proof.verify(&leaves[(i + 1) % n], &tree.root()) || n == 1);
+                assert_eq!(MerkleProof::from_bytes(&proof.to_bytes()), Ok(proof));
+            }
+            assert_eq!(tree.proof(n as u32), None);
+        }
+    }
+
+    #[test]
+    fn test_root() {
+        let leaves = commitments(3);
+        let tree = MerkleTree::new(&leaves).unwrap();
+        let left = node_hash(&leaf_hash(&leaves[0]), &leaf_hash(&leaves[1]));
+        assert_eq!(tree.root(), node_hash(&left, &leaf_hash(&leaves[2])));
+
+        // a lone leaf is still hashed, so the root is not the commitment
+        let single = MerkleTree::new(&leaves[..1]).unwrap();
+        assert_eq!(single.root(), leaf_hash(&leaves[0]));
+
+        // not padded by repeating the last leaf
+        let padded = [leaves[0], leaves[1], leaves[2], leaves[2]];
+        assert_ne!(MerkleTree::new(&padded).unwrap().root(), tree.root());
+
+        assert!(MerkleTree::new(&[]).is_err());
+    }
+
+    #[test]
+    fn test_proof_from_bytes_rejects() {
+        let tree = MerkleTree::new(&commitments(5)).unwrap();
+        let bytes = tree.proof(4).unwrap().to_bytes();
+        assert!(MerkleProof::from_bytes(&bytes[..7]).is_err());
+        assert!(MerkleProof::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(MerkleProof::from_bytes(&[bytes.as_slice(), &[0; 32]].concat()).is_err());
+
+        let mut out_of_range = bytes.clone();
+        out_of_range[3] = 5;
+        assert!(MerkleProof::from_bytes(&out_of_range).is_err());
+    }
+
+    // one message per recipient, so the challenge names no recipient
+    #[test]
+    fn test_verify_batch_member() {
+        let key = ChallengeKey::new([1; 32]);
+        let messages: Vec<Vec<u8>> = (0..3).map(|i| vec![i; 100]).collect();
+        let leaves: Vec<[u8; 32]> = messages.iter().map(|m| message_commitment(m)).collect();
+        let tree = MerkleTree::new(&leaves).unwrap();
+
+        let mut header = [0u8; 64];
+        header[NONCE_SIZE..].copy_from_slice(&[0x5a; 32]);
+        let mut challenge = Challenge {
+            purpose: ChallengePurpose::Message,
+            algorithm: SignatureAlgorithm::HmacSha256,
+            key_id: 0,
+            header,
+            target: Target::from_difficulty(3 * 8).unwrap(),
+            expires_at: 1_000,
+            sender_address: None,
+            recipient_address: None,
+        };
+        let signature = key.sign(&challenge, ChallengeVersion::V2);
+        let bound = batch_bound_header(&challenge.header, &tree.root(), tree.leaf_count());
+        let solved = mine_64b(&bound, &challenge.target, 100_000).unwrap();
+        challenge.header[..NONCE_SIZE].copy_from_slice(&solved[..NONCE_SIZE]);
+
+        let mut options = test_options(ChallengePurpose::Message, 0);
+        options.min_difficulties.message = 8;
+        let store = MemorySpentStore::new();
+        for (i, message) in messages.iter().enumerate() {
+            let proof = tree.proof(i as u32).unwrap();
+            assert_eq!(
+                key.verify_batch_member(&challenge, &signature, &options, message, &proof),
+                Ok(ChallengeVersion::V2)
+            );
+            assert_eq!(
+                key.verify_batch_member(&challenge, &signature, &options, b"forged", &proof),
+                Err(ChallengeError::HashDoesNotMeetTarget)
+            );
+            assert_eq!(consume_batch_member(&challenge, &proof, 0, &store), Ok(()));
+            assert_eq!(
+                consume_batch_member(&challenge, &proof, 0, &store),
+                Err(ChallengeError::AlreadySpent)
+            );
+        }
+
+        // the batch must pay the per-message minimum for every leaf
+        options.min_difficulties.message = 9;
+        let proof = tree.proof(0).unwrap();
+        assert_eq!(
+            key.verify_batch_member(&challenge, &signature, &options, &messages[0], &proof),
+            Err(ChallengeError::DifficultyTooLow {
+                purpose: ChallengePurpose::Message,
+                min_difficulty: 27,
+            })
+        );
+    }
+
+    #[test]
+    fn test_batch_for_one_recipient() {
+        let key = ChallengeKey::new([1; 32]);
+        let burst: Vec<Vec<u8>> = (0..2).map(|i| vec![i; 50]).collect();
+        let leaves: Vec<[u8; 32]> = burst.iter().map(|m| message_commitment(m)).collect();
+        let tree = MerkleTree::new(&leaves).unwrap();
+
+        let mut challenge = Challenge {
+            purpose: ChallengePurpose::Message,
+            algorithm: SignatureAlgorithm::HmacSha256,
+            key_id: 0,
+            header: [0x33; 64],
+            target: Target::from_difficulty(2).unwrap(),
+            expires_at: 1_000,
+            sender_address: None,
+            recipient_address: Some("bob@example.com".to_string()),
+        };
+        let signature = key.sign(&challenge, ChallengeVersion::V2);
+        let bound = batch_bound_header(&challenge.header, &tree.root(), tree.leaf_count());
+        let solved = mine_64b(&bound, &challenge.target, 100_000).unwrap();
+        challenge.header[..NONCE_SIZE].copy_from_slice(&solved[..NONCE_SIZE]);
+
+        let options = test_options(ChallengePurpose::Message, 0);
+        for (i, message) in burst.iter().enumerate() {
+            let proof = tree.proof(i as u32).unwrap();
+            assert_eq!(
+                key.verify_batch_member(&challenge, &signature, &options, message, &proof),
+                Ok(ChallengeVersion::V2)
+            );
+        }
+
+        // the same work cannot be passed on to another recipient
+        let carol = Challenge {
+            recipient_address: Some("carol@example.com".to_string()),
+            ..challenge
+        };
+        let proof = tree.proof(0).unwrap();
+        assert_eq!(
+            key.verify_batch_member(&carol, &signature, &options, &burst[0], &proof),
+            Err(ChallengeError::InvalidSignature)
+        );
+    }
+}
@@ -0,0 +1,160 @@
+// Message-bound proof of work.
+//
+// A plain challenge proves that work was done, but not for which message, so a
+// solved challenge can be attached to a different message before it expires.
+// Binding fixes that: the server still signs a random 32-byte nonce in
+// `header[32..]`, but the client mines on a header whose challenge half is
+// derived from that nonce and a BLAKE3 commitment to the message ciphertext.
+// The verifier recomputes the header from the message it received, so the
+// proof only holds for that message.
+//
+// The commitment uses the full BLAKE3 (ciphertexts can be far larger than the
+// 1024 bytes `blake3_reference_hash` accepts) in derive-key mode, so it cannot
+// collide with any other BLAKE3 hash of the same bytes.
+
+use crate::blake3_reference::blake3_reference_hash;
+use crate::challenge::push_field;
+
+const COMMITMENT_CONTEXT: &str = "keypears pow5-64b message commitment v1";
+const DOMAIN_TAG: &[u8] = b"keypears pow5-64b message binding v1";
+const TAG_SERVER_NONCE: u8 = 0x01;
+const TAG_COMMITMENT: u8 = 0x02;
+
+const NONCE_SIZE: usize = 32;
+
+/// The BLAKE3 commitment to a message ciphertext.
+pub fn message_commitment(ciphertext: &[u8]) -> [u8; 32] {
+    blake3::derive_key(COMMITMENT_CONTEXT, ciphertext)
+}
+
+/// The challenge half to mine on for the message with `commitment`.
+pub fn bind_challenge(server_nonce: &[u8; 32], commitment: &[u8; 32]) -> [u8; 32] {
+    let mut input = DOMAIN_TAG.to_vec();
+    push_field(&mut input, TAG_SERVER_NONCE, server_nonce);
+    push_field(&mut input, TAG_COMMITMENT, commitment);
+    blake3_reference_hash(input).try_into().unwrap()
+}
+
+/// The header whose hash must meet the target for `ciphertext`: the nonce half
+/// of `header`, followed by its challenge half bound to the message.
+pub fn message_bound_header(header: &[u8; 64], ciphertext: &[u8]) -> [u8; 64] {
+    let server_nonce: &[u8; 32] = header[NONCE_SIZE..].try_into().unwrap();
+    let mut bound = *header;
+    bound[NONCE_SIZE..].copy_from_slice(&bind_challenge(
+        server_nonce,
+        &message_commitment(ciphertext),
+    ));
+    bound
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::challenge::test_support::test_options;
+    use crate::challenge::{
+        Challenge, ChallengeError, ChallengeKey, ChallengePurpose, ChallengeVerifier,
+        ChallengeVersion, SignatureAlgorithm, VerifyOptions,
+    };
+    use crate::difficulty::Target;
+    use crate::mine::mine_64b;
+    use crate::replay::{MemorySpentStore, consume};
+
+    fn signed_challenge(key: &ChallengeKey) -> (Challenge, [u8; 32]) {
+        let mut header = [0u8; 64];
+        header[NONCE_SIZE..].copy_from_slice(&[0x5a; 32]);
+        let challenge = Challenge {
+            purpose: ChallengePurpose::Message,
+            algorithm: SignatureAlgorithm::HmacSha256,
+            key_id: 0,
+            header,
+            target: Target::from_difficulty(16).unwrap(),
+            expires_at: 1_000,
+            sender_address: Some("alice@keypears.com".to_string()),
+            recipient_address: Some("bob@example.com".to_string()),
+        };
+        let signature = key.sign(&challenge, ChallengeVersion::V2);
+        (challenge, signature)
+    }
+
+    fn options() -> VerifyOptions {
+        test_options(ChallengePurpose::Message, 0)
+    }
+
+    /// Mine the bound header and copy the nonce back into the challenge, as a
+    /// client would before submitting it.
+    fn solve(challenge: &mut Challenge, ciphertext: &[u8]) {
+        let bound = message_bound_header(&challenge.header, ciphertext);
+        let solved = mine_64b(&bound, &challenge.target, 100_000).unwrap();
+        challenge.header[..NONCE_SIZE].copy_from_slice(&solved[..NONCE_SIZE]);
+    }
+
+    #[test]
+    fn test_binding_depends_on_everything() {
+        let header = [0x11; 64];
+        let bound = message_bound_header(&header, b"hello");
+        assert_eq!(&bound[..NONCE_SIZE], &header[..NONCE_SIZE]);
+        assert_ne!(&bound[NONCE_SIZE..], &header[NONCE_SIZE..]);
+        assert_ne!(bound, message_bound_header(&header, b"hellp"));
+
+        let mut other_nonce = header;
+        other_nonce[63] ^= 1;
+        assert_ne!(
+            bound[NONCE_SIZE..],
+            message_bound_header(&other_nonce, b"hello")[NONCE_SIZE..]
+        );
+
+        // the commitment is not a plain BLAKE3 hash
+        assert_ne!(
+            message_commitment(b"hello"),
+            *blake3::hash(b"hello").as_bytes()
+        );
+    }
+
+    #[test]
+    fn test_large_ciphertext() {
+        let ciphertext = vec![0xab; 50_000];
+        let bound = message_bound_header(&[0; 64], &ciphertext);
+        assert_ne!(bound, message_bound_header(&[0; 64], &ciphertext[1..]));
+    }
+
+    #[test]
+    fn test_verify_message_bound() {
+        let key = ChallengeKey::new([1; 32]);
+        let (mut challenge, signature) = signed_challenge(&key);
+        let ciphertext = b"encrypted message";
+        solve(&mut challenge, ciphertext);
+
+        assert_eq!(
+            key.verify_message_bound(&challenge, &signature, &options(), ciphertext),
+            Ok(ChallengeVersion::V2)
+        );
+        assert_eq!(
+            key.verify_message_bound(&challenge, &signature, &options(), b"another message"),
+            Err(ChallengeError::HashDoesNotMeetTarget)
+        );
+
+        let store = MemorySpentStore::new();
+        assert_eq!(consume(&challenge, 0, &store), Ok(()));
+        assert_eq!(
+            consume(&challenge, 0, &store),
+            Err(ChallengeError::AlreadySpent)
+        );
+    }
+
+    #[test]
+    fn test_unbound_solution_is_rejected() {
+        let key = ChallengeKey::new([1; 32]);
+        let (challenge, signature) = signed_challenge(&key);
+        let mut solved = challenge.clone();
+        solved.header = mine_64b(&challenge.header, &challenge.target, 100_000).unwrap();
+
+        assert_eq!(
+            key.verify(&solved, &signature, &options()),
+            Ok(ChallengeVersion::V2)
+        );
+        assert_eq!(
+            key.verify_message_bound(&solved, &signature, &options(), b"encrypted message"),
+            Err(ChallengeError::HashDoesNotMeetTarget)
+        );
+    }
+}
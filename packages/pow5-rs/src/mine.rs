@@ -0,0 +1,193 @@
+// Array-based pow5-64b hashing and a CPU miner.
+//
+// The miner iterates a 64-bit big-endian counter in bytes 24-31 of the nonce.
+// The low 32 bits are bytes 28-31, the same bytes `insert_nonce_64b` and the
+// WGSL miner iterate, so a nonce found here is found there too.
+
+use crate::difficulty::Target;
+use crate::pow::elementary_iteration_64b;
+
+const COUNTER_START: usize = 24;
+const COUNTER_END: usize = 32;
+
+/// The pow5-64b hash of a 64-byte header.
+pub fn hash_64b(header: &[u8; 64]) -> [u8; 32] {
+    elementary_iteration_64b(header)
+}
+
+/// Search for a header whose hash meets `target`, starting from the counter
+/// already in `header` and trying at most `max_iterations` counters. Bytes
+/// 0-23 and the challenge half are left as they are.
+pub fn mine_64b(header: &[u8; 64], target: &Target, max_iterations: u64) -> Option<[u8; 64]> {
+    mine_64b_with_progress(header, target, max_iterations, 0, |_| true)
+}
+
+/// [`mine_64b`], calling `progress` with the number of counters tried after
+/// every `interval` of them (never, if `interval` is 0). The search stops
+/// early if `progress` returns false.
+pub fn mine_64b_with_progress(
+    header: &[u8; 64],
+    target: &Target,
+    max_iterations: u64,
+    interval: u64,
+    mut progress: impl FnMut(u64) -> bool,
+) -> Option<[u8; 64]> {
+    let mut header = *header;
+    let start = u64::from_be_bytes(header[COUNTER_START..COUNTER_END].try_into().unwrap());
+    for i in 0..max_iterations {
+        if interval > 0 && i > 0 && i % interval == 0 && !progress(i) {
+            return None;
+        }
+        let counter = start.wrapping_add(i);
+        header[COUNTER_START..COUNTER_END].copy_from_slice(&counter.to_be_bytes());
+        if target.is_met_by(&hash_64b(&header)) {
+            return Some(header);
+        }
+    }
+    None
+}
+
+/// Counters a thread of [`mine_64b_parallel`] claims at a time.
+#[cfg(feature = "threads")]
+const PARALLEL_BATCH: u64 = 1 << 10;
+
+/// [`mine_64b`] on up to `threads` rayon threads. The threads claim batches of
+/// counters in order and finish any batch below the best solution so far, so
+/// the result is the one `mine_64b` would give.
+#[cfg(feature = "threads")]
+pub fn mine_64b_parallel(
+    header: &[u8; 64],
+    target: &Target,
+    max_iterations: u64,
+    threads: usize,
+) -> Option<[u8; 64]> {
+    use core::sync::atomic::{AtomicU64, Ordering};
+
+    if threads <= 1 {
+        return mine_64b(header, target, max_iterations);
+    }
+    let start = u64::from_be_bytes(header[COUNTER_START..COUNTER_END].try_into().unwrap());
+    let at_offset = |offset: u64| {
+        let mut header = *header;
+        header[COUNTER_START..COUNTER_END]
+            .copy_from_slice(&start.wrapping_add(offset).to_be_bytes());
+        header
+    };
+
+    let next_batch = AtomicU64::new(0);
+    // offset of the lowest solution found, u64::MAX for none
+    let best = AtomicU64::new(u64::MAX);
+    rayon::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|_| {
+                loop {
+                    let batch = next_batch.fetch_add(PARALLEL_BATCH, Ordering::Relaxed);
+                    if batch >= max_iterations || batch >= best.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    let len = PARALLEL_BATCH.min(max_iterations - batch);
+                    if let Some(solved) = mine_64b(&at_offset(batch), target, len) {
+                        let counter = u64::from_be_bytes(
+                            solved[COUNTER_START..COUNTER_END].try_into().unwrap(),
+                        );
+                        best.fetch_min(counter.wrapping_sub(start), Ordering::Relaxed);
+                    }
+                }
+            });
+        }
+    });
+
+    match best.into_inner() {
+        u64::MAX => None,
+        offset => Some(at_offset(offset)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pow::insert_nonce_64b;
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn test_hash_64b_matches_vec_api() {
+        let header = [0x11; 64];
+        let expected = crate::elementary_iteration_64b(header.to_vec()).unwrap();
+        assert_eq!(hash_64b(&header).to_vec(), expected);
+    }
+
+    #[test]
+    fn test_mine_64b() {
+        let target = Target::from_difficulty(16).unwrap();
+        let header = [0x42; 64];
+        let mut start = header;
+        start[COUNTER_START..COUNTER_END].fill(0);
+        let solved = mine_64b(&start, &target, 10_000).unwrap();
+        assert!(target.is_met_by(&hash_64b(&solved)));
+        assert_eq!(&solved[..COUNTER_START], &header[..COUNTER_START]);
+        assert_eq!(&solved[32..], &header[32..]);
+
+        // the low 32 bits of the counter are the WGSL nonce
+        let nonce = u32::from_be_bytes(solved[28..32].try_into().unwrap());
+        let mut expected = start;
+        insert_nonce_64b(&mut expected, nonce);
+        assert_eq!(solved, expected);
+    }
+
+    #[test]
+    fn test_mine_64b_gives_up() {
+        let impossible = Target::from_bytes([0; 32]);
+        assert_eq!(mine_64b(&[0; 64], &impossible, 10), None);
+    }
+
+    #[test]
+    fn test_mine_64b_with_progress() {
+        let impossible = Target::from_bytes([0; 32]);
+        let mut calls = Vec::new();
+        let result = mine_64b_with_progress(&[0; 64], &impossible, 100, 10, |n| {
+            calls.push(n);
+            calls.len() < 3
+        });
+        assert_eq!(result, None);
+        assert_eq!(calls, vec![10, 20, 30]);
+
+        // every counter is tried when no callback stops it
+        let mut calls = 0;
+        let result = mine_64b_with_progress(&[0; 64], &impossible, 25, 10, |_| {
+            calls += 1;
+            true
+        });
+        assert_eq!(result, None);
+        assert_eq!(calls, 2);
+    }
+
+    #[cfg(feature = "threads")]
+    #[test]
+    fn test_mine_64b_parallel_matches_mine_64b() {
+        let target = Target::from_difficulty(1000).unwrap();
+        // the first solution is in the second batch for seed 0, the first for 1
+        for seed in 0..2u8 {
+            let mut header = [seed; 64];
+            header[COUNTER_START..COUNTER_END].fill(0);
+            let expected = mine_64b(&header, &target, 50_000).unwrap();
+            for threads in [1, 4] {
+                assert_eq!(
+                    mine_64b_parallel(&header, &target, 50_000, threads),
+                    Some(expected)
+                );
+            }
+
+            // max_iterations is exact, even inside a batch
+            let counter =
+                u64::from_be_bytes(expected[COUNTER_START..COUNTER_END].try_into().unwrap());
+            assert_eq!(mine_64b_parallel(&header, &target, counter, 4), None);
+            assert_eq!(
+                mine_64b_parallel(&header, &target, counter + 1, 4),
+                Some(expected)
+            );
+        }
+
+        let impossible = Target::from_bytes([0; 32]);
+        assert_eq!(mine_64b_parallel(&[0; 64], &impossible, 3000, 3), None);
+    }
+}
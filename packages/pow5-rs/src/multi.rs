@@ -0,0 +1,261 @@
+// Variance-reduced proofs: k independent sub-solutions.
+//
+// The number of hashes needed to solve one challenge at difficulty D is
+// geometrically distributed with mean D and a standard deviation of nearly D,
+// so a challenge sized for 15 seconds takes over a minute about 1.8% of the
+// time. A `MultiProof` instead carries k solutions at difficulty D/k, each for
+// its own sub-challenge derived from the signed challenge and the part index.
+// The expected work is unchanged (k parts of D/k hashes each), but the total is
+// now a sum of k independent geometric variables:
+//
+//   mean                      D
+//   standard deviation        D / sqrt(k)
+//   P(work > t * D)           ~ e^(-kt) * sum_{j<k} (kt)^j / j!
+//
+// For t = 4 (a 15 second solve taking a minute) that is 1.8% at k = 1, 0.009%
+// at k = 4 and 1e-7 at k = 8. See `tail_probability`.
+//
+// Each part is an ordinary pow5-64b header, `nonce || sub_challenge`, so the
+// existing miners solve them unchanged.
+
+use crate::blake3_reference::blake3_reference_hash;
+use crate::challenge::push_field;
+use crate::difficulty::Target;
+use crate::mine::{hash_64b, mine_64b};
+
+const DOMAIN_TAG: &[u8] = b"keypears pow5-64b multi v1";
+const TAG_CHALLENGE: u8 = 0x01;
+const TAG_PARTS: u8 = 0x02;
+const TAG_INDEX: u8 = 0x03;
+
+const NONCE_SIZE: usize = 32;
+
+/// The most parts a proof may be split into.
+pub const MAX_PARTS: u8 = 64;
+
+/// The challenge half of part `index` of a proof in `parts` parts.
+pub fn sub_challenge(challenge: &[u8; 32], parts: u8, index: u8) -> [u8; 32] {
+    let mut input = DOMAIN_TAG.to_vec();
+    push_field(&mut input, TAG_CHALLENGE, challenge);
+    push_field(&mut input, TAG_PARTS, &[parts]);
+    push_field(&mut input, TAG_INDEX, &[index]);
+    blake3_reference_hash(input).try_into().unwrap()
+}
+
+/// The target each part must meet when a proof for `target` is split into
+/// `parts` parts.
+pub fn sub_target(target: &Target, parts: u8) -> Target {
+    target.saturating_mul(u64::from(parts))
+}
+
+/// The standard deviation of the work for a proof in `parts` parts, as a
+/// fraction of the mean.
+pub fn coefficient_of_variation(parts: u8) -> f64 {
+    1.0 / f64::from(parts).sqrt()
+}
+
+/// The probability that a proof in `parts` parts takes more than `multiple`
+/// times the expected work. This treats each part as exponential rather than
+/// geometric, which is exact in the limit of high difficulty.
+pub fn tail_probability(parts: u8, multiple: f64) -> f64 {
+    let rate = f64::from(parts) * multiple;
+    let mut term = 1.0;
+    let mut sum = 0.0;
+    for j in 0..parts {
+        sum += term;
+        term *= rate / f64::from(j + 1);
+    }
+    (-rate).exp() * sum
+}
+
+/// A solution made of independent parts, all bound to the same challenge.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MultiProof {
+    nonces: Vec<[u8; 32]>,
+}
+
+impl MultiProof {
+    /// Solve the challenge in `header[32..]` at `target` in `parts` parts.
+    /// Each part starts from the nonce in `header[..32]`.
+    pub fn mint(header: &[u8; 64], target: &Target, parts: u8) -> Result<Self, String> {
+        check_parts(parts)?;
+        let challenge: &[u8; 32] = header[NONCE_SIZE..].try_into().unwrap();
+        let target = sub_target(target, parts);
+        let mut nonces = Vec::with_capacity(parts as usize);
+        for index in 0..parts {
+            let mut part = *header;
+            part[NONCE_SIZE..].copy_from_slice(&sub_challenge(challenge, parts, index));
+            let solved =
+                mine_64b(&part, &target, u64::MAX).ok_or("no solution found".to_string())?;
+            nonces.push(solved[..NONCE_SIZE].try_into().unwrap());
+        }
+        Ok(MultiProof { nonces })
+    }
+
+    pub fn parts(&self) -> u8 {
+        self.nonces.len() as u8
+    }
+
+    pub fn nonces(&self) -> &[[u8; 32]] {
+        &self.nonces
+    }
+
+    /// Whether every part solves its sub-challenge of `header[32..]` at the
+    /// sub-target for `target`.
+    pub fn is_valid(&self, header: &[u8; 64], target: &Target) -> bool {
+        let challenge: &[u8; 32] = header[NONCE_SIZE..].try_into().unwrap();
+        let parts = self.parts();
+        let target = sub_target(target, parts);
+        self.nonces.iter().zip(0..parts).all(|(nonce, index)| {
+            let mut part = [0u8; 64];
+            part[..NONCE_SIZE].copy_from_slice(nonce);
+            part[NONCE_SIZE..].copy_from_slice(&sub_challenge(challenge, parts, index));
+            target.is_met_by(&hash_64b(&part))
+        })
+    }
+
+    /// Serialize as the number of parts (one byte) followed by the nonces.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(1 + self.nonces.len() * NONCE_SIZE);
+        bytes.push(self.parts());
+        for nonce in &self.nonces {
+            bytes.extend_from_slice(nonce);
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        let (&parts, rest) = bytes.split_first().ok_or("proof is empty")?;
+        check_parts(parts)?;
+        if rest.len() != parts as usize * NONCE_SIZE {
+            return Err(format!(
+                "expected {} bytes of nonces, got {}",
+                parts as usize * NONCE_SIZE,
+                rest.len()
+            ));
+        }
+        let nonces = rest
+            .chunks_exact(NONCE_SIZE)
+            .map(|chunk| chunk.try_into().unwrap())
+            .collect();
+        Ok(MultiProof { nonces })
+    }
+}
+
+fn check_parts(parts: u8) -> Result<(), String> {
+    if parts == 0 || parts > MAX_PARTS {
+        return Err(format!("parts must be between 1 and {}", MAX_PARTS));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::challenge::test_support::test_options;
+    use crate::challenge::{
+        Challenge, ChallengeError, ChallengeKey, ChallengePurpose, ChallengeVerifier,
+        ChallengeVersion, SignatureAlgorithm,
+    };
+    use crate::replay::{MemorySpentStore, consume_multi};
+
+    fn header() -> [u8; 64] {
+        let mut header = [0u8; 64];
+        header[NONCE_SIZE..].copy_from_slice(&[0x5a; 32]);
+        header
+    }
+
+    #[test]
+    fn test_mint_and_verify() {
+        let target = Target::from_difficulty(64).unwrap();
+        let proof = MultiProof::mint(&header(), &target, 4).unwrap();
+        assert_eq!(proof.parts(), 4);
+        assert!(proof.is_valid(&header(), &target));
+
+        // parts are bound to their index and to the challenge
+        let mut swapped = proof.clone();
+        swapped.nonces.swap(0, 1);
+        assert!(!swapped.is_valid(&header(), &target));
+        let mut other = header();
+        other[63] ^= 1;
+        assert!(!proof.is_valid(&other, &target));
+
+        // and to the number of parts
+        let mut truncated = proof.clone();
+        truncated.nonces.pop();
+        assert!(!truncated.is_valid(&header(), &target));
+
+        assert!(MultiProof::mint(&header(), &target, 0).is_err());
+        assert!(MultiProof::mint(&header(), &target, MAX_PARTS + 1).is_err());
+    }
+
+    #[test]
+    fn test_bytes_round_trip() {
+        let target = Target::from_difficulty(8).unwrap();
+        let proof = MultiProof::mint(&header(), &target, 3).unwrap();
+        let bytes = proof.to_bytes();
+        assert_eq!(bytes.len(), 1 + 3 * 32);
+        assert_eq!(MultiProof::from_bytes(&bytes), Ok(proof));
+
+        assert!(MultiProof::from_bytes(&[]).is_err());
+        assert!(MultiProof::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(MultiProof::from_bytes(&[0]).is_err());
+    }
+
+    #[test]
+    fn test_variance_math() {
+        assert_eq!(coefficient_of_variation(1), 1.0);
+        assert_eq!(coefficient_of_variation(4), 0.5);
+
+        // a single solve is exponential
+        assert!((tail_probability(1, 4.0) - (-4.0f64).exp()).abs() < 1e-12);
+        assert!((tail_probability(4, 4.0) - 9.31e-5).abs() < 1e-7);
+        assert!(tail_probability(8, 4.0) < 1.1e-7);
+        // half the time every proof takes at most about the mean
+        assert!((tail_probability(64, 1.0) - 0.5).abs() < 0.05);
+    }
+
+    #[test]
+    fn test_verify_multi() {
+        let key = ChallengeKey::new([1; 32]);
+        let challenge = Challenge {
+            purpose: ChallengePurpose::Channel,
+            algorithm: SignatureAlgorithm::HmacSha256,
+            key_id: 0,
+            header: header(),
+            target: Target::from_difficulty(32).unwrap(),
+            expires_at: 1_000,
+            sender_address: None,
+            recipient_address: None,
+        };
+        let signature = key.sign(&challenge, ChallengeVersion::V2);
+        let proof = MultiProof::mint(&challenge.header, &challenge.target, 4).unwrap();
+
+        let mut options = test_options(ChallengePurpose::Channel, 0);
+        options.min_difficulties.channel = 32;
+        assert_eq!(
+            key.verify_multi(&challenge, &signature, &options, &proof),
+            Ok(ChallengeVersion::V2)
+        );
+
+        let mut forged = proof.clone();
+        forged.nonces[3] = [0xee; 32];
+        assert_eq!(
+            key.verify_multi(&challenge, &signature, &options, &forged),
+            Err(ChallengeError::HashDoesNotMeetTarget)
+        );
+
+        let store = MemorySpentStore::new();
+        assert_eq!(consume_multi(&challenge, 0, &store), Ok(()));
+        // a second set of parts for the same challenge is still a replay
+        let again = MultiProof::mint(&challenge.header, &challenge.target, 2).unwrap();
+        assert_eq!(
+            key.verify_multi(&challenge, &signature, &options, &again),
+            Ok(ChallengeVersion::V2)
+        );
+        assert_eq!(
+            consume_multi(&challenge, 0, &store),
+            Err(ChallengeError::AlreadySpent)
+        );
+    }
+}
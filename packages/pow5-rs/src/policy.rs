@@ -0,0 +1,430 @@
+// Recipient difficulty policy.
+//
+// Recipients choose how much work it costs to reach them: a difficulty for
+// opening a channel and one for each message (`channelDifficulty` and
+// `messageDifficulty` in the webapp), plus overrides for particular senders
+// and domains and lists of senders and domains they trust. The server sets the
+// defaults, the minimums no setting can go below and a maximum no setting can
+// go above. Registration and login are not the recipient's to configure and
+// always use the server default.
+//
+// For channel and message challenges the first rule that matches wins, from
+// most to least specific:
+//
+//   1. an override for the sender's address
+//   2. the sender's address is trusted: the server minimum
+//   3. an override for the sender's domain
+//   4. the sender's domain is trusted: the server minimum
+//   5. the recipient's own setting
+//   6. the server default
+//
+// The result is then clamped to the server minimum and maximum, and the
+// resolution records both the rule and whether it was clamped.
+
+use crate::challenge::{ChallengePurpose, MinDifficulties};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+/// A difficulty for each purpose a recipient can configure. `None` defers to
+/// the next rule.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DifficultyOverride {
+    pub channel: Option<u64>,
+    pub message: Option<u64>,
+}
+
+impl DifficultyOverride {
+    fn get(&self, purpose: ChallengePurpose) -> Option<u64> {
+        match purpose {
+            ChallengePurpose::Channel => self.channel,
+            ChallengePurpose::Message => self.message,
+            ChallengePurpose::Register | ChallengePurpose::Login => None,
+        }
+    }
+}
+
+/// What one recipient has configured.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RecipientSettings {
+    /// The recipient's own `channelDifficulty` and `messageDifficulty`.
+    pub difficulty: DifficultyOverride,
+    /// Overrides keyed by sender address.
+    pub sender_overrides: HashMap<String, DifficultyOverride>,
+    /// Overrides keyed by sender domain.
+    pub domain_overrides: HashMap<String, DifficultyOverride>,
+    /// Senders who only ever pay the server minimum.
+    pub trusted_senders: HashSet<String>,
+    /// Domains whose senders only ever pay the server minimum.
+    pub trusted_domains: HashSet<String>,
+}
+
+/// The rule that decided a difficulty.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rule {
+    SenderOverride,
+    TrustedSender,
+    DomainOverride,
+    TrustedDomain,
+    RecipientSetting,
+    ServerDefault,
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rule::SenderOverride => write!(f, "override for this sender"),
+            Rule::TrustedSender => write!(f, "trusted sender"),
+            Rule::DomainOverride => write!(f, "override for the sender's domain"),
+            Rule::TrustedDomain => write!(f, "trusted domain"),
+            Rule::RecipientSetting => write!(f, "recipient setting"),
+            Rule::ServerDefault => write!(f, "server default"),
+        }
+    }
+}
+
+/// Which server bound a difficulty was moved to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Clamp {
+    Minimum,
+    Maximum,
+}
+
+/// An effective difficulty and why.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Resolution {
+    pub difficulty: u64,
+    pub rule: Rule,
+    /// Set if the rule's difficulty was outside the server bounds.
+    pub clamped: Option<Clamp>,
+}
+
+impl fmt::Display for Resolution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.difficulty, self.rule)?;
+        match self.clamped {
+            Some(Clamp::Minimum) => write!(f, ", raised to the server minimum"),
+            Some(Clamp::Maximum) => write!(f, ", lowered to the server maximum"),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The difficulty for each purpose when no recipient rule applies. Unlike
+/// [`MinDifficulties`], these are not floors a verifier enforces.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DefaultDifficulties {
+    pub register: u64,
+    pub login: u64,
+    pub channel: u64,
+    pub message: u64,
+}
+
+impl DefaultDifficulties {
+    pub const fn get(&self, purpose: ChallengePurpose) -> u64 {
+        match purpose {
+            ChallengePurpose::Register => self.register,
+            ChallengePurpose::Login => self.login,
+            ChallengePurpose::Channel => self.channel,
+            ChallengePurpose::Message => self.message,
+        }
+    }
+}
+
+impl Default for DefaultDifficulties {
+    /// The constants in `pow.server.ts`.
+    fn default() -> Self {
+        DefaultDifficulties {
+            register: 70_000_000,
+            login: 7_000_000,
+            channel: 70_000_000,
+            message: 7_000_000,
+        }
+    }
+}
+
+/// Server-wide difficulty rules.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DifficultyPolicy {
+    pub defaults: DefaultDifficulties,
+    /// The verifier's floors, which no rule can go below.
+    pub minimums: MinDifficulties,
+    /// No recipient setting or override can go above this.
+    pub maximum: u64,
+}
+
+impl Default for DifficultyPolicy {
+    /// The constants in `pow.server.ts`, with a maximum of 7 billion (about
+    /// 20 minutes at the message rate).
+    fn default() -> Self {
+        DifficultyPolicy {
+            defaults: DefaultDifficulties::default(),
+            minimums: MinDifficulties::default(),
+            maximum: 7_000_000_000,
+        }
+    }
+}
+
+impl DifficultyPolicy {
+    /// Check that every default lies between its minimum and the maximum.
+    pub fn validate(&self) -> Result<(), String> {
+        for purpose in [
+            ChallengePurpose::Register,
+            ChallengePurpose::Login,
+            ChallengePurpose::Channel,
+            ChallengePurpose::Message,
+        ] {
+            let min = self.minimums.get(purpose);
+            let default = self.defaults.get(purpose);
+            if min == 0 || min > default || default > self.maximum {
+                return Err(format!(
+                    "{} difficulty must satisfy 0 < minimum <= default <= maximum",
+                    purpose
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// The difficulty `sender` must solve to reach the recipient with
+    /// `settings` for `purpose`. `sender` is `None` when unknown, in which case
+    /// only the recipient's setting and the server default apply.
+    pub fn resolve(
+        &self,
+        sender: Option<&str>,
+        settings: &RecipientSettings,
+        purpose: ChallengePurpose,
+    ) -> Resolution {
+        let minimum = self.minimums.get(purpose);
+        let (rule, difficulty) = match purpose {
+            ChallengePurpose::Register | ChallengePurpose::Login => {
+                (Rule::ServerDefault, self.defaults.get(purpose))
+            }
+            ChallengePurpose::Channel | ChallengePurpose::Message => {
+                self.select_rule(sender, settings, purpose, minimum)
+            }
+        };
+
+        if difficulty < minimum {
+            Resolution {
+                difficulty: minimum,
+                rule,
+                clamped: Some(Clamp::Minimum),
+            }
+        } else if difficulty > self.maximum {
+            Resolution {
+                difficulty: self.maximum,
+                rule,
+                clamped: Some(Clamp::Maximum),
+            }
+        } else {
+            Resolution {
+                difficulty,
+                rule,
+                clamped: None,
+            }
+        }
+    }
+
+    fn select_rule(
+        &self,
+        sender: Option<&str>,
+        settings: &RecipientSettings,
+        purpose: ChallengePurpose,
+        minimum: u64,
+    ) -> (Rule, u64) {
+        if let Some(sender) = sender {
+            if let Some(difficulty) = settings
+                .sender_overrides
+                .get(sender)
+                .and_then(|o| o.get(purpose))
+            {
+                return (Rule::SenderOverride, difficulty);
+            }
+            if settings.trusted_senders.contains(sender) {
+                return (Rule::TrustedSender, minimum);
+            }
+            if let Some((_, domain)) = sender.split_once('@') {
+                if let Some(difficulty) = settings
+                    .domain_overrides
+                    .get(domain)
+                    .and_then(|o| o.get(purpose))
+                {
+                    return (Rule::DomainOverride, difficulty);
+                }
+                if settings.trusted_domains.contains(domain) {
+                    return (Rule::TrustedDomain, minimum);
+                }
+            }
+        }
+        match settings.difficulty.get(purpose) {
+            Some(difficulty) => (Rule::RecipientSetting, difficulty),
+            None => (Rule::ServerDefault, self.defaults.get(purpose)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALICE: &str = "alice@keypears.com";
+    const MALLORY: &str = "mallory@keypears.com";
+    const EVE: &str = "eve@spam.example";
+
+    fn channel(difficulty: u64) -> DifficultyOverride {
+        DifficultyOverride {
+            channel: Some(difficulty),
+            message: None,
+        }
+    }
+
+    /// Settings where every rule has something to say about ALICE's channel
+    /// difficulty.
+    fn full_settings() -> RecipientSettings {
+        RecipientSettings {
+            difficulty: channel(500_000_000),
+            sender_overrides: HashMap::from([(ALICE.to_string(), channel(10_000_000))]),
+            domain_overrides: HashMap::from([("keypears.com".to_string(), channel(20_000_000))]),
+            trusted_senders: HashSet::from([ALICE.to_string()]),
+            trusted_domains: HashSet::from(["keypears.com".to_string()]),
+        }
+    }
+
+    fn resolve(settings: &RecipientSettings, sender: Option<&str>) -> Resolution {
+        DifficultyPolicy::default().resolve(sender, settings, ChallengePurpose::Channel)
+    }
+
+    #[test]
+    fn test_server_default() {
+        let policy = DifficultyPolicy::default();
+        let settings = RecipientSettings::default();
+        let cases = [
+            (ChallengePurpose::Channel, 70_000_000),
+            (ChallengePurpose::Message, 7_000_000),
+        ];
+        for (purpose, difficulty) in cases {
+            assert_eq!(
+                policy.resolve(Some(ALICE), &settings, purpose),
+                Resolution {
+                    difficulty,
+                    rule: Rule::ServerDefault,
+                    clamped: None,
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn test_register_and_login_ignore_recipient() {
+        let policy = DifficultyPolicy::default();
+        let mut settings = full_settings();
+        settings.difficulty.message = Some(1);
+        for (purpose, difficulty) in [
+            (ChallengePurpose::Register, 70_000_000),
+            (ChallengePurpose::Login, 7_000_000),
+        ] {
+            let resolution = policy.resolve(Some(ALICE), &settings, purpose);
+            assert_eq!(resolution.rule, Rule::ServerDefault);
+            assert_eq!(resolution.difficulty, difficulty);
+        }
+    }
+
+    #[test]
+    fn test_precedence() {
+        // peel the rules off one at a time, most specific first
+        let mut settings = full_settings();
+        assert_eq!(resolve(&settings, Some(ALICE)).rule, Rule::SenderOverride);
+        assert_eq!(resolve(&settings, Some(ALICE)).difficulty, 10_000_000);
+
+        settings.sender_overrides.clear();
+        assert_eq!(resolve(&settings, Some(ALICE)).rule, Rule::TrustedSender);
+        assert_eq!(resolve(&settings, Some(ALICE)).difficulty, 7_000_000);
+
+        settings.trusted_senders.clear();
+        assert_eq!(resolve(&settings, Some(ALICE)).rule, Rule::DomainOverride);
+        assert_eq!(resolve(&settings, Some(ALICE)).difficulty, 20_000_000);
+
+        settings.domain_overrides.clear();
+        assert_eq!(resolve(&settings, Some(ALICE)).rule, Rule::TrustedDomain);
+        assert_eq!(resolve(&settings, Some(ALICE)).difficulty, 7_000_000);
+
+        settings.trusted_domains.clear();
+        assert_eq!(resolve(&settings, Some(ALICE)).rule, Rule::RecipientSetting);
+        assert_eq!(resolve(&settings, Some(ALICE)).difficulty, 500_000_000);
+
+        settings.difficulty.channel = None;
+        assert_eq!(resolve(&settings, Some(ALICE)).rule, Rule::ServerDefault);
+        assert_eq!(resolve(&settings, Some(ALICE)).difficulty, 70_000_000);
+    }
+
+    #[test]
+    fn test_rules_only_match_their_sender() {
+        let settings = full_settings();
+        // same domain, different sender
+        assert_eq!(resolve(&settings, Some(MALLORY)).rule, Rule::DomainOverride);
+        // different domain
+        assert_eq!(resolve(&settings, Some(EVE)).rule, Rule::RecipientSetting);
+        // unknown sender
+        assert_eq!(resolve(&settings, None).rule, Rule::RecipientSetting);
+        // not an address, so no domain rules
+        let mut settings = full_settings();
+        settings
+            .domain_overrides
+            .insert("alice".to_string(), channel(1));
+        assert_eq!(
+            resolve(&settings, Some("alice")).rule,
+            Rule::RecipientSetting
+        );
+    }
+
+    #[test]
+    fn test_override_for_other_purpose_falls_through() {
+        let mut settings = full_settings();
+        settings.trusted_senders.clear();
+        let policy = DifficultyPolicy::default();
+        // the sender override only sets a channel difficulty
+        let resolution = policy.resolve(Some(ALICE), &settings, ChallengePurpose::Message);
+        assert_eq!(resolution.rule, Rule::TrustedDomain);
+    }
+
+    #[test]
+    fn test_clamping() {
+        let mut settings = RecipientSettings::default();
+        settings
+            .sender_overrides
+            .insert(EVE.to_string(), channel(1_000));
+        assert_eq!(
+            resolve(&settings, Some(EVE)),
+            Resolution {
+                difficulty: 7_000_000,
+                rule: Rule::SenderOverride,
+                clamped: Some(Clamp::Minimum),
+            }
+        );
+
+        settings.difficulty.channel = Some(u64::MAX);
+        let resolution = resolve(&settings, Some(ALICE));
+        assert_eq!(resolution.difficulty, 7_000_000_000);
+        assert_eq!(resolution.clamped, Some(Clamp::Maximum));
+        assert_eq!(
+            resolution.to_string(),
+            "7000000000 (recipient setting), lowered to the server maximum"
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(DifficultyPolicy::default().validate().is_ok());
+        let policy = DifficultyPolicy {
+            maximum: 1_000,
+            ..DifficultyPolicy::default()
+        };
+        assert!(policy.validate().is_err());
+        let mut policy = DifficultyPolicy::default();
+        policy.minimums.message = 0;
+        assert!(policy.validate().is_err());
+        let mut policy = DifficultyPolicy::default();
+        policy.defaults.channel = 1;
+        assert!(policy.validate().is_err());
+    }
+}
@@ -0,0 +1,133 @@
+// Array-based pow5 hashing, with no allocation.
+//
+// These are the hash functions behind the `Vec` API at the crate root, which
+// checks sizes and copies into arrays before calling them. They are always
+// compiled, so a verifier can check a pow5 hash without `std` or `alloc`.
+
+use crate::blake3_reference;
+use crate::{HASH_SIZE, HEADER_SIZE_64B, HEADER_SIZE_217A};
+use crate::{NONCE_END_64B, NONCE_END_217A, NONCE_START_64B, NONCE_START_217A};
+use crate::{WORK_PAR_END_217A, WORK_PAR_START_217A};
+use core::fmt;
+
+/// A buffer that is not the size its field must be.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SizeError {
+    pub name: &'static str,
+    pub expected: usize,
+    pub actual: usize,
+}
+
+impl fmt::Display for SizeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} is not the correct size: expected {}, got {}",
+            self.name, self.expected, self.actual
+        )
+    }
+}
+
+/// Copy `bytes` into an array, or say which field was the wrong size.
+pub fn to_array<const N: usize>(name: &'static str, bytes: &[u8]) -> Result<[u8; N], SizeError> {
+    bytes.try_into().map_err(|_| SizeError {
+        name,
+        expected: N,
+        actual: bytes.len(),
+    })
+}
+
+// The ASIC-resistant matmul shared by both header formats: hash the header
+// hash 32 more times, multiply and add each new column against the header
+// hash, and hash the 128-byte big-endian row that results.
+fn matmul(header_hash: &[u8; HASH_SIZE]) -> [u8; HASH_SIZE] {
+    let mut working_column = *header_hash;
+    let mut row = [0u32; HASH_SIZE];
+    for cell in row.iter_mut() {
+        working_column = blake3_reference::hash(&working_column);
+        for (a, c) in header_hash.iter().zip(working_column) {
+            *cell += (*a as u32) * (c as u32);
+        }
+    }
+
+    let mut pre_hash = [0u8; HASH_SIZE * 4];
+    for (chunk, cell) in pre_hash.chunks_exact_mut(4).zip(row) {
+        chunk.copy_from_slice(&cell.to_be_bytes());
+    }
+    blake3_reference::hash(&pre_hash)
+}
+
+/// work_par of a 217-byte header (earthbucks format).
+pub fn get_work_par_217a(header: &[u8; HEADER_SIZE_217A]) -> [u8; HASH_SIZE] {
+    matmul(&blake3_reference::hash(header))
+}
+
+/// The block id of a 217-byte header: work_par inserted into the header, then
+/// double-hashed.
+pub fn elementary_iteration_217a(header: &[u8; HEADER_SIZE_217A]) -> [u8; HASH_SIZE] {
+    let mut working_header = *header;
+    working_header[WORK_PAR_START_217A..WORK_PAR_END_217A]
+        .copy_from_slice(&get_work_par_217a(header));
+    blake3_reference::hash(&blake3_reference::hash(&working_header))
+}
+
+/// Write a 4-byte nonce into bytes 117-121 of a 217-byte header.
+pub fn insert_nonce_217a(header: &mut [u8; HEADER_SIZE_217A], nonce: u32) {
+    header[NONCE_START_217A..NONCE_END_217A].copy_from_slice(&nonce.to_be_bytes());
+}
+
+/// The matmul work of a 64-byte header.
+pub fn matmul_work_64b(header: &[u8; HEADER_SIZE_64B]) -> [u8; HASH_SIZE] {
+    matmul(&blake3_reference::hash(header))
+}
+
+/// The pow5-64b hash of a 64-byte header: the matmul work, double-hashed.
+pub fn elementary_iteration_64b(header: &[u8; HEADER_SIZE_64B]) -> [u8; HASH_SIZE] {
+    blake3_reference::hash(&blake3_reference::hash(&matmul_work_64b(header)))
+}
+
+/// Write a nonce into bytes 28-31 of a 64-byte header, the bytes the WGSL
+/// miner iterates.
+pub fn insert_nonce_64b(header: &mut [u8; HEADER_SIZE_64B], nonce: u32) {
+    header[NONCE_END_64B - 4..NONCE_END_64B].copy_from_slice(&nonce.to_be_bytes());
+}
+
+/// Replace the full 32-byte nonce (bytes 0-31) of a 64-byte header.
+pub fn set_nonce_64b(header: &mut [u8; HEADER_SIZE_64B], nonce: &[u8; 32]) {
+    header[NONCE_START_64B..NONCE_END_64B].copy_from_slice(nonce);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches_vec_api() {
+        let header = [0x11; HEADER_SIZE_64B];
+        assert_eq!(
+            hex::encode(elementary_iteration_64b(&header)),
+            "b5906d01328e86064b2a4783d0fc5f512fb1f2f923b3a869575482c0904fba44"
+        );
+        assert_eq!(
+            hex::encode(get_work_par_217a(&[0; HEADER_SIZE_217A])),
+            "6fe9eddc39bb4183c44853c41876801be94a138ea9adea89f40a08442d2f79b8"
+        );
+
+        let mut nonced = header;
+        insert_nonce_64b(&mut nonced, 0x01020304);
+        assert_eq!(nonced[24..32], [0x11, 0x11, 0x11, 0x11, 1, 2, 3, 4]);
+        set_nonce_64b(&mut nonced, &[0; 32]);
+        assert_eq!(nonced[..32], [0; 32]);
+        assert_eq!(nonced[32..], header[32..]);
+    }
+
+    #[test]
+    fn test_size_error() {
+        assert_eq!(to_array::<4>("nonce", &[1, 2, 3, 4]), Ok([1, 2, 3, 4]));
+        let err = to_array::<64>("header", &[0; 63]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "header is not the correct size: expected 64, got 63"
+        );
+    }
+}
@@ -0,0 +1,496 @@
+// Replay prevention for solved challenges.
+//
+// Challenges are stateless, so nothing stops a client from submitting the same
+// solved header twice before it expires. Every accepted solution is recorded
+// in a spent-token store, keyed by the SHA-256 of the solved header (the same
+// id as the `used_pow` table in the webapp), until the challenge expires.
+// Expired entries are cleaned up lazily as new ones are added.
+
+use crate::challenge::{Challenge, ChallengeError};
+use crate::merkle::MerkleProof;
+use sha2::{Digest, Sha256};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+const RECORD_SIZE: usize = 32 + 8;
+
+/// The spent-token id of a solved header: its SHA-256 hash.
+pub fn spent_id(solved_header: &[u8; 64]) -> [u8; 32] {
+    Sha256::digest(solved_header).into()
+}
+
+/// Records solutions that have been used.
+pub trait SpentStore {
+    /// Atomically check that `id` is not spent and mark it spent until
+    /// `expires_at` (ms since the Unix epoch). Returns `Ok(false)` if it was
+    /// already spent.
+    fn mark_spent(&self, id: [u8; 32], expires_at: u64, now: u64) -> Result<bool, String>;
+}
+
+/// Record a verified challenge's solution as spent until the challenge
+/// expires. Fails with [`ChallengeError::AlreadySpent`] if it already was.
+pub fn consume(
+    challenge: &Challenge,
+    now: u64,
+    store: &dyn SpentStore,
+) -> Result<(), ChallengeError> {
+    mark_challenge_spent(store, spent_id(&challenge.header), challenge, now)
+}
+
+/// The spent-token id of one message of a batch: the SHA-256 of the solved
+/// header followed by the leaf index (u32, big-endian). Every message of a
+/// batch shares the solved header, so each is consumed on its own.
+pub fn batch_spent_id(solved_header: &[u8; 64], leaf_index: u32) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(solved_header);
+    hasher.update(leaf_index.to_be_bytes());
+    hasher.finalize().into()
+}
+
+/// Record one verified message of a batch as spent until the challenge
+/// expires, like [`consume`].
+pub fn consume_batch_member(
+    challenge: &Challenge,
+    proof: &MerkleProof,
+    now: u64,
+    store: &dyn SpentStore,
+) -> Result<(), ChallengeError> {
+    let id = batch_spent_id(&challenge.header, proof.leaf_index());
+    mark_challenge_spent(store, id, challenge, now)
+}
+
+/// Record a challenge solved with a
+/// [`MultiProof`](crate::multi::MultiProof) as spent until it expires.
+/// The id covers the challenge half of the header, not the sub-solutions, so
+/// each challenge pays for one action however many proofs are mined for it.
+pub fn consume_multi(
+    challenge: &Challenge,
+    now: u64,
+    store: &dyn SpentStore,
+) -> Result<(), ChallengeError> {
+    let id = Sha256::digest(&challenge.header[32..]).into();
+    mark_challenge_spent(store, id, challenge, now)
+}
+
+fn mark_challenge_spent(
+    store: &dyn SpentStore,
+    id: [u8; 32],
+    challenge: &Challenge,
+    now: u64,
+) -> Result<(), ChallengeError> {
+    match store.mark_spent(id, challenge.expires_at, now) {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(ChallengeError::AlreadySpent),
+        Err(err) => Err(ChallengeError::SpentStore(err)),
+    }
+}
+
+#[derive(Default)]
+struct SpentSet {
+    entries: HashMap<[u8; 32], u64>,
+    // min-heap of expiries, so cleanup only looks at entries that are due
+    expiries: BinaryHeap<Reverse<(u64, [u8; 32])>>,
+}
+
+impl SpentSet {
+    fn remove_expired(&mut self, now: u64) -> usize {
+        let mut removed = 0;
+        while let Some(Reverse((expires_at, id))) = self.expiries.peek().copied() {
+            if expires_at >= now {
+                break;
+            }
+            self.expiries.pop();
+            self.entries.remove(&id);
+            removed += 1;
+        }
+        removed
+    }
+
+    fn is_spent(&self, id: &[u8; 32], now: u64) -> bool {
+        matches!(self.entries.get(id), Some(&expires_at) if expires_at >= now)
+    }
+
+    fn insert(&mut self, id: [u8; 32], expires_at: u64) {
+        self.entries.insert(id, expires_at);
+        self.expiries.push(Reverse((expires_at, id)));
+    }
+}
+
+/// An in-memory spent-token store for a single process.
+#[derive(Default)]
+pub struct MemorySpentStore {
+    set: Mutex<SpentSet>,
+}
+
+impl MemorySpentStore {
+    pub fn new() -> Self {
+        MemorySpentStore::default()
+    }
+
+    /// Number of entries currently held, including any expired ones that
+    /// have not been cleaned up yet.
+    pub fn len(&self) -> usize {
+        self.set.lock().unwrap().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl SpentStore for MemorySpentStore {
+    fn mark_spent(&self, id: [u8; 32], expires_at: u64, now: u64) -> Result<bool, String> {
+        let mut set = self.set.lock().map_err(|_| "spent store lock poisoned")?;
+        set.remove_expired(now);
+        if set.is_spent(&id, now) {
+            return Ok(false);
+        }
+        set.insert(id, expires_at);
+        Ok(true)
+    }
+}
+
+struct FileState {
+    set: SpentSet,
+    file: File,
+    // records in the file that have since expired
+    dead_records: usize,
+}
+
+/// A spent-token store that survives restarts. Entries are appended to a log
+/// file and loaded back on open. The log is rewritten without expired entries
+/// once they outnumber the live ones.
+///
+/// One store owns a log: `open` takes an advisory lock on `<log>.lock`, held
+/// until the store is dropped, and fails if another store or process has it.
+pub struct FileSpentStore {
+    path: PathBuf,
+    state: Mutex<FileState>,
+    // holds the advisory lock
+    _lock: File,
+}
+
+impl FileSpentStore {
+    /// Open the log at `path`, creating it if needed, and load the entries
+    /// that have not expired by `now`.
+    pub fn open(path: impl AsRef<Path>, now: u64) -> Result<Self, String> {
+        let path = path.as_ref().to_path_buf();
+        let lock_path = sidecar(&path, ".lock");
+        let lock = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&lock_path)
+            .map_err(|err| format!("failed to open {}: {}", lock_path.display(), err))?;
+        lock.try_lock().map_err(|err| match err {
+            TryLockError::WouldBlock => format!("{} is in use by another store", path.display()),
+            TryLockError::Error(err) => {
+                format!("failed to lock {}: {}", lock_path.display(), err)
+            }
+        })?;
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(&path)
+            .map_err(|err| format!("failed to open {}: {}", path.display(), err))?;
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)
+            .map_err(|err| format!("failed to read {}: {}", path.display(), err))?;
+
+        // a torn final record from a crash mid-append is ignored
+        let mut set = SpentSet::default();
+        let mut dead_records = 0;
+        for record in bytes.chunks_exact(RECORD_SIZE) {
+            let id: [u8; 32] = record[..32].try_into().unwrap();
+            let expires_at = u64::from_be_bytes(record[32..].try_into().unwrap());
+            if expires_at >= now {
+                set.insert(id, expires_at);
+            } else {
+                dead_records += 1;
+            }
+        }
+
+        let store = FileSpentStore {
+            path,
+            state: Mutex::new(FileState {
+                set,
+                file,
+                dead_records,
+            }),
+            _lock: lock,
+        };
+        if bytes.len() % RECORD_SIZE != 0 || dead_records > 0 {
+            store.compact(now)?;
+        }
+        Ok(store)
+    }
+
+    /// Rewrite the log with only the entries that have not expired.
+    pub fn compact(&self, now: u64) -> Result<(), String> {
+        let mut state = self.state.lock().map_err(|_| "spent store lock poisoned")?;
+        self.compact_locked(&mut state, now)
+    }
+
+    fn compact_locked(&self, state: &mut FileState, now: u64) -> Result<(), String> {
+        state.set.remove_expired(now);
+        let mut bytes = Vec::with_capacity(state.set.entries.len() * RECORD_SIZE);
+        for (id, expires_at) in &state.set.entries {
+            bytes.extend_from_slice(id);
+            bytes.extend_from_slice(&expires_at.to_be_bytes());
+        }
+
+        // the new log and its rename reach the disk before it is appended to,
+        // so a crash leaves either the old log or the whole new one
+        let tmp_path = sidecar(&self.path, ".tmp");
+        write_synced(&tmp_path, &bytes)
+            .map_err(|err| format!("failed to write {}: {}", tmp_path.display(), err))?;
+        fs::rename(&tmp_path, &self.path)
+            .and_then(|_| sync_parent_dir(&self.path))
+            .map_err(|err| format!("failed to replace {}: {}", self.path.display(), err))?;
+        state.file = OpenOptions::new()
+            .append(true)
+            .open(&self.path)
+            .map_err(|err| format!("failed to open {}: {}", self.path.display(), err))?;
+        state.dead_records = 0;
+        Ok(())
+    }
+}
+
+// `<log><suffix>`, keeping the log's own extension so that logs differing
+// only by extension get their own lock and temporary files.
+fn sidecar(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+fn write_synced(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    let mut file = File::create(path)?;
+    file.write_all(bytes)?;
+    file.sync_all()
+}
+
+// Directories cannot be opened as files on Windows, where NTFS journals the
+// rename itself.
+fn sync_parent_dir(path: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        File::open(dir)?.sync_all()
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = path;
+        Ok(())
+    }
+}
+
+impl SpentStore for FileSpentStore {
+    fn mark_spent(&self, id: [u8; 32], expires_at: u64, now: u64) -> Result<bool, String> {
+        let mut state = self.state.lock().map_err(|_| "spent store lock poisoned")?;
+        state.dead_records += state.set.remove_expired(now);
+        if state.set.is_spent(&id, now) {
+            return Ok(false);
+        }
+
+        // persist before accepting, so a crash never forgets a spent token
+        let mut record = [0u8; RECORD_SIZE];
+        record[..32].copy_from_slice(&id);
+        record[32..].copy_from_slice(&expires_at.to_be_bytes());
+        state
+            .file
+            .write_all(&record)
+            .and_then(|_| state.file.sync_data())
+            .map_err(|err| format!("failed to append to {}: {}", self.path.display(), err))?;
+        state.set.insert(id, expires_at);
+
+        if state.dead_records > state.set.entries.len() {
+            self.compact_locked(&mut state, now)?;
+        }
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::challenge::test_support::{NOW, test_challenge, test_options};
+    use crate::challenge::{
+        CHALLENGE_EXPIRY_MS, ChallengeKey, ChallengePurpose, ChallengeVerifier, ChallengeVersion,
+    };
+    use std::sync::Arc;
+
+    fn temp_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "keypears-pow5-{}-{}.spent",
+            name,
+            std::process::id()
+        ));
+        remove_log(&path);
+        path
+    }
+
+    fn remove_log(path: &Path) {
+        let _ = fs::remove_file(path);
+        let _ = fs::remove_file(sidecar(path, ".lock"));
+    }
+
+    #[test]
+    fn test_spent_id_is_sha256_of_header() {
+        let id = spent_id(&[0; 64]);
+        assert_eq!(
+            hex::encode(id),
+            "f5a5fd42d16a20302798ef6ed309979b43003d2320d9f0e8ea9831a92759fb4b"
+        );
+    }
+
+    #[test]
+    fn test_memory_store_rejects_duplicates_until_expiry() {
+        let store = MemorySpentStore::new();
+        let expires_at = NOW + CHALLENGE_EXPIRY_MS;
+        assert_eq!(store.mark_spent([1; 32], expires_at, NOW), Ok(true));
+        assert_eq!(store.mark_spent([1; 32], expires_at, NOW + 1), Ok(false));
+        assert_eq!(store.mark_spent([2; 32], expires_at, NOW + 1), Ok(true));
+        assert_eq!(store.mark_spent([1; 32], expires_at, expires_at), Ok(false));
+
+        // after expiry the entries are cleaned up lazily
+        assert_eq!(store.mark_spent([3; 32], NOW * 2, expires_at + 1), Ok(true));
+        assert_eq!(store.len(), 1);
+    }
+
+    #[test]
+    fn test_memory_store_is_atomic() {
+        let store = Arc::new(MemorySpentStore::new());
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let store = Arc::clone(&store);
+                std::thread::spawn(move || store.mark_spent([7; 32], NOW + 1, NOW).unwrap())
+            })
+            .collect();
+        let accepted = handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .filter(|&accepted| accepted)
+            .count();
+        assert_eq!(accepted, 1);
+    }
+
+    #[test]
+    fn test_file_store_survives_reopen() {
+        let path = temp_path("reopen");
+        {
+            let store = FileSpentStore::open(&path, NOW).unwrap();
+            assert_eq!(store.mark_spent([1; 32], NOW + 10, NOW), Ok(true));
+            assert_eq!(store.mark_spent([2; 32], NOW + 1000, NOW), Ok(true));
+        }
+        {
+            let store = FileSpentStore::open(&path, NOW + 1).unwrap();
+            assert_eq!(store.mark_spent([1; 32], NOW + 10, NOW + 1), Ok(false));
+            assert_eq!(store.mark_spent([2; 32], NOW + 1000, NOW + 1), Ok(false));
+        }
+        {
+            // reopening after the first entry expired drops it from the log
+            let store = FileSpentStore::open(&path, NOW + 11).unwrap();
+            assert_eq!(fs::metadata(&path).unwrap().len(), RECORD_SIZE as u64);
+            assert_eq!(store.mark_spent([1; 32], NOW + 20, NOW + 11), Ok(true));
+            assert_eq!(store.mark_spent([2; 32], NOW + 1000, NOW + 11), Ok(false));
+        }
+        remove_log(&path);
+    }
+
+    #[test]
+    fn test_file_store_ignores_torn_record() {
+        let path = temp_path("torn");
+        {
+            let store = FileSpentStore::open(&path, NOW).unwrap();
+            assert_eq!(store.mark_spent([1; 32], NOW + 10, NOW), Ok(true));
+        }
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(&[0xab; 17]).unwrap();
+        drop(file);
+
+        let store = FileSpentStore::open(&path, NOW).unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().len(), RECORD_SIZE as u64);
+        assert_eq!(store.mark_spent([1; 32], NOW + 10, NOW), Ok(false));
+        remove_log(&path);
+    }
+
+    #[test]
+    fn test_file_store_is_locked() {
+        let path = temp_path("locked");
+        let store = FileSpentStore::open(&path, NOW).unwrap();
+        assert_eq!(store.mark_spent([1; 32], NOW + 10, NOW), Ok(true));
+        assert!(
+            FileSpentStore::open(&path, NOW)
+                .err()
+                .unwrap()
+                .contains("in use")
+        );
+
+        // compaction keeps the lock and the entries
+        store.compact(NOW).unwrap();
+        assert!(FileSpentStore::open(&path, NOW).is_err());
+        drop(store);
+        let store = FileSpentStore::open(&path, NOW).unwrap();
+        assert_eq!(store.mark_spent([1; 32], NOW + 10, NOW), Ok(false));
+        assert!(!sidecar(&path, ".tmp").exists());
+        drop(store);
+        remove_log(&path);
+    }
+
+    #[test]
+    fn test_file_stores_differing_by_extension() {
+        let log = temp_path("extension");
+        let other = log.with_extension("log");
+        remove_log(&other);
+        let store = FileSpentStore::open(&log, NOW).unwrap();
+        let other_store = FileSpentStore::open(&other, NOW).unwrap();
+        assert!(sidecar(&log, ".lock").exists());
+        assert!(sidecar(&other, ".lock").exists());
+
+        assert_eq!(store.mark_spent([1; 32], NOW + 10, NOW), Ok(true));
+        assert_eq!(other_store.mark_spent([1; 32], NOW + 10, NOW), Ok(true));
+        store.compact(NOW).unwrap();
+        other_store.compact(NOW).unwrap();
+        assert_eq!(fs::metadata(&log).unwrap().len(), RECORD_SIZE as u64);
+        assert_eq!(fs::metadata(&other).unwrap().len(), RECORD_SIZE as u64);
+        drop(store);
+        drop(other_store);
+        remove_log(&log);
+        remove_log(&other);
+    }
+
+    #[test]
+    fn test_verify_and_consume() {
+        let key = ChallengeKey::new([7; 32]);
+        let challenge = test_challenge(ChallengePurpose::Login);
+        let signature = key.sign(&challenge, ChallengeVersion::V2);
+        let options = test_options(ChallengePurpose::Login, NOW);
+        let store = MemorySpentStore::new();
+        assert_eq!(
+            key.verify_and_consume(&challenge, &signature, &options, &store),
+            Ok(ChallengeVersion::V2)
+        );
+        assert_eq!(
+            key.verify_and_consume(&challenge, &signature, &options, &store),
+            Err(ChallengeError::AlreadySpent)
+        );
+
+        // a different nonce is a different solution
+        let mut other = challenge.clone();
+        other.header[0] = 0;
+        assert_eq!(
+            key.verify_and_consume(&other, &signature, &options, &store),
+            Ok(ChallengeVersion::V2)
+        );
+    }
+}
@@ -0,0 +1,215 @@
+// Experimental memory-hard pow5-64b variant.
+//
+// The pow5 matmul is compute-bound: its whole state is 32 bytes times 32
+// rounds and fits in registers, so an ASIC can pack many cores with no memory
+// at all. This variant makes each hash depend on a scratchpad of a configurable
+// size, in the style of scrypt and Argon2d, so the cost of a hash includes the
+// memory to hold the scratchpad and the latency of reading it. All hashing uses
+// `blake3_reference_hash` on 32- and 64-byte inputs, like the rest of pow5.
+//
+//   seed     = BLAKE3(tag || memory_kib || reads || header)
+//   block[0] = seed
+//   block[i] = BLAKE3(block[i - 1] || block[j]),  j = block[i - 1] mod i
+//   acc      = block[n - 1]
+//   reads times:
+//     acc    = BLAKE3(acc || block[acc mod n])
+//   hash     = BLAKE3(BLAKE3(acc))
+//
+// Blocks are 32 bytes, so a 1 KiB scratchpad holds 32 of them. Every index is
+// read as a big-endian u64 from the first 8 bytes of the previous hash, so the
+// reads cannot be predicted before the previous hash is done. The parameters
+// are part of the seed, so hashes at different sizes are unrelated.
+//
+// This is not used for challenges. It exists so the whitepaper can compare the
+// cost of memory-bound work across phones, laptops and ASICs.
+
+use crate::blake3_reference::blake3_reference_hash;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+const DOMAIN_TAG: &[u8] = b"keypears pow5-64b scratchpad v1";
+const BLOCK_SIZE: usize = 32;
+
+/// The smallest scratchpad, 1 KiB.
+pub const MIN_MEMORY_KIB: u32 = 1;
+/// The largest scratchpad, 1 GiB.
+pub const MAX_MEMORY_KIB: u32 = 1 << 20;
+
+/// The size of the scratchpad and how many times it is read after filling.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ScratchpadParams {
+    memory_kib: u32,
+    reads: u32,
+}
+
+impl ScratchpadParams {
+    pub fn new(memory_kib: u32, reads: u32) -> Result<Self, String> {
+        if !(MIN_MEMORY_KIB..=MAX_MEMORY_KIB).contains(&memory_kib) {
+            return Err(format!(
+                "memory must be between {} and {} KiB",
+                MIN_MEMORY_KIB, MAX_MEMORY_KIB
+            ));
+        }
+        Ok(ScratchpadParams { memory_kib, reads })
+    }
+
+    pub fn memory_kib(&self) -> u32 {
+        self.memory_kib
+    }
+
+    pub fn reads(&self) -> u32 {
+        self.reads
+    }
+
+    /// Number of 32-byte blocks in the scratchpad.
+    pub fn blocks(&self) -> usize {
+        self.memory_kib as usize * 1024 / BLOCK_SIZE
+    }
+}
+
+impl Default for ScratchpadParams {
+    /// An 8 MiB scratchpad read 65536 times.
+    fn default() -> Self {
+        ScratchpadParams {
+            memory_kib: 8 * 1024,
+            reads: 1 << 16,
+        }
+    }
+}
+
+fn hash_pair(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let mut input = Vec::with_capacity(2 * BLOCK_SIZE);
+    input.extend_from_slice(left);
+    input.extend_from_slice(right);
+    blake3_reference_hash(input).try_into().unwrap()
+}
+
+fn index(hash: &[u8; 32], len: usize) -> usize {
+    (u64::from_be_bytes(hash[..8].try_into().unwrap()) % len as u64) as usize
+}
+
+/// A scratchpad that can be reused for many headers, so mining allocates it
+/// only once.
+pub struct Scratchpad {
+    params: ScratchpadParams,
+    blocks: Vec<[u8; 32]>,
+}
+
+impl Scratchpad {
+    pub fn new(params: ScratchpadParams) -> Self {
+        Scratchpad {
+            params,
+            blocks: vec![[0; BLOCK_SIZE]; params.blocks()],
+        }
+    }
+
+    pub fn params(&self) -> ScratchpadParams {
+        self.params
+    }
+
+    /// The memory-hard hash of a 64-byte header.
+    pub fn hash(&mut self, header: &[u8; 64]) -> [u8; 32] {
+        let mut input = DOMAIN_TAG.to_vec();
+        input.extend_from_slice(&self.params.memory_kib.to_be_bytes());
+        input.extend_from_slice(&self.params.reads.to_be_bytes());
+        input.extend_from_slice(header);
+        self.blocks[0] = blake3_reference_hash(input).try_into().unwrap();
+
+        for i in 1..self.blocks.len() {
+            let previous = self.blocks[i - 1];
+            let dependency = self.blocks[index(&previous, i)];
+            self.blocks[i] = hash_pair(&previous, &dependency);
+        }
+
+        let mut acc = *self.blocks.last().unwrap();
+        for _ in 0..self.params.reads {
+            acc = hash_pair(&acc, &self.blocks[index(&acc, self.blocks.len())]);
+        }
+
+        let hash_1 = blake3_reference_hash(acc.to_vec());
+        blake3_reference_hash(hash_1).try_into().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hash(header: &[u8; 64], memory_kib: u32, reads: u32) -> String {
+        let params = ScratchpadParams::new(memory_kib, reads).unwrap();
+        hex::encode(Scratchpad::new(params).hash(header))
+    }
+
+    #[test]
+    fn test_reference_vectors() {
+        // memory_kib, reads, header byte, hash; cross-checked against an
+        // implementation on the blake3 crate
+        let vectors = [
+            (
+                1,
+                0,
+                0x00,
+                "adf2bd46f129dbd598a31add3da31005934b39e2574ec329e88352eea42cf924",
+            ),
+            (
+                1,
+                32,
+                0x00,
+                "04a0979a60fbc5708f326ae8ae4ddc74a1a5937be0d2e425913a47ecb2e7fec7",
+            ),
+            (
+                64,
+                1024,
+                0x00,
+                "884de2a9fdaae2ac509df5669096d76e02e9da861b468dc2f7971915f1fba020",
+            ),
+            (
+                64,
+                1024,
+                0xff,
+                "d494858dbc86d6f3a057cb59786db91d96912eff28fbd4cd9b00c9712c05d67e",
+            ),
+            (
+                1024,
+                4096,
+                0x11,
+                "0882d002bb76d642eb74d5e9cf622a786933c6d06ffa9eb27c10283fe8bac855",
+            ),
+        ];
+        for (memory_kib, reads, byte, expected) in vectors {
+            assert_eq!(hash(&[byte; 64], memory_kib, reads), expected);
+        }
+    }
+
+    #[test]
+    fn test_params_change_the_hash() {
+        let header = [0x22; 64];
+        let base = hash(&header, 4, 16);
+        assert_ne!(hash(&header, 5, 16), base);
+        assert_ne!(hash(&header, 4, 17), base);
+        let mut other = header;
+        other[0] ^= 1;
+        assert_ne!(hash(&other, 4, 16), base);
+    }
+
+    #[test]
+    fn test_scratchpad_is_reusable() {
+        let params = ScratchpadParams::new(16, 64).unwrap();
+        let mut scratchpad = Scratchpad::new(params);
+        let first = scratchpad.hash(&[1; 64]);
+        scratchpad.hash(&[2; 64]);
+        assert_eq!(scratchpad.hash(&[1; 64]), first);
+        assert_eq!(Scratchpad::new(params).hash(&[1; 64]), first);
+    }
+
+    #[test]
+    fn test_params() {
+        let params = ScratchpadParams::new(2048, 10).unwrap();
+        assert_eq!(params.blocks(), 65536);
+        assert!(ScratchpadParams::new(0, 10).is_err());
+        assert!(ScratchpadParams::new(MAX_MEMORY_KIB + 1, 10).is_err());
+        assert_eq!(ScratchpadParams::default().memory_kib(), 8192);
+    }
+}
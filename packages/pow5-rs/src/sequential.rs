@@ -0,0 +1,324 @@
+// Sequential work: iterated BLAKE3 with checkpoints.
+//
+// pow5 is a parallel search, so twice the hardware finds a solution in half
+// the time. This puzzle is a single hash chain, x[i + 1] = BLAKE3(x[i]), which
+// cannot be split across threads: a prover with a thousand GPUs finishes no
+// sooner than one with a single core running at the same clock. It can fill
+// the `work_ser` slot of a 217a header or serve on its own as a rate-limit
+// proof.
+//
+// The prover records a checkpoint at the end of each of `segments` equal
+// segments of the chain. Segments start from known values, so a verifier can
+// check them all in parallel (`verify_full`), or only a random sample of them
+// (`verify_sampled`) at a fraction of the cost. Sampling catches a proof with
+// a fraction f of wrong segments with probability 1 - (1 - f)^samples, but only
+// if the sample is drawn after the proof is received; a prover that knows the
+// sample in advance only needs to compute those segments.
+//
+// The verifier, not the proof, decides how long the chain must be: a proof of
+// any other number of iterations is rejected before anything is hashed, since
+// a short one proves too little and an oversized one costs the verifier as
+// much as the prover.
+//
+// Wire format:
+//
+//   offset  size         field
+//   0       8            iterations, big-endian
+//   8       4            segments, big-endian
+//   12      32 * segs    checkpoints, in chain order; the last is the output
+
+use crate::header217a::Header217a;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+const SEED_CONTEXT: &str = "keypears sequential work v1";
+const SAMPLE_CONTEXT: &str = "keypears sequential work sample v1";
+
+/// The `work_ser` algorithm id of iterated BLAKE3 in a 217a header.
+pub const WORK_SER_ALGORITHM_ITERATED_BLAKE3: u16 = 1;
+
+/// The most checkpoints a proof may carry.
+pub const MAX_SEGMENTS: u32 = 4096;
+
+fn step(hash: &[u8; 32]) -> [u8; 32] {
+    blake3::hash(hash).into()
+}
+
+/// A hash chain over a seed, with checkpoints.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SequentialProof {
+    iterations: u64,
+    checkpoints: Vec<[u8; 32]>,
+}
+
+impl SequentialProof {
+    /// Run `iterations` steps of the chain from `seed`, keeping `segments`
+    /// checkpoints. This takes time proportional to `iterations` however many
+    /// cores are available.
+    pub fn compute(seed: &[u8; 32], iterations: u64, segments: u32) -> Result<Self, String> {
+        check_shape(iterations, segments)?;
+        let mut hash = blake3::derive_key(SEED_CONTEXT, seed);
+        let mut checkpoints = Vec::with_capacity(segments as usize);
+        for segment in 0..segments {
+            let start = segment_start(iterations, segments, segment);
+            let end = segment_start(iterations, segments, segment + 1);
+            for _ in start..end {
+                hash = step(&hash);
+            }
+            checkpoints.push(hash);
+        }
+        Ok(SequentialProof {
+            iterations,
+            checkpoints,
+        })
+    }
+
+    pub fn iterations(&self) -> u64 {
+        self.iterations
+    }
+
+    pub fn segments(&self) -> u32 {
+        self.checkpoints.len() as u32
+    }
+
+    pub fn checkpoints(&self) -> &[[u8; 32]] {
+        &self.checkpoints
+    }
+
+    /// The end of the chain.
+    pub fn output(&self) -> [u8; 32] {
+        *self.checkpoints.last().unwrap()
+    }
+
+    /// Check one segment: that its checkpoint follows from the previous one
+    /// (or from the seed, for the first segment).
+    pub fn verify_segment(&self, seed: &[u8; 32], segment: u32) -> bool {
+        let Some(expected) = self.checkpoints.get(segment as usize) else {
+            return false;
+        };
+        let mut hash = match segment {
+            0 => blake3::derive_key(SEED_CONTEXT, seed),
+            _ => self.checkpoints[segment as usize - 1],
+        };
+        let start = segment_start(self.iterations, self.segments(), segment);
+        let end = segment_start(self.iterations, self.segments(), segment + 1);
+        for _ in start..end {
+            hash = step(&hash);
+        }
+        hash == *expected
+    }
+
+    /// Check that the chain is `iterations` steps long and every segment is
+    /// right. The segments are independent, so this can be spread over as
+    /// many threads as there are segments.
+    pub fn verify_full(&self, seed: &[u8; 32], iterations: u64) -> bool {
+        self.iterations == iterations
+            && (0..self.segments()).all(|segment| self.verify_segment(seed, segment))
+    }
+
+    /// Check that the chain is `iterations` steps long, and `samples`
+    /// segments picked by `randomness`, which must be chosen by the verifier
+    /// after receiving the proof. The last segment, which ends in the output,
+    /// is always checked.
+    pub fn verify_sampled(
+        &self,
+        seed: &[u8; 32],
+        iterations: u64,
+        samples: u32,
+        randomness: &[u8; 32],
+    ) -> bool {
+        if self.iterations != iterations {
+            return false;
+        }
+        let segments = self.segments();
+        if !self.verify_segment(seed, segments - 1) {
+            return false;
+        }
+        let mut hasher = blake3::Hasher::new_derive_key(SAMPLE_CONTEXT);
+        hasher.update(randomness);
+        let mut reader = hasher.finalize_xof();
+        (0..samples).all(|_| {
+            let mut bytes = [0u8; 8];
+            reader.fill(&mut bytes);
+            let segment = (u64::from_be_bytes(bytes) % u64::from(segments)) as u32;
+            self.verify_segment(seed, segment)
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(12 + self.checkpoints.len() * 32);
+        bytes.extend_from_slice(&self.iterations.to_be_bytes());
+        bytes.extend_from_slice(&self.segments().to_be_bytes());
+        for checkpoint in &self.checkpoints {
+            bytes.extend_from_slice(checkpoint);
+        }
+        bytes
+    }
+
+    /// Parse a proof, which must be of a chain of `iterations` steps.
+    pub fn from_bytes(bytes: &[u8], iterations: u64) -> Result<Self, String> {
+        if bytes.len() < 12 {
+            return Err("proof is too short".to_string());
+        }
+        let proven = u64::from_be_bytes(bytes[0..8].try_into().unwrap());
+        if proven != iterations {
+            return Err(format!(
+                "expected a proof of {} iterations, got {}",
+                iterations, proven
+            ));
+        }
+        let segments = u32::from_be_bytes(bytes[8..12].try_into().unwrap());
+        check_shape(iterations, segments)?;
+        if bytes.len() - 12 != segments as usize * 32 {
+            return Err(format!(
+                "expected {} bytes of checkpoints, got {}",
+                segments as usize * 32,
+                bytes.len() - 12
+            ));
+        }
+        let checkpoints = bytes[12..]
+            .chunks_exact(32)
+            .map(|chunk| chunk.try_into().unwrap())
+            .collect();
+        Ok(SequentialProof {
+            iterations,
+            checkpoints,
+        })
+    }
+}
+
+/// The step at which `segment` starts. Segments differ in length by at most
+/// one step.
+fn segment_start(iterations: u64, segments: u32, segment: u32) -> u64 {
+    (iterations as u128 * segment as u128 / segments as u128) as u64
+}
+
+fn check_shape(iterations: u64, segments: u32) -> Result<(), String> {
+    if segments == 0 || segments > MAX_SEGMENTS {
+        return Err(format!("segments must be between 1 and {}", MAX_SEGMENTS));
+    }
+    if iterations < u64::from(segments) {
+        return Err("iterations must be at least the number of segments".to_string());
+    }
+    Ok(())
+}
+
+/// The sequential-work seed of a 217a header: the BLAKE3 hash of the header
+/// with the nonce, `work_ser` and its algorithm id, and `work_par` zeroed. The
+/// work does not depend on the nonce, so it is done once per header and the
+/// parallel nonce search cannot shorten it.
+pub fn seed_217a(header: &Header217a) -> [u8; 32] {
+    let header = Header217a {
+        nonce: [0; 32],
+        work_ser_algo: 0,
+        work_ser: [0; 32],
+        work_par: [0; 32],
+        ..header.clone()
+    };
+    blake3::hash(&header.to_bytes()).into()
+}
+
+/// Fill the `work_ser` slot of a 217a header with the output of an iterated
+/// BLAKE3 chain.
+pub fn set_work_ser_217a(header: &mut Header217a, output: &[u8; 32]) {
+    header.work_ser_algo = WORK_SER_ALGORITHM_ITERATED_BLAKE3;
+    header.work_ser = *output;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chain() {
+        let seed = [0x11; 32];
+        let proof = SequentialProof::compute(&seed, 1000, 7).unwrap();
+        assert_eq!(proof.segments(), 7);
+
+        let mut expected = blake3::derive_key(SEED_CONTEXT, &seed);
+        for _ in 0..1000 {
+            expected = *blake3::hash(&expected).as_bytes();
+        }
+        assert_eq!(proof.output(), expected);
+
+        // the checkpoints do not change the output
+        let single = SequentialProof::compute(&seed, 1000, 1).unwrap();
+        assert_eq!(single.output(), expected);
+        assert!(proof.verify_full(&seed, 1000));
+        assert!(!proof.verify_full(&[0x12; 32], 1000));
+    }
+
+    #[test]
+    fn test_wrong_checkpoint_is_caught() {
+        let seed = [0x22; 32];
+        let mut proof = SequentialProof::compute(&seed, 640, 64).unwrap();
+        proof.checkpoints[10] = [0; 32];
+        assert!(!proof.verify_full(&seed, 640));
+        assert!(!proof.verify_segment(&seed, 10));
+        assert!(!proof.verify_segment(&seed, 11));
+        assert!(proof.verify_segment(&seed, 12));
+        assert!(!proof.verify_segment(&seed, 64));
+
+        // sampling every segment many times over is bound to hit it
+        assert!(!proof.verify_sampled(&seed, 640, 1000, &[7; 32]));
+        assert!(proof.verify_sampled(&seed, 640, 0, &[7; 32]));
+
+        let honest = SequentialProof::compute(&seed, 640, 64).unwrap();
+        assert!(honest.verify_sampled(&seed, 640, 16, &[7; 32]));
+    }
+
+    #[test]
+    fn test_bytes_round_trip() {
+        let proof = SequentialProof::compute(&[0x33; 32], 100, 4).unwrap();
+        let bytes = proof.to_bytes();
+        assert_eq!(bytes.len(), 12 + 4 * 32);
+        assert_eq!(SequentialProof::from_bytes(&bytes, 100), Ok(proof));
+
+        assert!(SequentialProof::from_bytes(&bytes[..11], 100).is_err());
+        assert!(SequentialProof::from_bytes(&bytes[..bytes.len() - 1], 100).is_err());
+        assert!(SequentialProof::compute(&[0; 32], 3, 4).is_err());
+        assert!(SequentialProof::compute(&[0; 32], 100, 0).is_err());
+        assert!(SequentialProof::compute(&[0; 32], 10_000, MAX_SEGMENTS + 1).is_err());
+    }
+
+    #[test]
+    fn test_iterations_are_the_verifiers() {
+        let seed = [0x55; 32];
+        let short = SequentialProof::compute(&seed, 99, 4).unwrap();
+        let oversized = SequentialProof::compute(&seed, 101, 4).unwrap();
+        for proof in [short, oversized] {
+            // the proofs are honest chains, just not of the length asked for
+            assert!(proof.verify_full(&seed, proof.iterations()));
+            assert!(!proof.verify_full(&seed, 100));
+            assert!(!proof.verify_sampled(&seed, 100, 4, &[7; 32]));
+            assert!(
+                SequentialProof::from_bytes(&proof.to_bytes(), 100)
+                    .unwrap_err()
+                    .contains("100 iterations")
+            );
+        }
+
+        // a huge claimed length is refused before any checkpoint is read
+        let mut bytes = SequentialProof::compute(&seed, 100, 4).unwrap().to_bytes();
+        bytes[..8].copy_from_slice(&u64::MAX.to_be_bytes());
+        assert!(SequentialProof::from_bytes(&bytes, 100).is_err());
+    }
+
+    #[test]
+    fn test_217a() {
+        let mut header = Header217a::from_bytes(&[0x44; Header217a::SIZE]).unwrap();
+        let seed = seed_217a(&header);
+        let proof = SequentialProof::compute(&seed, 100, 1).unwrap();
+        set_work_ser_217a(&mut header, &proof.output());
+        assert_eq!(header.work_ser, proof.output());
+        assert_eq!(header.work_ser_algo, WORK_SER_ALGORITHM_ITERATED_BLAKE3);
+
+        // the seed ignores the nonce and the work slots, but not the rest
+        header.nonce = [0; 32];
+        header.work_par = [0; 32];
+        assert_eq!(seed_217a(&header), seed);
+        header.version = 0;
+        assert_ne!(seed_217a(&header), seed);
+    }
+}
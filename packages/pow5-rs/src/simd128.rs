@@ -0,0 +1,250 @@
+// BLAKE3 compression with the four G functions of each half-round run side by
+// side in SIMD lanes, for the `simd128` feature.
+//
+// The 16-word state is held as four rows of four words. A column step mixes
+// lane i of every row, which is exactly column i, so the four column G calls
+// are one set of vector operations. Rotating rows b, c and d left by 1, 2 and
+// 3 lanes lines the diagonals up as columns; after the diagonal step they are
+// rotated back. The result is bit-for-bit the scalar `compress`.
+//
+// On wasm32 built with `-C target-feature=+simd128` a row is a `v128`.
+// Elsewhere it is a `[u32; 4]` with the same operations, so the parity tests
+// run on any host. wasm without SIMD has its own build, since a module with
+// v128 instructions does not load at all in a browser without them.
+
+use crate::blake3_reference::{IV, MSG_PERMUTATION};
+
+#[cfg(all(target_arch = "wasm32", not(target_feature = "simd128")))]
+compile_error!("the simd128 feature needs RUSTFLAGS=\"-C target-feature=+simd128\" on wasm32");
+
+#[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+mod row {
+    use core::arch::wasm32::*;
+
+    #[derive(Clone, Copy)]
+    pub struct Row(v128);
+
+    impl Row {
+        #[inline(always)]
+        pub fn new(words: [u32; 4]) -> Self {
+            Row(u32x4(words[0], words[1], words[2], words[3]))
+        }
+
+        #[inline(always)]
+        pub fn to_array(self) -> [u32; 4] {
+            [
+                u32x4_extract_lane::<0>(self.0),
+                u32x4_extract_lane::<1>(self.0),
+                u32x4_extract_lane::<2>(self.0),
+                u32x4_extract_lane::<3>(self.0),
+            ]
+        }
+
+        #[inline(always)]
+        pub fn add(self, other: Row) -> Row {
+            Row(u32x4_add(self.0, other.0))
+        }
+
+        #[inline(always)]
+        pub fn xor(self, other: Row) -> Row {
+            Row(v128_xor(self.0, other.0))
+        }
+
+        #[inline(always)]
+        pub fn rotate_right<const N: u32>(self) -> Row {
+            Row(v128_or(u32x4_shr(self.0, N), u32x4_shl(self.0, 32 - N)))
+        }
+
+        /// Lane i becomes lane i + N, wrapping.
+        #[inline(always)]
+        pub fn rotate_lanes_left<const N: usize>(self) -> Row {
+            Row(match N % 4 {
+                1 => i32x4_shuffle::<1, 2, 3, 0>(self.0, self.0),
+                2 => i32x4_shuffle::<2, 3, 0, 1>(self.0, self.0),
+                3 => i32x4_shuffle::<3, 0, 1, 2>(self.0, self.0),
+                _ => self.0,
+            })
+        }
+    }
+}
+
+#[cfg(not(all(target_arch = "wasm32", target_feature = "simd128")))]
+mod row {
+    #[derive(Clone, Copy)]
+    pub struct Row([u32; 4]);
+
+    impl Row {
+        #[inline(always)]
+        pub fn new(words: [u32; 4]) -> Self {
+            Row(words)
+        }
+
+        #[inline(always)]
+        pub fn to_array(self) -> [u32; 4] {
+            self.0
+        }
+
+        #[inline(always)]
+        pub fn add(self, other: Row) -> Row {
+            Row([0, 1, 2, 3].map(|i| self.0[i].wrapping_add(other.0[i])))
+        }
+
+        #[inline(always)]
+        pub fn xor(self, other: Row) -> Row {
+            Row([0, 1, 2, 3].map(|i| self.0[i] ^ other.0[i]))
+        }
+
+        #[inline(always)]
+        pub fn rotate_right<const N: u32>(self) -> Row {
+            Row(self.0.map(|word| word.rotate_right(N)))
+        }
+
+        /// Lane i becomes lane i + N, wrapping.
+        #[inline(always)]
+        pub fn rotate_lanes_left<const N: usize>(self) -> Row {
+            let mut words = self.0;
+            words.rotate_left(N % 4);
+            Row(words)
+        }
+    }
+}
+
+use row::Row;
+
+struct State {
+    a: Row,
+    b: Row,
+    c: Row,
+    d: Row,
+}
+
+impl State {
+    // G on all four lanes at once
+    #[inline(always)]
+    fn g(&mut self, mx: Row, my: Row) {
+        self.a = self.a.add(self.b).add(mx);
+        self.d = self.d.xor(self.a).rotate_right::<16>();
+        self.c = self.c.add(self.d);
+        self.b = self.b.xor(self.c).rotate_right::<12>();
+        self.a = self.a.add(self.b).add(my);
+        self.d = self.d.xor(self.a).rotate_right::<8>();
+        self.c = self.c.add(self.d);
+        self.b = self.b.xor(self.c).rotate_right::<7>();
+    }
+
+    #[inline(always)]
+    fn round(&mut self, m: &[u32; 16]) {
+        // columns
+        self.g(
+            Row::new([m[0], m[2], m[4], m[6]]),
+            Row::new([m[1], m[3], m[5], m[7]]),
+        );
+        // diagonals, lined up as columns
+        self.b = self.b.rotate_lanes_left::<1>();
+        self.c = self.c.rotate_lanes_left::<2>();
+        self.d = self.d.rotate_lanes_left::<3>();
+        self.g(
+            Row::new([m[8], m[10], m[12], m[14]]),
+            Row::new([m[9], m[11], m[13], m[15]]),
+        );
+        self.b = self.b.rotate_lanes_left::<3>();
+        self.c = self.c.rotate_lanes_left::<2>();
+        self.d = self.d.rotate_lanes_left::<1>();
+    }
+}
+
+fn permute(m: &mut [u32; 16]) {
+    *m = MSG_PERMUTATION.map(|i| m[i]);
+}
+
+/// The BLAKE3 compression function, as `blake3_reference::compress` computes
+/// it, with no counter.
+pub(crate) fn compress(
+    chaining_value: &[u32; 8],
+    block_words: &[u32; 16],
+    block_len: u32,
+    flags: u32,
+) -> [u32; 16] {
+    let cv_low = Row::new([
+        chaining_value[0],
+        chaining_value[1],
+        chaining_value[2],
+        chaining_value[3],
+    ]);
+    let cv_high = Row::new([
+        chaining_value[4],
+        chaining_value[5],
+        chaining_value[6],
+        chaining_value[7],
+    ]);
+    let mut state = State {
+        a: cv_low,
+        b: cv_high,
+        c: Row::new([IV[0], IV[1], IV[2], IV[3]]),
+        d: Row::new([0, 0, block_len, flags]),
+    };
+    let mut block = *block_words;
+    for round in 0..7 {
+        if round > 0 {
+            permute(&mut block);
+        }
+        state.round(&block);
+    }
+
+    let rows = [
+        state.a.xor(state.c),
+        state.b.xor(state.d),
+        state.c.xor(cv_low),
+        state.d.xor(cv_high),
+    ];
+    let mut out = [0u32; 16];
+    for (chunk, row) in out.chunks_exact_mut(4).zip(rows) {
+        chunk.copy_from_slice(&row.to_array());
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blake3_reference;
+
+    // xorshift, so the inputs are arbitrary but the same every run
+    fn words<const N: usize>(seed: &mut u32) -> [u32; N] {
+        [0; N].map(|_| {
+            *seed ^= *seed << 13;
+            *seed ^= *seed >> 17;
+            *seed ^= *seed << 5;
+            *seed
+        })
+    }
+
+    #[test]
+    fn test_compress_matches_scalar() {
+        let mut seed = 0x9e37_79b9;
+        for i in 0..1000 {
+            let chaining_value = words::<8>(&mut seed);
+            let block = words::<16>(&mut seed);
+            let block_len = i % 65;
+            let flags = i % 16;
+            assert_eq!(
+                compress(&chaining_value, &block, block_len, flags),
+                blake3_reference::compress(&chaining_value, &block, block_len, flags),
+            );
+        }
+    }
+
+    #[test]
+    fn test_hash_matches_library() {
+        // every block count a pow5 input can have, through the SIMD path
+        let input: [u8; 256] = core::array::from_fn(|i| i as u8);
+        for len in [0, 1, 32, 63, 64, 65, 128, 217, 256] {
+            assert_eq!(
+                blake3_reference::hash(&input[..len]),
+                *blake3::hash(&input[..len]).as_bytes(),
+                "{}",
+                len
+            );
+        }
+    }
+}
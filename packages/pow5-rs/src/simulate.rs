@@ -0,0 +1,481 @@
+// Spam economics for pow5 difficulty settings.
+//
+// Each hash meets a target of difficulty d with probability 1/d, so the number
+// of hashes to a solution is geometric with mean d and, at the difficulties in
+// use, the time to a solution on a device hashing at rate h is exponential
+// with mean d / h. From that:
+//
+//   attacker cost per message = d / h_attacker / 3600 * cost per hour
+//   break-even difficulty     = value per message / cost per hour * 3600
+//                               * h_attacker
+//
+// Legitimate users are a mix of devices, each a share of the users, so their
+// mining time is a mixture of exponentials. Its percentiles have no closed
+// form and are found by bisection on the mixture's distribution function.
+//
+// Everything is computed, not sampled: the same scenario gives the same report
+// on every run, so tables built from it can be regenerated exactly. The
+// hashrates and prices are inputs; measure them with `whitepaper-bench`.
+
+use std::fmt;
+
+/// Latency percentiles reported for legitimate users.
+pub const PERCENTILES: [f64; 4] = [0.5, 0.9, 0.99, 0.999];
+
+/// Bisection steps for a percentile; enough to reach f64 precision.
+const BISECTION_STEPS: usize = 128;
+
+/// The most decimals an amount is printed with; smaller amounts are printed
+/// in scientific notation.
+const MAX_MONEY_DECIMALS: i32 = 8;
+
+/// A kind of device legitimate users mine on.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Device {
+    pub name: String,
+    pub hashes_per_second: f64,
+    /// Relative share of users on this device. Shares need not sum to 1.
+    pub share: f64,
+}
+
+/// Hardware a spammer rents or runs.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Attacker {
+    pub hashes_per_second: f64,
+    /// What an hour of that hashrate costs, in any currency.
+    pub cost_per_hour: f64,
+}
+
+impl Attacker {
+    /// Expected cost of one message at `difficulty`.
+    pub fn cost_per_message(&self, difficulty: u64) -> f64 {
+        difficulty as f64 / self.hashes_per_second / 3600.0 * self.cost_per_hour
+    }
+
+    /// Messages per hour at `difficulty`.
+    pub fn messages_per_hour(&self, difficulty: u64) -> f64 {
+        self.hashes_per_second * 3600.0 / difficulty as f64
+    }
+
+    /// The difficulty at which a message costs `value`, the most a spammer
+    /// expects to earn from one.
+    pub fn break_even_difficulty(&self, value: f64) -> f64 {
+        value / self.cost_per_hour * 3600.0 * self.hashes_per_second
+    }
+}
+
+/// What to simulate.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Scenario {
+    pub attacker: Attacker,
+    pub devices: Vec<Device>,
+    /// Named difficulties, e.g. the constants in `pow.server.ts`.
+    pub difficulties: Vec<(String, u64)>,
+    /// Values of a spam message to find break-even difficulties for.
+    pub spam_values: Vec<f64>,
+}
+
+impl Scenario {
+    /// The difficulties in `pow.server.ts`, with legitimate users on the same
+    /// hardware as the attacker.
+    pub fn new(attacker: Attacker) -> Self {
+        Scenario {
+            attacker,
+            devices: vec![Device {
+                name: "attacker hardware".to_string(),
+                hashes_per_second: attacker.hashes_per_second,
+                share: 1.0,
+            }],
+            difficulties: vec![
+                ("registration".to_string(), 70_000_000),
+                ("login".to_string(), 7_000_000),
+                ("channel".to_string(), 70_000_000),
+                ("message".to_string(), 7_000_000),
+            ],
+            spam_values: vec![0.0001, 0.001, 0.01],
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        let positive = |x: f64| x.is_finite() && x > 0.0;
+        if !positive(self.attacker.hashes_per_second) || !positive(self.attacker.cost_per_hour) {
+            return Err("attacker hashrate and cost must be greater than 0".to_string());
+        }
+        if self.devices.is_empty() {
+            return Err("at least one device is required".to_string());
+        }
+        for device in &self.devices {
+            if !positive(device.hashes_per_second) || !positive(device.share) {
+                return Err(format!(
+                    "device {} must have a hashrate and share greater than 0",
+                    device.name
+                ));
+            }
+        }
+        if self.difficulties.iter().any(|&(_, d)| d == 0) {
+            return Err("difficulty must be greater than 0".to_string());
+        }
+        if !self.spam_values.iter().all(|&v| positive(v)) {
+            return Err("spam values must be greater than 0".to_string());
+        }
+        // u64::MAX as f64 is 2^64, the first difficulty that does not fit
+        if let Some(value) = self
+            .spam_values
+            .iter()
+            .find(|&&v| self.attacker.break_even_difficulty(v) >= u64::MAX as f64)
+        {
+            return Err(format!(
+                "spam value {:e} breaks even above the largest difficulty",
+                value
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn run(&self) -> Result<Report, String> {
+        self.validate()?;
+        let rows = self
+            .difficulties
+            .iter()
+            .map(|(name, difficulty)| DifficultyRow {
+                name: name.clone(),
+                difficulty: *difficulty,
+                cost_per_message: self.attacker.cost_per_message(*difficulty),
+                messages_per_hour: self.attacker.messages_per_hour(*difficulty),
+                latency: PERCENTILES.map(|p| latency_percentile(&self.devices, *difficulty, p)),
+            })
+            .collect();
+        let break_even = self
+            .spam_values
+            .iter()
+            .map(|&value| {
+                let difficulty = self.attacker.break_even_difficulty(value);
+                BreakEven {
+                    value,
+                    difficulty,
+                    median_latency: latency_percentile(
+                        &self.devices,
+                        (difficulty as u64).max(1),
+                        0.5,
+                    ),
+                }
+            })
+            .collect();
+        Ok(Report { rows, break_even })
+    }
+}
+
+/// The time within which a fraction `p` of legitimate users mine a solution
+/// at `difficulty`, in seconds. `devices` must be valid and `p` in (0, 1).
+pub fn latency_percentile(devices: &[Device], difficulty: u64, p: f64) -> f64 {
+    let total_share: f64 = devices.iter().map(|d| d.share).sum();
+    let cdf = |t: f64| -> f64 {
+        devices
+            .iter()
+            .map(|d| {
+                d.share / total_share * -(-t * d.hashes_per_second / difficulty as f64).exp_m1()
+            })
+            .sum()
+    };
+    // the mixture has reached p by the time the slowest device has
+    let slowest = devices
+        .iter()
+        .map(|d| d.hashes_per_second)
+        .fold(f64::INFINITY, f64::min);
+    let (mut lo, mut hi) = (0.0, difficulty as f64 / slowest * -(-p).ln_1p());
+    for _ in 0..BISECTION_STEPS {
+        let mid = (lo + hi) / 2.0;
+        if cdf(mid) < p {
+            lo = mid;
+        } else {
+            hi = mid;
+        }
+    }
+    hi
+}
+
+/// Results for one difficulty.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DifficultyRow {
+    pub name: String,
+    pub difficulty: u64,
+    pub cost_per_message: f64,
+    pub messages_per_hour: f64,
+    /// Seconds, one for each of [`PERCENTILES`].
+    pub latency: [f64; PERCENTILES.len()],
+}
+
+/// The difficulty at which spam stops paying.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BreakEven {
+    pub value: f64,
+    pub difficulty: f64,
+    /// What the median legitimate user would wait at that difficulty, in
+    /// seconds.
+    pub median_latency: f64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Report {
+    pub rows: Vec<DifficultyRow>,
+    pub break_even: Vec<BreakEven>,
+}
+
+impl Report {
+    /// The report as two Typst tables, for the whitepaper.
+    pub fn to_typst(&self) -> String {
+        let mut out = String::new();
+        out.push_str("#table(\n  columns: 8,\n  inset: 6pt,\n  align: left,\n");
+        out.push_str("  table.header([*Purpose*], [*Difficulty*], [*Cost/msg*], [*Msgs/hour*], ");
+        out.push_str("[*p50*], [*p90*], [*p99*], [*p99.9*]),\n");
+        for row in &self.rows {
+            out.push_str(&format!(
+                "  [{}], [{}], [{}], [{:.0}]",
+                row.name,
+                row.difficulty,
+                format_money(row.cost_per_message),
+                row.messages_per_hour
+            ));
+            for latency in row.latency {
+                out.push_str(&format!(", [{}]", format_duration(latency)));
+            }
+            out.push_str(",\n");
+        }
+        out.push_str(")\n\n#table(\n  columns: 3,\n  inset: 6pt,\n  align: left,\n");
+        out.push_str(
+            "  table.header([*Value/msg*], [*Break-even difficulty*], [*Median wait*]),\n",
+        );
+        for row in &self.break_even {
+            out.push_str(&format!(
+                "  [{}], [{:.0}], [{}],\n",
+                format_money(row.value),
+                row.difficulty,
+                format_duration(row.median_latency)
+            ));
+        }
+        out.push_str(")\n");
+        out
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:<14} {:>14} {:>12} {:>12}",
+            "purpose", "difficulty", "cost/msg", "msgs/hour"
+        )?;
+        for p in PERCENTILES {
+            write!(f, " {:>10}", format!("p{}", p * 100.0))?;
+        }
+        writeln!(f)?;
+        for row in &self.rows {
+            write!(
+                f,
+                "{:<14} {:>14} {:>12} {:>12.0}",
+                row.name,
+                row.difficulty,
+                format_money(row.cost_per_message),
+                row.messages_per_hour
+            )?;
+            for latency in row.latency {
+                write!(f, " {:>10}", format_duration(latency))?;
+            }
+            writeln!(f)?;
+        }
+        writeln!(f)?;
+        writeln!(
+            f,
+            "{:>12} {:>22} {:>12}",
+            "value/msg", "break-even difficulty", "median wait"
+        )?;
+        for row in &self.break_even {
+            writeln!(
+                f,
+                "{:>12} {:>22.0} {:>12}",
+                format_money(row.value),
+                row.difficulty,
+                format_duration(row.median_latency)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Seconds in the largest unit that keeps the number readable, as
+/// `calc-whitepaper-numbers.ts` does.
+pub fn format_duration(seconds: f64) -> String {
+    if seconds < 1.0 {
+        format!("{:.1} ms", seconds * 1000.0)
+    } else if seconds < 60.0 {
+        format!("{:.1} s", seconds)
+    } else if seconds < 3600.0 {
+        format!("{:.1} min", seconds / 60.0)
+    } else if seconds < 86400.0 {
+        format!("{:.1} hours", seconds / 3600.0)
+    } else if seconds < 86400.0 * 365.0 {
+        format!("{:.1} days", seconds / 86400.0)
+    } else {
+        format!("{:.1} years", seconds / (86400.0 * 365.0))
+    }
+}
+
+/// An amount with three significant figures.
+fn format_money(amount: f64) -> String {
+    if !amount.is_finite() || amount == 0.0 {
+        format!("{}", amount)
+    } else if amount >= 100.0 {
+        format!("{:.0}", amount)
+    } else {
+        let decimals = 2 - amount.abs().log10().floor() as i32;
+        if decimals > MAX_MONEY_DECIMALS {
+            format!("{:.2e}", amount)
+        } else {
+            format!("{:.*}", decimals.max(0) as usize, amount)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attacker() -> Attacker {
+        Attacker {
+            hashes_per_second: 10_000_000.0,
+            cost_per_hour: 0.5,
+        }
+    }
+
+    fn device(name: &str, hashes_per_second: f64, share: f64) -> Device {
+        Device {
+            name: name.to_string(),
+            hashes_per_second,
+            share,
+        }
+    }
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() <= 1e-9 * b.abs()
+    }
+
+    #[test]
+    fn test_attacker_economics() {
+        let attacker = attacker();
+        // 7 seconds at 10M hashes/s
+        assert!(close(
+            attacker.cost_per_message(70_000_000),
+            7.0 / 3600.0 * 0.5
+        ));
+        assert!(close(attacker.messages_per_hour(7_000_000), 36_000.0 / 7.0));
+        // a tenth of a cent buys 7.2 seconds
+        let difficulty = attacker.break_even_difficulty(0.001);
+        assert!(close(difficulty, 72_000_000.0));
+        assert!(close(attacker.cost_per_message(difficulty as u64), 0.001));
+    }
+
+    #[test]
+    fn test_latency_single_device() {
+        // exponential with mean 7 s: the median is 7 ln 2
+        let devices = [device("gpu", 1_000_000.0, 1.0)];
+        let median = latency_percentile(&devices, 7_000_000, 0.5);
+        assert!(close(median, 7.0 * 2f64.ln()));
+        let p99 = latency_percentile(&devices, 7_000_000, 0.99);
+        assert!(close(p99, 7.0 * 100f64.ln()));
+    }
+
+    #[test]
+    fn test_latency_mixture() {
+        let fast = device("desktop", 10_000_000.0, 1.0);
+        let slow = device("phone", 100_000.0, 3.0);
+        let devices = [fast.clone(), slow.clone()];
+        let median = latency_percentile(&devices, 7_000_000, 0.5);
+        // between the medians of the two devices, nearer the slow one
+        let fast_median = latency_percentile(&[fast], 7_000_000, 0.5);
+        let slow_median = latency_percentile(&[slow], 7_000_000, 0.5);
+        assert!(fast_median < median && median < slow_median);
+        // at the median, the fast quarter has nearly all finished
+        let done = 0.25 * -(-median * 10.0 / 7.0).exp_m1() + 0.75 * -(-median / 70.0).exp_m1();
+        assert!((done - 0.5).abs() < 1e-12);
+        // shares are relative
+        let doubled = [device("a", 10_000_000.0, 2.0), device("b", 100_000.0, 6.0)];
+        assert_eq!(latency_percentile(&doubled, 7_000_000, 0.5), median);
+    }
+
+    #[test]
+    fn test_run_is_reproducible() {
+        let mut scenario = Scenario::new(attacker());
+        scenario.devices = vec![
+            device("desktop", 10_000_000.0, 1.0),
+            device("laptop", 2_000_000.0, 2.0),
+        ];
+        let report = scenario.run().unwrap();
+        assert_eq!(report, scenario.run().unwrap());
+        assert_eq!(report.rows.len(), 4);
+        assert_eq!(report.break_even.len(), 3);
+        for row in &report.rows {
+            assert!(row.latency.windows(2).all(|w| w[0] < w[1]));
+        }
+        assert_eq!(report.to_string(), scenario.run().unwrap().to_string());
+        let typst = report.to_typst();
+        assert!(typst.contains("[registration], [70000000], [0.000972], [514], [13.6 s]"));
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(Scenario::new(attacker()).validate().is_ok());
+        let mut bad = Scenario::new(Attacker {
+            hashes_per_second: 0.0,
+            cost_per_hour: 1.0,
+        });
+        assert!(bad.run().is_err());
+        bad = Scenario::new(attacker());
+        bad.devices.clear();
+        assert!(bad.run().is_err());
+        bad = Scenario::new(attacker());
+        bad.devices[0].share = f64::NAN;
+        assert!(bad.run().is_err());
+        bad = Scenario::new(attacker());
+        bad.difficulties.push(("free".to_string(), 0));
+        assert!(bad.run().is_err());
+
+        // a break-even difficulty must fit in a u64
+        bad = Scenario::new(attacker());
+        bad.spam_values = vec![1e300];
+        assert!(bad.run().unwrap_err().contains("1e300"));
+        bad.spam_values = vec![f64::MAX];
+        assert!(bad.run().is_err());
+    }
+
+    #[test]
+    fn test_extreme_attacker() {
+        // pow5-sim 1e300 1e-300: a message costs less than the smallest f64
+        let scenario = Scenario::new(Attacker {
+            hashes_per_second: 1e300,
+            cost_per_hour: 1e-300,
+        });
+        // and even the smallest value breaks even beyond any difficulty
+        assert!(scenario.validate().is_err());
+        let mut scenario = scenario;
+        scenario.spam_values.clear();
+        let report = scenario.run().unwrap();
+        assert_eq!(report.rows[0].cost_per_message, 0.0);
+        assert!(report.to_string().contains(" 0 "));
+    }
+
+    #[test]
+    fn test_format() {
+        assert_eq!(format_duration(0.0123), "12.3 ms");
+        assert_eq!(format_duration(7.0), "7.0 s");
+        assert_eq!(format_duration(5400.0), "1.5 hours");
+        assert_eq!(format_money(0.000972), "0.000972");
+        assert_eq!(format_money(1.5), "1.50");
+        assert_eq!(format_money(1234.5), "1234");
+        assert_eq!(format_money(0.0), "0");
+        assert_eq!(format_money(1.234e-300), "1.23e-300");
+        assert_eq!(format_money(0.0000001234), "1.23e-7");
+        assert_eq!(format_money(0.000001234), "0.00000123");
+        assert_eq!(format_money(f64::INFINITY), "inf");
+        assert_eq!(format_money(f64::NAN), "NaN");
+    }
+}
@@ -0,0 +1,344 @@
+// Non-interactive, Hashcash-style stamps.
+//
+// An interactive challenge needs a round trip to the recipient's server before
+// any work can start. A stamp instead derives the 32-byte challenge half of a
+// pow5-64b header from things the sender already knows: the recipient address,
+// a date bucket, a hash of the message content and the difficulty. The sender
+// mines it offline, and the message can be queued or relayed before it reaches
+// the recipient, who recomputes the challenge from the message it received.
+//
+// Freshness window: the date bucket is the UTC day (`now / STAMP_BUCKET_MS`).
+// A stamp minted on day D is accepted through the end of day
+// D + STAMP_MAX_AGE_BUCKETS, so for 48 to 72 hours, and up to one day early to
+// allow for clock skew. Replays within the window are caught with a
+// `SpentStore`, keyed on the solved header until `Stamp::expires_at`.
+//
+// Wire format (49 bytes, base64url-encoded by `Stamp::encode`):
+//
+//   offset  size  field
+//   0       1     stamp format version (1)
+//   1       8     date bucket, big-endian
+//   9       8     difficulty, big-endian
+//   17      32    nonce
+
+use crate::blake3_reference::blake3_reference_hash;
+use crate::challenge::push_field;
+use crate::difficulty::Target;
+use crate::mine::{hash_64b, mine_64b};
+use crate::replay::{SpentStore, spent_id};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use std::fmt;
+
+const DOMAIN_TAG: &[u8] = b"keypears pow5-64b stamp v1";
+const TAG_RECIPIENT_ADDRESS: u8 = 0x01;
+const TAG_DATE_BUCKET: u8 = 0x02;
+const TAG_CONTENT_HASH: u8 = 0x03;
+const TAG_DIFFICULTY: u8 = 0x04;
+
+pub const STAMP_VERSION: u8 = 1;
+const STAMP_SIZE: usize = 1 + 8 + 8 + 32;
+
+/// Length of a date bucket: one UTC day.
+pub const STAMP_BUCKET_MS: u64 = 24 * 60 * 60 * 1000;
+
+/// How many whole buckets after its own a stamp stays fresh.
+pub const STAMP_MAX_AGE_BUCKETS: u64 = 2;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum StampError {
+    Stale,
+    FromTheFuture,
+    DifficultyTooLow { min_difficulty: u64 },
+    HashDoesNotMeetTarget,
+    AlreadySpent,
+    SpentStore(String),
+}
+
+impl fmt::Display for StampError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StampError::Stale => write!(f, "Stamp is too old"),
+            StampError::FromTheFuture => write!(f, "Stamp is dated in the future"),
+            StampError::DifficultyTooLow { min_difficulty } => {
+                write!(f, "Difficulty is below the minimum of {min_difficulty}")
+            }
+            StampError::HashDoesNotMeetTarget => write!(f, "Hash does not meet target"),
+            StampError::AlreadySpent => write!(f, "Stamp already used"),
+            StampError::SpentStore(message) => write!(f, "Spent-token store failed: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for StampError {}
+
+/// The challenge half of a stamp's header.
+pub fn stamp_challenge(
+    recipient_address: &str,
+    date_bucket: u64,
+    content_hash: &[u8; 32],
+    difficulty: u64,
+) -> [u8; 32] {
+    let mut input = DOMAIN_TAG.to_vec();
+    push_field(
+        &mut input,
+        TAG_RECIPIENT_ADDRESS,
+        recipient_address.as_bytes(),
+    );
+    push_field(&mut input, TAG_DATE_BUCKET, &date_bucket.to_be_bytes());
+    push_field(&mut input, TAG_CONTENT_HASH, content_hash);
+    push_field(&mut input, TAG_DIFFICULTY, &difficulty.to_be_bytes());
+    blake3_reference_hash(input).try_into().unwrap()
+}
+
+/// A proof of work bound to one message for one recipient.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Stamp {
+    pub date_bucket: u64,
+    pub difficulty: u64,
+    pub nonce: [u8; 32],
+}
+
+impl Stamp {
+    /// Mine a stamp dated `now` (ms since the Unix epoch). This runs until a
+    /// solution is found, which takes `difficulty` hashes on average. Fails if
+    /// the difficulty is zero or, in principle, if none of the 2^64 counters
+    /// meets the target.
+    pub fn mint(
+        recipient_address: &str,
+        content_hash: &[u8; 32],
+        difficulty: u64,
+        now: u64,
+    ) -> Result<Self, String> {
+        let target = Target::from_difficulty(difficulty).map_err(|err| err.to_string())?;
+        let date_bucket = now / STAMP_BUCKET_MS;
+        let mut header = [0u8; 64];
+        header[32..].copy_from_slice(&stamp_challenge(
+            recipient_address,
+            date_bucket,
+            content_hash,
+            difficulty,
+        ));
+        let solved = mine_64b(&header, &target, u64::MAX)
+            .ok_or_else(|| format!("no stamp meets difficulty {}", difficulty))?;
+        Ok(Stamp {
+            date_bucket,
+            difficulty,
+            nonce: solved[..32].try_into().unwrap(),
+        })
+    }
+
+    /// The solved header this stamp stands for.
+    pub fn header(&self, recipient_address: &str, content_hash: &[u8; 32]) -> [u8; 64] {
+        let mut header = [0u8; 64];
+        header[..32].copy_from_slice(&self.nonce);
+        header[32..].copy_from_slice(&stamp_challenge(
+            recipient_address,
+            self.date_bucket,
+            content_hash,
+            self.difficulty,
+        ));
+        header
+    }
+
+    /// The last moment (ms since the Unix epoch) this stamp is fresh.
+    pub fn expires_at(&self) -> u64 {
+        self.date_bucket
+            .saturating_add(STAMP_MAX_AGE_BUCKETS + 1)
+            .saturating_mul(STAMP_BUCKET_MS)
+            .saturating_sub(1)
+    }
+
+    /// Verify a stamp received for `recipient_address` on a message whose
+    /// content hashes to `content_hash`: fresh, at least `min_difficulty`, and
+    /// the hash of the recomputed header meets the stamp's target.
+    pub fn verify(
+        &self,
+        recipient_address: &str,
+        content_hash: &[u8; 32],
+        min_difficulty: u64,
+        now: u64,
+    ) -> Result<(), StampError> {
+        let current_bucket = now / STAMP_BUCKET_MS;
+        if self.date_bucket > current_bucket + 1 {
+            return Err(StampError::FromTheFuture);
+        }
+        if self.date_bucket + STAMP_MAX_AGE_BUCKETS < current_bucket {
+            return Err(StampError::Stale);
+        }
+        if self.difficulty < min_difficulty.max(1) {
+            return Err(StampError::DifficultyTooLow { min_difficulty });
+        }
+
+        let target = Target::from_difficulty(self.difficulty).expect("difficulty >= 1");
+        let header = self.header(recipient_address, content_hash);
+        if !target.is_met_by(&hash_64b(&header)) {
+            return Err(StampError::HashDoesNotMeetTarget);
+        }
+        Ok(())
+    }
+
+    /// Verify a stamp and record it as spent, so it is rejected if the same
+    /// message is delivered again while the stamp is fresh.
+    pub fn verify_and_consume(
+        &self,
+        recipient_address: &str,
+        content_hash: &[u8; 32],
+        min_difficulty: u64,
+        now: u64,
+        store: &dyn SpentStore,
+    ) -> Result<(), StampError> {
+        self.verify(recipient_address, content_hash, min_difficulty, now)?;
+        let id = spent_id(&self.header(recipient_address, content_hash));
+        match store.mark_spent(id, self.expires_at(), now) {
+            Ok(true) => Ok(()),
+            Ok(false) => Err(StampError::AlreadySpent),
+            Err(err) => Err(StampError::SpentStore(err)),
+        }
+    }
+
+    pub fn to_bytes(&self) -> [u8; STAMP_SIZE] {
+        let mut out = [0u8; STAMP_SIZE];
+        out[0] = STAMP_VERSION;
+        out[1..9].copy_from_slice(&self.date_bucket.to_be_bytes());
+        out[9..17].copy_from_slice(&self.difficulty.to_be_bytes());
+        out[17..].copy_from_slice(&self.nonce);
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        if bytes.len() != STAMP_SIZE {
+            return Err(format!(
+                "stamp is not the correct size: expected {}, got {}",
+                STAMP_SIZE,
+                bytes.len()
+            ));
+        }
+        if bytes[0] != STAMP_VERSION {
+            return Err(format!("unsupported stamp version: {}", bytes[0]));
+        }
+        Ok(Stamp {
+            date_bucket: u64::from_be_bytes(bytes[1..9].try_into().unwrap()),
+            difficulty: u64::from_be_bytes(bytes[9..17].try_into().unwrap()),
+            nonce: bytes[17..].try_into().unwrap(),
+        })
+    }
+
+    /// Encode as unpadded base64url.
+    pub fn encode(&self) -> String {
+        URL_SAFE_NO_PAD.encode(self.to_bytes())
+    }
+
+    pub fn decode(stamp: &str) -> Result<Self, String> {
+        if stamp.len() != (STAMP_SIZE * 4).div_ceil(3) {
+            return Err(format!("stamp is not the correct length: {}", stamp.len()));
+        }
+        let bytes = URL_SAFE_NO_PAD
+            .decode(stamp)
+            .map_err(|err| format!("stamp is not valid base64url: {}", err))?;
+        Stamp::from_bytes(&bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::challenge::test_support::NOW;
+    use crate::replay::MemorySpentStore;
+
+    const RECIPIENT: &str = "bob@example.com";
+    const CONTENT: [u8; 32] = [0xc0; 32];
+
+    #[test]
+    fn test_mint_and_verify() {
+        let stamp = Stamp::mint(RECIPIENT, &CONTENT, 16, NOW).unwrap();
+        assert_eq!(stamp.date_bucket, NOW / STAMP_BUCKET_MS);
+        assert_eq!(stamp.verify(RECIPIENT, &CONTENT, 16, NOW), Ok(()));
+
+        // bound to the recipient and the message
+        assert_eq!(
+            stamp.verify("eve@example.com", &CONTENT, 16, NOW),
+            Err(StampError::HashDoesNotMeetTarget)
+        );
+        assert_eq!(
+            stamp.verify(RECIPIENT, &[0xc1; 32], 16, NOW),
+            Err(StampError::HashDoesNotMeetTarget)
+        );
+        assert_eq!(
+            stamp.verify(RECIPIENT, &CONTENT, 17, NOW),
+            Err(StampError::DifficultyTooLow { min_difficulty: 17 })
+        );
+
+        // claiming a higher difficulty changes the challenge
+        let inflated = Stamp {
+            difficulty: 1 << 40,
+            ..stamp
+        };
+        assert_eq!(
+            inflated.verify(RECIPIENT, &CONTENT, 16, NOW),
+            Err(StampError::HashDoesNotMeetTarget)
+        );
+    }
+
+    #[test]
+    fn test_freshness_window() {
+        let stamp = Stamp::mint(RECIPIENT, &CONTENT, 4, NOW).unwrap();
+        let day = STAMP_BUCKET_MS;
+        let minted_day_start = stamp.date_bucket * day;
+
+        assert_eq!(
+            stamp.verify(RECIPIENT, &CONTENT, 4, minted_day_start - day),
+            Ok(())
+        );
+        assert_eq!(
+            stamp.verify(RECIPIENT, &CONTENT, 4, minted_day_start - day - 1),
+            Err(StampError::FromTheFuture)
+        );
+        assert_eq!(
+            stamp.verify(RECIPIENT, &CONTENT, 4, stamp.expires_at()),
+            Ok(())
+        );
+        assert_eq!(
+            stamp.verify(RECIPIENT, &CONTENT, 4, stamp.expires_at() + 1),
+            Err(StampError::Stale)
+        );
+        assert_eq!(stamp.expires_at() + 1, minted_day_start + 3 * day);
+    }
+
+    #[test]
+    fn test_verify_and_consume() {
+        let stamp = Stamp::mint(RECIPIENT, &CONTENT, 4, NOW).unwrap();
+        let store = MemorySpentStore::new();
+        assert_eq!(
+            stamp.verify_and_consume(RECIPIENT, &CONTENT, 4, NOW, &store),
+            Ok(())
+        );
+        assert_eq!(
+            stamp.verify_and_consume(RECIPIENT, &CONTENT, 4, NOW + 1, &store),
+            Err(StampError::AlreadySpent)
+        );
+    }
+
+    #[test]
+    fn test_encoding_round_trip() {
+        let stamp = Stamp::mint(RECIPIENT, &CONTENT, 4, NOW).unwrap();
+        let encoded = stamp.encode();
+        assert_eq!(encoded.len(), 66);
+        assert_eq!(Stamp::decode(&encoded), Ok(stamp));
+
+        let mut bytes = stamp.to_bytes();
+        bytes[0] = 2;
+        assert!(Stamp::from_bytes(&bytes).is_err());
+        assert!(Stamp::from_bytes(&bytes[..48]).is_err());
+        assert!(Stamp::decode(&encoded[..65]).is_err());
+    }
+
+    #[test]
+    fn test_stamp_challenge_separates_fields() {
+        let a = stamp_challenge("a@b", 1, &CONTENT, 2);
+        assert_ne!(a, stamp_challenge("a@c", 1, &CONTENT, 2));
+        assert_ne!(a, stamp_challenge("a@b", 2, &CONTENT, 2));
+        assert_ne!(a, stamp_challenge("a@b", 1, &[0; 32], 2));
+        assert_ne!(a, stamp_challenge("a@b", 1, &CONTENT, 3));
+    }
+}
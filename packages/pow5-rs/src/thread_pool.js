@@ -0,0 +1,43 @@
+// Web Workers for the rayon pool of the threaded wasm build; see
+// thread_pool.rs. The wasm-bindgen glue imports this file for startWorkers,
+// and every pool worker runs it as its script.
+
+function waitForMessage(target, type) {
+  return new Promise((resolve) => {
+    target.addEventListener("message", function onMessage({ data }) {
+      if (data?.type !== type) return;
+      target.removeEventListener("message", onMessage);
+      resolve(data);
+    });
+  });
+}
+
+// In a pool worker: instantiate the module on the shared memory, say so, and
+// become a rayon thread. wasm-pack puts this file in snippets/<crate>/src/.
+waitForMessage(self, "keypears_pow5_thread_init").then(async ({ init }) => {
+  const pkg = await import("../../../keypears_pow5.js");
+  await pkg.default(init);
+  postMessage({ type: "keypears_pow5_thread_ready" });
+  pkg.runPoolThread();
+});
+
+// kept so the workers are never collected
+let workers = [];
+
+export async function startWorkers(module, memory, builder) {
+  const message = {
+    type: "keypears_pow5_thread_init",
+    init: { module_or_path: module, memory },
+  };
+  workers = await Promise.all(
+    Array.from({ length: builder.numThreads() }, async () => {
+      const worker = new Worker(new URL("./thread_pool.js", import.meta.url), {
+        type: "module",
+      });
+      worker.postMessage(message);
+      await waitForMessage(worker, "keypears_pow5_thread_ready");
+      return worker;
+    }),
+  );
+  builder.build();
+}
@@ -0,0 +1,104 @@
+// The rayon thread pool on Web Workers, for the threaded wasm build.
+//
+// wasm32 cannot spawn threads itself. `initThreadPool` starts one Web Worker
+// per thread, each instantiating this module on the same shared memory, and
+// then builds the rayon global pool with a spawn handler that hands each
+// rayon thread to a worker waiting in `runPoolThread`. This is the scheme of
+// wasm-bindgen-rayon, cut down to what the miner needs.
+//
+// Anything that blocks on shared memory must be a worker, since the browser
+// main thread cannot wait: the pool threads, and whoever calls
+// `mine_64b_parallel`. Until the pool is up, `mine_64b_parallel` runs on the
+// calling thread.
+
+use rayon::ThreadBuilder;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Mutex, OnceLock};
+use wasm_bindgen::prelude::*;
+
+#[cfg(not(target_feature = "atomics"))]
+compile_error!(
+    "the threads feature needs RUSTFLAGS=\"-C target-feature=+atomics,+bulk-memory\" on wasm32"
+);
+
+static POOL_READY: AtomicBool = AtomicBool::new(false);
+static POOL_THREADS: OnceLock<Mutex<Receiver<ThreadBuilder>>> = OnceLock::new();
+
+#[wasm_bindgen(module = "/src/thread_pool.js")]
+extern "C" {
+    #[wasm_bindgen(js_name = startWorkers)]
+    fn start_workers(module: JsValue, memory: JsValue, builder: PoolBuilder) -> JsValue;
+}
+
+/// Whether `initThreadPool` has finished.
+pub(crate) fn pool_ready() -> bool {
+    POOL_READY.load(Ordering::Acquire)
+}
+
+/// Passed to `startWorkers`, which builds the pool once every worker is
+/// waiting for a thread.
+#[doc(hidden)]
+#[wasm_bindgen]
+pub struct PoolBuilder {
+    threads: usize,
+    sender: Sender<ThreadBuilder>,
+}
+
+#[wasm_bindgen]
+impl PoolBuilder {
+    #[wasm_bindgen(js_name = numThreads)]
+    pub fn num_threads(&self) -> usize {
+        self.threads
+    }
+
+    pub fn build(self) -> Result<(), String> {
+        let sender = self.sender;
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(self.threads)
+            .spawn_handler(move |thread| {
+                sender
+                    .send(thread)
+                    .map_err(|_| io::Error::other("pool workers are gone"))
+            })
+            .build_global()
+            .map_err(|err| err.to_string())?;
+        POOL_READY.store(true, Ordering::Release);
+        Ok(())
+    }
+}
+
+/// Start `threads` Web Workers and build the rayon pool on them. Returns a
+/// Promise that resolves once the pool is up. Call it at most once, from a
+/// worker of a cross-origin isolated page.
+#[wasm_bindgen(js_name = initThreadPool)]
+pub fn init_thread_pool(threads: usize) -> Result<JsValue, String> {
+    if threads == 0 {
+        return Err("threads must be at least 1".to_string());
+    }
+    let (sender, receiver) = mpsc::channel();
+    POOL_THREADS
+        .set(Mutex::new(receiver))
+        .map_err(|_| "the thread pool is already started".to_string())?;
+    Ok(start_workers(
+        wasm_bindgen::module(),
+        wasm_bindgen::memory(),
+        PoolBuilder { threads, sender },
+    ))
+}
+
+/// Become one rayon thread. Each pool worker calls this once; it does not
+/// return.
+#[doc(hidden)]
+#[wasm_bindgen(js_name = runPoolThread)]
+pub fn run_pool_thread() {
+    let thread = POOL_THREADS
+        .get()
+        .expect_throw("the thread pool is not started")
+        .lock()
+        .expect_throw("a pool worker panicked")
+        .recv()
+        .expect_throw("the thread pool was dropped");
+    thread.run();
+}
@@ -0,0 +1,381 @@
+// Compact, self-contained challenge tokens.
+//
+// A token packs a signed challenge (or a solved one, since only the nonce half
+// of the header differs) into a single base64url string that fits in an HTTP
+// header, an email header or a QR code:
+//
+//   offset  size  field
+//   0       1     token format version (1)
+//   1       1     signature algorithm
+//   2       1     purpose
+//   3       4     key id, big-endian
+//   7       64    header
+//   71      32    target
+//   103     8     expires at (ms since the Unix epoch), big-endian
+//   111     1     address flags (bit 0 sender, bit 1 recipient)
+//   112     ...   for each present address: 1-byte length, then UTF-8 bytes
+//   ...     32/64 signature, sized by the algorithm
+//
+// Parsing is strict: the encoded length is checked before decoding, unknown
+// versions, flags and enum values are rejected, and there must be no trailing
+// bytes. All of this happens before any hashing.
+
+use crate::challenge::{Challenge, ChallengePurpose, SignatureAlgorithm};
+use crate::difficulty::Target;
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+
+pub const TOKEN_VERSION: u8 = 1;
+
+/// Longest address a token can carry, the SMTP path limit.
+pub const MAX_ADDRESS_LEN: usize = 254;
+
+const FIXED_SIZE: usize = 1 + 1 + 1 + 4 + 64 + 32 + 8 + 1;
+const MAX_SIZE: usize = FIXED_SIZE + 2 * (1 + MAX_ADDRESS_LEN) + 64;
+
+/// Longest base64url string [`ChallengeToken::decode`] accepts.
+pub const MAX_TOKEN_LEN: usize = (MAX_SIZE * 4).div_ceil(3);
+
+const FLAG_SENDER: u8 = 0b01;
+const FLAG_RECIPIENT: u8 = 0b10;
+
+/// A challenge together with its signature.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChallengeToken {
+    challenge: Challenge,
+    signature: Vec<u8>,
+}
+
+impl ChallengeToken {
+    /// Fails if an address is empty or longer than [`MAX_ADDRESS_LEN`], since
+    /// neither would decode, or the signature is the wrong size for the
+    /// challenge's algorithm.
+    pub fn new(challenge: Challenge, signature: &[u8]) -> Result<Self, String> {
+        for address in [&challenge.sender_address, &challenge.recipient_address]
+            .into_iter()
+            .flatten()
+        {
+            if address.is_empty() {
+                return Err("address is empty".to_string());
+            }
+            if address.len() > MAX_ADDRESS_LEN {
+                return Err(format!(
+                    "address is too long: max {}, got {}",
+                    MAX_ADDRESS_LEN,
+                    address.len()
+                ));
+            }
+        }
+        let signature_size = challenge.algorithm.signature_size();
+        if signature.len() != signature_size {
+            return Err(format!(
+                "signature is not the correct size: expected {}, got {}",
+                signature_size,
+                signature.len()
+            ));
+        }
+        Ok(ChallengeToken {
+            challenge,
+            signature: signature.to_vec(),
+        })
+    }
+
+    pub fn challenge(&self) -> &Challenge {
+        &self.challenge
+    }
+
+    pub fn signature(&self) -> &[u8] {
+        &self.signature
+    }
+
+    pub fn into_parts(self) -> (Challenge, Vec<u8>) {
+        (self.challenge, self.signature)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let challenge = &self.challenge;
+        let mut out = Vec::with_capacity(MAX_SIZE);
+        out.push(TOKEN_VERSION);
+        out.push(challenge.algorithm.as_byte());
+        out.push(challenge.purpose.as_byte());
+        out.extend_from_slice(&challenge.key_id.to_be_bytes());
+        out.extend_from_slice(&challenge.header);
+        out.extend_from_slice(challenge.target.as_bytes());
+        out.extend_from_slice(&challenge.expires_at.to_be_bytes());
+
+        let mut flags = 0;
+        if challenge.sender_address.is_some() {
+            flags |= FLAG_SENDER;
+        }
+        if challenge.recipient_address.is_some() {
+            flags |= FLAG_RECIPIENT;
+        }
+        out.push(flags);
+        for address in [&challenge.sender_address, &challenge.recipient_address]
+            .into_iter()
+            .flatten()
+        {
+            out.push(address.len() as u8);
+            out.extend_from_slice(address.as_bytes());
+        }
+
+        out.extend_from_slice(&self.signature);
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        if bytes.len() < FIXED_SIZE || bytes.len() > MAX_SIZE {
+            return Err(format!(
+                "token is not a valid size: expected {} to {}, got {}",
+                FIXED_SIZE,
+                MAX_SIZE,
+                bytes.len()
+            ));
+        }
+        let mut reader = Reader { bytes, pos: 0 };
+
+        let version = reader.byte()?;
+        if version != TOKEN_VERSION {
+            return Err(format!("unsupported token version: {}", version));
+        }
+        let algorithm = reader.byte()?;
+        let algorithm = SignatureAlgorithm::from_byte(algorithm)
+            .ok_or_else(|| format!("unknown signature algorithm: {}", algorithm))?;
+        let purpose = reader.byte()?;
+        let purpose = ChallengePurpose::from_byte(purpose)
+            .ok_or_else(|| format!("unknown challenge purpose: {}", purpose))?;
+        let key_id = u32::from_be_bytes(reader.array()?);
+        let header: [u8; 64] = reader.array()?;
+        let target = Target::from_bytes(reader.array()?);
+        let expires_at = u64::from_be_bytes(reader.array()?);
+
+        let flags = reader.byte()?;
+        if flags & !(FLAG_SENDER | FLAG_RECIPIENT) != 0 {
+            return Err(format!("unknown address flags: {:#04x}", flags));
+        }
+        let sender_address = if flags & FLAG_SENDER != 0 {
+            Some(reader.address()?)
+        } else {
+            None
+        };
+        let recipient_address = if flags & FLAG_RECIPIENT != 0 {
+            Some(reader.address()?)
+        } else {
+            None
+        };
+
+        let signature = reader.take(algorithm.signature_size())?;
+        if reader.pos != bytes.len() {
+            return Err(format!(
+                "token has {} trailing bytes",
+                bytes.len() - reader.pos
+            ));
+        }
+
+        let challenge = Challenge {
+            purpose,
+            algorithm,
+            key_id,
+            header,
+            target,
+            expires_at,
+            sender_address,
+            recipient_address,
+        };
+        ChallengeToken::new(challenge, signature)
+    }
+
+    /// Encode as unpadded base64url.
+    pub fn encode(&self) -> String {
+        URL_SAFE_NO_PAD.encode(self.to_bytes())
+    }
+
+    pub fn decode(token: &str) -> Result<Self, String> {
+        if token.len() > MAX_TOKEN_LEN {
+            return Err(format!(
+                "token is too long: max {}, got {}",
+                MAX_TOKEN_LEN,
+                token.len()
+            ));
+        }
+        let bytes = URL_SAFE_NO_PAD
+            .decode(token)
+            .map_err(|err| format!("token is not valid base64url: {}", err))?;
+        ChallengeToken::from_bytes(&bytes)
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let end = self.pos + len;
+        if end > self.bytes.len() {
+            return Err("token is truncated".to_string());
+        }
+        let out = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(out)
+    }
+
+    fn byte(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    fn address(&mut self) -> Result<String, String> {
+        let len = self.byte()? as usize;
+        if len == 0 {
+            return Err("address is empty".to_string());
+        }
+        let bytes = self.take(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| "address is not valid UTF-8".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::challenge::test_support::{self, NOW};
+    use crate::challenge::{CHALLENGE_EXPIRY_MS, ChallengeKey, ChallengeVersion};
+    use crate::ed25519::Ed25519Signer;
+
+    fn test_challenge() -> Challenge {
+        let mut header = [0x42; 64];
+        header[..32].copy_from_slice(&[0x99; 32]);
+        Challenge {
+            key_id: 0x01020304,
+            header,
+            target: Target::from_difficulty(7_000_000).unwrap(),
+            sender_address: Some("alice@example.com".to_string()),
+            recipient_address: Some("bob@example.org".to_string()),
+            ..test_support::test_challenge(ChallengePurpose::Message)
+        }
+    }
+
+    #[test]
+    fn test_round_trip_hmac() {
+        let challenge = test_challenge();
+        let signature = ChallengeKey::new([7; 32]).sign(&challenge, ChallengeVersion::V2);
+        let token = ChallengeToken::new(challenge, &signature).unwrap();
+        let encoded = token.encode();
+        assert!(encoded.len() <= MAX_TOKEN_LEN);
+        assert!(
+            encoded
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+        );
+        assert_eq!(ChallengeToken::decode(&encoded).unwrap(), token);
+    }
+
+    #[test]
+    fn test_round_trip_ed25519_without_addresses() {
+        let mut challenge = Challenge {
+            sender_address: None,
+            recipient_address: None,
+            ..test_challenge()
+        };
+        let signature = Ed25519Signer::new(5, [3; 32]).sign(&mut challenge);
+        let token = ChallengeToken::new(challenge, &signature).unwrap();
+        let bytes = token.to_bytes();
+        assert_eq!(bytes.len(), FIXED_SIZE + 64);
+        assert_eq!(ChallengeToken::from_bytes(&bytes).unwrap(), token);
+    }
+
+    #[test]
+    fn test_layout() {
+        let token = ChallengeToken::new(test_challenge(), &[0xee; 32]).unwrap();
+        let bytes = token.to_bytes();
+        assert_eq!(&bytes[..7], &[1, 1, 4, 1, 2, 3, 4]);
+        assert_eq!(&bytes[7..71], &token.challenge().header);
+        assert_eq!(&bytes[103..111], &(NOW + CHALLENGE_EXPIRY_MS).to_be_bytes());
+        assert_eq!(bytes[111], FLAG_SENDER | FLAG_RECIPIENT);
+        assert_eq!(bytes[112], 17);
+        assert_eq!(&bytes[113..130], b"alice@example.com");
+        assert_eq!(bytes[130], 15);
+        assert_eq!(bytes.len(), 131 + 15 + 32);
+    }
+
+    #[test]
+    fn test_recipient_only() {
+        let challenge = Challenge {
+            sender_address: None,
+            ..test_challenge()
+        };
+        let token = ChallengeToken::new(challenge, &[0xee; 32]).unwrap();
+        let decoded = ChallengeToken::from_bytes(&token.to_bytes()).unwrap();
+        assert_eq!(decoded.challenge().sender_address, None);
+        assert_eq!(
+            decoded.challenge().recipient_address.as_deref(),
+            Some("bob@example.org")
+        );
+    }
+
+    #[test]
+    fn test_new_rejects_bad_input() {
+        assert!(ChallengeToken::new(test_challenge(), &[0; 64]).is_err());
+        let long = Challenge {
+            sender_address: Some("a".repeat(MAX_ADDRESS_LEN + 1)),
+            ..test_challenge()
+        };
+        assert!(ChallengeToken::new(long, &[0; 32]).is_err());
+        let empty = Challenge {
+            recipient_address: Some(String::new()),
+            ..test_challenge()
+        };
+        assert_eq!(
+            ChallengeToken::new(empty, &[0; 32]),
+            Err("address is empty".to_string())
+        );
+    }
+
+    #[test]
+    fn test_every_accepted_address_round_trips() {
+        for len in [1, MAX_ADDRESS_LEN] {
+            let challenge = Challenge {
+                sender_address: Some("a".repeat(len)),
+                recipient_address: Some("b".repeat(len)),
+                ..test_challenge()
+            };
+            let token = ChallengeToken::new(challenge, &[0xee; 32]).unwrap();
+            assert_eq!(ChallengeToken::decode(&token.encode()).unwrap(), token);
+        }
+    }
+
+    #[test]
+    fn test_decode_is_strict() {
+        let token = ChallengeToken::new(test_challenge(), &[0xee; 32]).unwrap();
+        let bytes = token.to_bytes();
+
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert!(ChallengeToken::from_bytes(&trailing).is_err());
+        assert!(ChallengeToken::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(ChallengeToken::from_bytes(&bytes[..FIXED_SIZE - 1]).is_err());
+
+        for (offset, value) in [(0, 2), (1, 3), (2, 0), (111, 0b100)] {
+            let mut bad = bytes.clone();
+            bad[offset] = value;
+            assert!(
+                ChallengeToken::from_bytes(&bad).is_err(),
+                "offset {}",
+                offset
+            );
+        }
+
+        let mut empty_address = bytes.clone();
+        empty_address[112] = 0;
+        assert!(ChallengeToken::from_bytes(&empty_address).is_err());
+
+        assert!(ChallengeToken::decode(&"A".repeat(MAX_TOKEN_LEN + 1)).is_err());
+        assert!(ChallengeToken::decode("not base64!").is_err());
+        let padded = format!("{}=", token.encode());
+        assert!(ChallengeToken::decode(&padded).is_err());
+    }
+}
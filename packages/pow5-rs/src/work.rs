@@ -0,0 +1,359 @@
+// Exact accounting of proof-of-work done.
+//
+// Difficulties are u64, but running totals of them are not: the webapp keeps
+// cumulative difficulty in a Postgres bigint and hands it to the client as a
+// JS number, which loses precision above 2^53. `Work` is an unsigned 256-bit
+// count of hashes with saturating addition, so totals never wrap or round.
+//
+// The expected work of a target is 2^256 / target, the mean number of hashes
+// needed to find one below it. `work_from_hash` estimates the work a single
+// hash stands for the same way, as if its value had been the target; summed
+// over many solutions this converges on the work actually done.
+
+use crate::difficulty::Target;
+use core::fmt;
+use core::str::FromStr;
+
+/// An unsigned 256-bit amount of work, in hashes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Work {
+    // most significant limb first, so the derived ordering is numeric
+    limbs: [u64; 4],
+}
+
+impl Work {
+    pub const ZERO: Work = Work { limbs: [0; 4] };
+    pub const MAX: Work = Work {
+        limbs: [u64::MAX; 4],
+    };
+
+    pub const fn from_u64(value: u64) -> Self {
+        Work {
+            limbs: [0, 0, 0, value],
+        }
+    }
+
+    /// The value as a u64, if it fits.
+    pub fn to_u64(&self) -> Option<u64> {
+        match self.limbs {
+            [0, 0, 0, value] => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Parse 32 big-endian bytes.
+    pub fn from_bytes(bytes: [u8; 32]) -> Self {
+        let mut limbs = [0u64; 4];
+        for (limb, chunk) in limbs.iter_mut().zip(bytes.chunks_exact(8)) {
+            *limb = u64::from_be_bytes(chunk.try_into().unwrap());
+        }
+        Work { limbs }
+    }
+
+    /// Serialize as 32 big-endian bytes.
+    pub fn to_bytes(&self) -> [u8; 32] {
+        let mut bytes = [0u8; 32];
+        for (chunk, limb) in bytes.chunks_exact_mut(8).zip(self.limbs) {
+            chunk.copy_from_slice(&limb.to_be_bytes());
+        }
+        bytes
+    }
+
+    pub fn is_zero(&self) -> bool {
+        *self == Work::ZERO
+    }
+
+    /// `self + other`, or [`Work::MAX`] if that does not fit.
+    pub fn saturating_add(self, other: Work) -> Work {
+        match self.checked_add(other) {
+            Some(sum) => sum,
+            None => Work::MAX,
+        }
+    }
+
+    pub fn checked_add(self, other: Work) -> Option<Work> {
+        let mut limbs = [0u64; 4];
+        let mut carry = false;
+        for i in (0..4).rev() {
+            let (sum, carry_1) = self.limbs[i].overflowing_add(other.limbs[i]);
+            let (sum, carry_2) = sum.overflowing_add(carry as u64);
+            limbs[i] = sum;
+            carry = carry_1 || carry_2;
+        }
+        (!carry).then_some(Work { limbs })
+    }
+
+    /// The expected number of hashes to find one below `target`:
+    /// 2^256 / target, rounded down. An all-zero target can never be met and
+    /// gives [`Work::MAX`].
+    pub fn expected(target: &Target) -> Work {
+        let target = Work::from_bytes(target.to_bytes());
+        if target.is_zero() {
+            return Work::MAX;
+        }
+        // 2^256 / t = (2^256 - t) / t + 1, and 2^256 - t fits in 256 bits
+        let (quotient, _) = target.negate().div_rem(target);
+        quotient.saturating_add(Work::from_u64(1))
+    }
+
+    // 2^256 - self, for self != 0
+    fn negate(self) -> Work {
+        let mut limbs = self.limbs.map(|limb| !limb);
+        for limb in limbs.iter_mut().rev() {
+            let (sum, carry) = limb.overflowing_add(1);
+            *limb = sum;
+            if !carry {
+                break;
+            }
+        }
+        Work { limbs }
+    }
+
+    // schoolbook binary long division; divisor must not be zero
+    fn div_rem(self, divisor: Work) -> (Work, Work) {
+        let mut quotient = Work::ZERO;
+        let mut remainder = Work::ZERO;
+        for bit in 0..256 {
+            let overflow = remainder.limbs[0] >> 63 == 1;
+            remainder = remainder.shl1();
+            remainder.limbs[3] |= self.bit(bit) as u64;
+            if overflow || remainder >= divisor {
+                remainder = remainder.wrapping_sub(divisor);
+                quotient.limbs[bit / 64] |= 1 << (63 - bit % 64);
+            }
+        }
+        (quotient, remainder)
+    }
+
+    // bit `index` counting from the most significant
+    fn bit(&self, index: usize) -> bool {
+        (self.limbs[index / 64] >> (63 - index % 64)) & 1 == 1
+    }
+
+    fn shl1(self) -> Work {
+        let mut limbs = self.limbs.map(|limb| limb << 1);
+        for (limb, next) in limbs.iter_mut().zip(&self.limbs[1..]) {
+            *limb |= next >> 63;
+        }
+        Work { limbs }
+    }
+
+    fn wrapping_sub(self, other: Work) -> Work {
+        let mut limbs = [0u64; 4];
+        let mut borrow = false;
+        for i in (0..4).rev() {
+            let (diff, borrow_1) = self.limbs[i].overflowing_sub(other.limbs[i]);
+            let (diff, borrow_2) = diff.overflowing_sub(borrow as u64);
+            limbs[i] = diff;
+            borrow = borrow_1 || borrow_2;
+        }
+        Work { limbs }
+    }
+
+    fn div_rem_u64(self, divisor: u64) -> (Work, u64) {
+        let mut limbs = [0u64; 4];
+        let mut remainder: u128 = 0;
+        for (out, limb) in limbs.iter_mut().zip(self.limbs) {
+            let dividend = (remainder << 64) | limb as u128;
+            *out = (dividend / divisor as u128) as u64;
+            remainder = dividend % divisor as u128;
+        }
+        (Work { limbs }, remainder as u64)
+    }
+
+    fn mul_add_u64(self, factor: u64, addend: u64) -> Option<Work> {
+        let mut limbs = [0u64; 4];
+        let mut carry = addend as u128;
+        for (out, limb) in limbs.iter_mut().zip(self.limbs).rev() {
+            let value = limb as u128 * factor as u128 + carry;
+            *out = value as u64;
+            carry = value >> 64;
+        }
+        (carry == 0).then_some(Work { limbs })
+    }
+}
+
+/// Estimate the work a solution with this hash stands for: the expected work
+/// of a target just above the hash, 2^256 / (hash + 1).
+pub fn work_from_hash(hash: &[u8; 32]) -> Work {
+    let hash = Work::from_bytes(*hash);
+    match hash.checked_add(Work::from_u64(1)) {
+        Some(target) => Work::expected(&Target::from_bytes(target.to_bytes())),
+        // 2^256 / 2^256
+        None => Work::from_u64(1),
+    }
+}
+
+impl From<u64> for Work {
+    fn from(value: u64) -> Self {
+        Work::from_u64(value)
+    }
+}
+
+/// Decimal, so totals can be stored and sent as exact strings.
+impl fmt::Display for Work {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // 2^256 - 1 has 78 digits
+        let mut digits = [0u8; 78];
+        let mut start = digits.len();
+        let mut rest = *self;
+        loop {
+            let (quotient, remainder) = rest.div_rem_u64(10);
+            start -= 1;
+            digits[start] = b'0' + remainder as u8;
+            if quotient.is_zero() {
+                break;
+            }
+            rest = quotient;
+        }
+        let digits = core::str::from_utf8(&digits[start..]).expect("digits are ascii");
+        f.pad_integral(true, "", digits)
+    }
+}
+
+impl FromStr for Work {
+    type Err = ParseWorkError;
+
+    /// Parse a decimal string, as written by `Display`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(ParseWorkError::Invalid);
+        }
+        let mut work = Work::ZERO;
+        for b in s.bytes() {
+            work = work
+                .mul_add_u64(10, u64::from(b - b'0'))
+                .ok_or(ParseWorkError::Overflow)?;
+        }
+        Ok(work)
+    }
+}
+
+/// Why a string is not a [`Work`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParseWorkError {
+    /// Empty, or not all decimal digits.
+    Invalid,
+    /// More than 2^256 - 1.
+    Overflow,
+}
+
+impl fmt::Display for ParseWorkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseWorkError::Invalid => write!(f, "invalid work"),
+            ParseWorkError::Overflow => write!(f, "work does not fit in 256 bits"),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Work {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Work {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = <String as serde::Deserialize>::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expected_matches_difficulty() {
+        for difficulty in [1, 2, 3, 1000, 7_000_000, 70_000_000, u64::MAX] {
+            let target = Target::from_difficulty(difficulty).unwrap();
+            assert_eq!(
+                Work::expected(&target),
+                Work::from_u64(difficulty),
+                "{}",
+                difficulty
+            );
+        }
+        assert_eq!(Work::expected(&Target::from_bytes([0; 32])), Work::MAX);
+
+        // 2^256 / 1 does not fit
+        let mut one = [0u8; 32];
+        one[31] = 1;
+        assert_eq!(Work::expected(&Target::from_bytes(one)), Work::MAX);
+    }
+
+    #[test]
+    fn test_saturating_add() {
+        let sum = Work::from_u64(u64::MAX).saturating_add(Work::from_u64(1));
+        assert_eq!(sum.to_u64(), None);
+        assert_eq!(sum.to_string(), "18446744073709551616");
+        assert_eq!(Work::MAX.saturating_add(Work::from_u64(1)), Work::MAX);
+        assert_eq!(Work::MAX.checked_add(Work::from_u64(1)), None);
+
+        // totals past 2^53 stay exact
+        let mut total = Work::ZERO;
+        for _ in 0..1_000 {
+            total = total.saturating_add(Work::from_u64(70_000_000_000_000));
+        }
+        assert_eq!(total.to_string(), "70000000000000000");
+    }
+
+    #[test]
+    fn test_work_from_hash() {
+        assert_eq!(work_from_hash(&[0xff; 32]), Work::from_u64(1));
+        let mut hash = [0u8; 32];
+        hash[0] = 0x7f;
+        hash[1..].fill(0xff);
+        assert_eq!(work_from_hash(&hash), Work::from_u64(2));
+        // 2^256 / 1 is saturated
+        assert_eq!(work_from_hash(&[0; 32]), Work::MAX);
+        // a hash just under the 7M target is worth about 7M
+        let target = Target::from_difficulty(7_000_000).unwrap();
+        let mut hash = target.to_bytes();
+        hash[31] -= 1;
+        assert_eq!(work_from_hash(&hash), Work::from_u64(7_000_000));
+    }
+
+    #[test]
+    fn test_decimal_and_bytes() {
+        assert_eq!(Work::ZERO.to_string(), "0");
+        assert_eq!(Work::from_u64(123).to_string(), "123");
+        assert_eq!(
+            Work::MAX.to_string(),
+            "115792089237316195423570985008687907853269984665640564039457584007913129639935"
+        );
+        for work in [
+            Work::ZERO,
+            Work::from_u64(10_000_000_000_000_000_000),
+            Work::MAX,
+        ] {
+            assert_eq!(work.to_string().parse::<Work>(), Ok(work));
+            assert_eq!(Work::from_bytes(work.to_bytes()), work);
+        }
+        assert_eq!(format!("{:>5}", Work::from_u64(42)), "   42");
+
+        assert!("".parse::<Work>().is_err());
+        assert!("-1".parse::<Work>().is_err());
+        assert!("1e3".parse::<Work>().is_err());
+        let too_big =
+            "115792089237316195423570985008687907853269984665640564039457584007913129639936";
+        assert!(too_big.parse::<Work>().is_err());
+
+        let mut bytes = [0u8; 32];
+        bytes[24..].copy_from_slice(&7_000_000u64.to_be_bytes());
+        assert_eq!(Work::from_bytes(bytes).to_u64(), Some(7_000_000));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_decimal_string() {
+        let work = Work::from_u64(u64::MAX).saturating_add(Work::from_u64(1));
+        let json = serde_json::to_string(&work).unwrap();
+        assert_eq!(json, "\"18446744073709551616\"");
+        assert_eq!(serde_json::from_str::<Work>(&json).unwrap(), work);
+        assert!(serde_json::from_str::<Work>("123").is_err());
+    }
+}